@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+/// Hex encoded Ed25519 public key of the images the bootloader installs, see `tools/sign-firmware.py`.
+const PUBLIC_KEY_VAR: &str = "EXPRESSOR_PUBLIC_KEY";
+
+fn main() {
+    // the bootloader only takes the start of the flash, so it brings its own memory layout
+    let out = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::copy("memory.x", out.join("memory.x")).unwrap();
+    println!("cargo:rustc-link-search={}", out.display());
+    println!("cargo:rerun-if-changed=memory.x");
+
+    // there is no key in the repository, a bootloader built without one rejects every image
+    println!("cargo:rerun-if-env-changed={PUBLIC_KEY_VAR}");
+    let public_key = match env::var(PUBLIC_KEY_VAR) {
+        Ok(hex) => format!("Some({:?})", parse_key(hex.trim())),
+        Err(_) if env::var("PROFILE").unwrap() == "release" => {
+            panic!("{PUBLIC_KEY_VAR} is not set, release builds need the public key of the signing key")
+        },
+        Err(_) => {
+            println!("cargo:warning={PUBLIC_KEY_VAR} is not set, the bootloader will reject all firmware updates");
+            "None".into()
+        },
+    };
+    fs::write(out.join("public_key.rs"), public_key).unwrap();
+
+    println!("cargo:rustc-link-arg-bins=--nmagic");
+    println!("cargo:rustc-link-arg-bins=-Tlink.x");
+    println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+}
+
+fn parse_key(hex: &str) -> [u8; 32] {
+    let bytes: Option<Vec<u8>> = (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect();
+    bytes
+        .and_then(|bytes| bytes.try_into().ok())
+        .unwrap_or_else(|| panic!("{PUBLIC_KEY_VAR} must be 64 hexadecimal digits"))
+}
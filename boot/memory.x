@@ -0,0 +1,7 @@
+/* The bootloader keeps the first 40K of the flash, see `expressor_common::update::layout`. */
+MEMORY
+{
+    FLASH : ORIGIN = 0x08000000, LENGTH =  40K
+    /* the last word holds the update request from the firmware, the stack stays 8-byte aligned */
+    RAM   : ORIGIN = 0x20000000, LENGTH =  32K - 8
+}
@@ -0,0 +1,241 @@
+#![no_std]
+#![no_main]
+
+//! Recovery bootloader.
+//!
+//! Starts the installed firmware unless it was asked for an update or the firmware is missing or damaged. In that
+//! case it shows up as a USB MIDI device and takes a signed image from the desktop app over SysEx. The header page is
+//! erased before the image is written and only written again once the complete image checked out, so a failed
+//! update always ends up back here.
+
+use defmt::*;
+use embassy_executor::Spawner;
+use embassy_futures::join::join;
+use embassy_stm32::flash::{self, Blocking, Flash};
+use embassy_stm32::usb::Driver;
+use embassy_stm32::{Config, bind_interrupts, peripherals, uid, usb};
+use embassy_time::Timer;
+use embassy_usb::Builder;
+use embassy_usb::class::midi::MidiClass;
+use embassy_usb::driver::EndpointError;
+use expressor_common::midi::{SysexAssembler, sysex_packets};
+use expressor_common::protocol::{DeviceMessage, FRAME_SIZE, HostMessage, Identity, device_release, is_identity_request};
+use expressor_common::update::layout::*;
+use expressor_common::update::{CHUNK_SIZE, ImageHeader, Transfer, UpdateStatus};
+
+use {defmt_rtt as _, panic_probe as _};
+
+/// Reported in the identity reply and as the USB device release.
+const VERSION: [u8; 4] = [0, 1, 0, 0];
+
+// the transfer checks the chunks against the write size of the layout
+const _: () = assert!(flash::WRITE_SIZE == WRITE_SIZE);
+
+/// Key that signed the images to install, from `EXPRESSOR_PUBLIC_KEY` at build time.
+const PUBLIC_KEY: Option<[u8; 32]> = include!(concat!(env!("OUT_DIR"), "/public_key.rs"));
+
+bind_interrupts!(struct Irqs {
+    USB_LP => usb::InterruptHandler<peripherals::USB>;
+});
+
+fn identity() -> Identity {
+    // flagged, so the desktop app can tell the bootloader from the firmware
+    let [major, minor, patch, build] = VERSION;
+    Identity { version: [major | Identity::BOOTLOADER, minor, patch, build], serial: *uid::uid_hex_bytes() }
+}
+
+/// Header of the installed image, if there is one.
+fn installed_header() -> Option<ImageHeader> {
+    ImageHeader::from_bytes(flash_slice(HEADER_OFFSET, ImageHeader::SIZE as u32))
+}
+
+fn flash_slice(offset: u32, len: u32) -> &'static [u8] {
+    // SAFETY: the range is part of the memory mapped flash, callers keep it within the image area
+    unsafe { core::slice::from_raw_parts((FLASH_BASE + offset) as *const u8, len.min(FLASH_SIZE - offset) as usize) }
+}
+
+/// Takes the update request of the firmware, clearing it for the next start.
+fn take_update_request() -> bool {
+    let address = UPDATE_REQUEST_ADDRESS as *mut u32;
+    // SAFETY: the word is excluded from the RAM in memory.x, so nothing else uses it
+    unsafe {
+        let requested = core::ptr::read_volatile(address) == UPDATE_REQUEST;
+        core::ptr::write_volatile(address, 0);
+        requested
+    }
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    if !take_update_request()
+        && let Some(header) = installed_header()
+        && header.check_crc(flash_slice(IMAGE_OFFSET, header.size))
+    {
+        // SAFETY: nothing has been set up yet, the firmware starts from a clean state
+        unsafe { cortex_m::asm::bootload((FLASH_BASE + IMAGE_OFFSET) as *const u32) }
+    }
+
+    let p = embassy_stm32::init(Config::default());
+    info!("Waiting for a firmware update");
+
+    let driver = Driver::new(p.USB, Irqs, p.PA12, p.PA11);
+    let mut config = embassy_usb::Config::new(0x1209, 0xd2b3);
+    config.manufacturer = Some("schlegelflegel");
+    config.product = Some("Midi Expressor Bootloader");
+    config.serial_number = Some(uid::uid_hex());
+    config.device_release = device_release(VERSION);
+    config.max_power = 100;
+    config.max_packet_size_0 = 64;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [], // no msos descriptors
+        &mut control_buf,
+    );
+    let mut midi = MidiClass::new(&mut builder, 1, 1, 64);
+    let mut usb = builder.build();
+
+    let mut updater = Updater { flash: Flash::new_blocking(p.FLASH), transfer: None };
+
+    let midi_fut = async {
+        loop {
+            midi.wait_connection().await;
+            info!("USB Connected");
+            let _ = update_session(&mut midi, &mut updater).await;
+            info!("USB Disconnected");
+        }
+    };
+
+    join(usb.run(), midi_fut).await;
+}
+
+struct Disconnected;
+
+impl From<EndpointError> for Disconnected {
+    fn from(value: EndpointError) -> Self {
+        match value {
+            EndpointError::BufferOverflow => defmt::panic!("Buffer overflow"),
+            EndpointError::Disabled => Disconnected,
+        }
+    }
+}
+
+async fn update_session<'d, T: usb::Instance + 'd>(
+    midi: &mut MidiClass<'d, Driver<'d, T>>,
+    updater: &mut Updater,
+) -> Result<(), Disconnected> {
+    let mut buf = [0u8; 64];
+    let mut sysex = SysexAssembler::<FRAME_SIZE>::new();
+
+    loop {
+        let len = midi.read_packet(&mut buf).await?;
+
+        let (packets, _) = buf[..len].as_chunks::<4>();
+        for packet in packets {
+            let Some(frame) = sysex.push(packet) else {
+                continue;
+            };
+
+            if is_identity_request(frame) {
+                write_frame(midi, &identity().encode()).await?;
+                continue;
+            }
+
+            let Some((offset, status)) = HostMessage::decode(frame).and_then(|message| updater.handle(message)) else {
+                debug!("Ignoring SysEx of {} bytes", frame.len());
+                continue;
+            };
+            if status != UpdateStatus::Ok {
+                info!("Update at {}: {}", offset, status);
+            }
+            write_frame(midi, &DeviceMessage::UpdateAck { offset, status }.encode()).await?;
+
+            if status == UpdateStatus::Done {
+                // lets the acknowledgement reach the desktop app before the device disconnects
+                Timer::after_millis(50).await;
+                cortex_m::peripheral::SCB::sys_reset();
+            }
+        }
+    }
+}
+
+async fn write_frame<'d, T: usb::Instance + 'd>(midi: &mut MidiClass<'d, Driver<'d, T>>, frame: &[u8]) -> Result<(), Disconnected> {
+    for packet in sysex_packets(0, frame) {
+        midi.write_packet(&packet).await?;
+    }
+    Ok(())
+}
+
+struct Updater {
+    flash: Flash<'static, Blocking>,
+    transfer: Option<Transfer>,
+}
+
+impl Updater {
+    /// Handles an update message and returns the acknowledgement, or `None` for messages meant for the firmware.
+    fn handle(&mut self, message: HostMessage) -> Option<(u32, UpdateStatus)> {
+        let result = match message {
+            HostMessage::UpdateBegin(header) => self.begin(header).map(|()| UpdateStatus::Ok),
+            HostMessage::UpdateChunk { offset, len, data } => {
+                self.write(offset, &data[..len as usize]).map(|()| UpdateStatus::Ok)
+            },
+            HostMessage::UpdateFinish => self.finish().map(|()| UpdateStatus::Done),
+            _ => return None,
+        };
+
+        let received = self.transfer.as_ref().map_or(0, Transfer::received);
+        Some((received, result.unwrap_or_else(|status| status)))
+    }
+
+    fn begin(&mut self, header: ImageHeader) -> Result<(), UpdateStatus> {
+        info!("Receiving firmware {} of {} bytes", header.version, header.size);
+        self.transfer = None;
+        let transfer = Transfer::begin(header, IMAGE_CAPACITY)?;
+
+        // the header goes first, so the old image is never started again once it was partly overwritten
+        let end = IMAGE_OFFSET + header.size.next_multiple_of(PAGE_SIZE);
+        self.flash.blocking_erase(HEADER_OFFSET, end).map_err(|_| UpdateStatus::FlashError)?;
+
+        self.transfer = Some(transfer);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), UpdateStatus> {
+        let transfer = self.transfer.as_mut().ok_or(UpdateStatus::NotStarted)?;
+        transfer.accept(offset, data)?;
+
+        // only the last chunk is not a multiple of the write size, the erased flash after it is padded the same way
+        let mut padded = [0xFF; CHUNK_SIZE];
+        padded[..data.len()].copy_from_slice(data);
+        let len = data.len().next_multiple_of(WRITE_SIZE);
+        self.flash
+            .blocking_write(IMAGE_OFFSET + offset, &padded[..len])
+            .map_err(|_| UpdateStatus::FlashError)?;
+
+        // the desktop app resends the chunk at the acknowledged offset after a failed write
+        transfer.commit(data.len());
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), UpdateStatus> {
+        let transfer = self.transfer.as_ref().ok_or(UpdateStatus::NotStarted)?;
+        if !transfer.is_complete() {
+            return Err(UpdateStatus::Incomplete);
+        }
+
+        let header = transfer.header();
+        let public_key = PUBLIC_KEY.as_ref().ok_or(UpdateStatus::BadSignature)?;
+        header.check(flash_slice(IMAGE_OFFSET, header.size), public_key)?;
+
+        // the header size is a multiple of the write size
+        self.flash.blocking_write(HEADER_OFFSET, &header.to_bytes()).map_err(|_| UpdateStatus::FlashError)?;
+        info!("Firmware {} installed", header.version);
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use strum::VariantArray;
+
+/// Writes the compact binary config format to a fixed buffer.
+///
+/// Values are written in declaration order without any field tags, so every change to a config struct must be
+/// mirrored in its `encode` and `decode`. Multi-byte integers are little endian and enums are stored as their index
+/// in `VARIANTS`. Running out of space is only reported by [`Writer::finish`], so encoders do not have to check every
+/// write.
+pub struct Writer<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+    overflow: bool,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        match self.buffer.get_mut(self.len..self.len + bytes.len()) {
+            Some(slot) => {
+                slot.copy_from_slice(bytes);
+                self.len += bytes.len();
+            },
+            None => self.overflow = true,
+        }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn variant<T: VariantArray + PartialEq>(&mut self, value: &T) {
+        self.u8(T::VARIANTS.iter().position(|variant| variant == value).unwrap_or(0) as u8);
+    }
+
+    /// Number of bytes written, or `None` if the buffer was too small.
+    pub fn finish(self) -> Option<usize> {
+        (!self.overflow).then_some(self.len)
+    }
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let (head, tail) = self.bytes.split_first_chunk::<N>()?;
+        self.bytes = tail;
+        Some(*head)
+    }
+
+    pub fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|[value]| value)
+    }
+
+    pub fn u16(&mut self) -> Option<u16> {
+        self.bytes().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_le_bytes)
+    }
+
+    /// Takes the next `len` bytes.
+    pub fn slice(&mut self, len: usize) -> Option<&'a [u8]> {
+        let (head, tail) = self.bytes.split_at_checked(len)?;
+        self.bytes = tail;
+        Some(head)
+    }
+
+    pub fn bool(&mut self) -> Option<bool> {
+        self.u8().map(|value| value != 0)
+    }
+
+    pub fn variant<T: VariantArray + Clone>(&mut self) -> Option<T> {
+        T::VARIANTS.get(self.u8()? as usize).cloned()
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OutputPath;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut buffer = [0; 8];
+        let mut writer = Writer::new(&mut buffer);
+        writer.u8(7);
+        writer.u16(1000);
+        writer.bool(true);
+        writer.variant(&OutputPath::Din);
+        assert_eq!(writer.finish(), Some(5));
+
+        let mut reader = Reader::new(&buffer[..5]);
+        assert_eq!(reader.u8(), Some(7));
+        assert_eq!(reader.u16(), Some(1000));
+        assert_eq!(reader.bool(), Some(true));
+        assert_eq!(reader.variant(), Some(OutputPath::Din));
+        assert!(reader.is_empty());
+        assert_eq!(reader.u8(), None);
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut buffer = [0; 2];
+        let mut writer = Writer::new(&mut buffer);
+        writer.u16(1);
+        writer.u8(2);
+        assert_eq!(writer.finish(), None);
+    }
+}
@@ -0,0 +1,1480 @@
+use crate::codec::{Reader, Writer};
+use crate::curve::Curve;
+use crate::detect::{Detection, PedalKind, RAW_MAX};
+use crate::hid::{KeyCombo, MediaKey};
+use crate::midi::MidiMessage;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SwitchConfig {
+    pub released_value: u8,
+    pub pressed_value: u8,
+}
+
+impl Default for SwitchConfig {
+    fn default() -> Self {
+        Self {
+            released_value: 0,
+            pressed_value: 127,
+        }
+    }
+}
+
+impl SwitchConfig {
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.bytes(&[self.released_value, self.pressed_value]);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let [released_value, pressed_value] = reader.bytes()?.map(|value: u8| value.min(127));
+        Some(Self { released_value, pressed_value })
+    }
+}
+
+/// Number of gestures a switch tells apart.
+pub const NUM_GESTURES: usize = 4;
+
+/// Ways of using a footswitch, each with its own action.
+#[derive(Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum Gesture {
+    Tap,
+    #[strum(to_string="Double Tap")]
+    DoubleTap,
+    #[strum(to_string="Long Press")]
+    LongPress,
+    /// Repeats while the switch is held past the long press time.
+    #[strum(to_string="Hold Repeat")]
+    Repeat,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum ActionKind {
+    #[default]
+    #[strum(to_string="No Action")]
+    None,
+    Message,
+    #[strum(to_string="Preset Change")]
+    Preset,
+    /// Switches the channel between its released and pressed value, sent through its mappings.
+    Toggle,
+    /// Sends the macro of the channel.
+    Macro,
+}
+
+/// What a gesture does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GestureAction {
+    pub kind: ActionKind,
+    /// Zero based MIDI channel of the message (0 - 15).
+    pub channel: u8,
+    pub message_type: MessageType,
+    /// Controller or note number of the message.
+    pub number: u8,
+    /// Value of the message, or the zero based preset to select.
+    pub value: u8,
+}
+
+impl Default for GestureAction {
+    fn default() -> Self {
+        Self {
+            kind: ActionKind::default(),
+            channel: 0,
+            message_type: MessageType::default(),
+            number: 0,
+            value: 127,
+        }
+    }
+}
+
+impl GestureAction {
+    /// Messages of a message action. A note is a short trigger, its note off follows right away.
+    pub fn messages(&self) -> impl Iterator<Item = MidiMessage> {
+        let message = self.message_type.message(self.channel, self.number, self.value);
+        let note_off = (self.message_type == MessageType::Note).then_some(MidiMessage::NoteOff(self.channel, self.number, 0));
+
+        core::iter::once(message).chain(note_off)
+    }
+
+    pub fn with_kind(mut self, value: ActionKind) -> Self {
+        self.kind = value;
+        self
+    }
+
+    pub fn with_channel(mut self, value: u8) -> Self {
+        self.channel = value.min(15);
+        self
+    }
+
+    pub fn with_message_type(mut self, value: MessageType) -> Self {
+        self.message_type = value;
+        self
+    }
+
+    pub fn with_number(mut self, value: u8) -> Self {
+        self.number = value.min(127);
+        self
+    }
+
+    pub fn with_value(mut self, value: u8) -> Self {
+        self.value = value.min(127);
+        self
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.variant(&self.kind);
+        writer.u8(self.channel);
+        writer.variant(&self.message_type);
+        writer.bytes(&[self.number, self.value]);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let kind = reader.variant()?;
+        let channel = reader.u8()?.min(15);
+        let message_type = reader.variant()?;
+        let [number, value] = reader.bytes()?;
+        Some(Self { kind, channel, message_type, number: number.min(127), value: value.min(127) })
+    }
+}
+
+/// Gesture detection on a switch input. As soon as one gesture has an action, the gestures replace the pressed and
+/// released values of the switch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GestureConfig {
+    /// Longest pause between the taps of a double tap, in milliseconds.
+    pub double_tap_time: u16,
+    /// Time a switch has to be held for a long press, in milliseconds.
+    pub long_press_time: u16,
+    /// Time between the repeats of a held switch, in milliseconds.
+    pub repeat_interval: u16,
+    pub actions: [GestureAction; NUM_GESTURES],
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self {
+            double_tap_time: 300,
+            long_press_time: 600,
+            repeat_interval: 150,
+            actions: [GestureAction::default(); NUM_GESTURES],
+        }
+    }
+}
+
+impl GestureConfig {
+    pub fn enabled(&self) -> bool {
+        self.actions.iter().any(|action| action.kind != ActionKind::None)
+    }
+
+    pub fn action(&self, gesture: Gesture) -> &GestureAction {
+        &self.actions[gesture as usize]
+    }
+
+    pub fn has_action(&self, gesture: Gesture) -> bool {
+        self.action(gesture).kind != ActionKind::None
+    }
+
+    pub fn double_tap_micros(&self) -> u64 {
+        self.double_tap_time as u64 * 1000
+    }
+
+    pub fn long_press_micros(&self) -> u64 {
+        self.long_press_time as u64 * 1000
+    }
+
+    pub fn repeat_micros(&self) -> u64 {
+        self.repeat_interval.max(1) as u64 * 1000
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.u16(self.double_tap_time);
+        writer.u16(self.long_press_time);
+        writer.u16(self.repeat_interval);
+        for action in &self.actions {
+            action.encode(writer);
+        }
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let double_tap_time = reader.u16()?;
+        let long_press_time = reader.u16()?;
+        let repeat_interval = reader.u16()?;
+        let mut actions = [GestureAction::default(); NUM_GESTURES];
+        for action in &mut actions {
+            *action = GestureAction::decode(reader)?;
+        }
+        Some(Self { double_tap_time, long_press_time, repeat_interval, actions })
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContinuousConfig {
+    pub minimum_input: u8,
+    pub maximum_input: u8,
+    pub minimum_output: u8,
+    pub maximum_output: u8,
+    pub curve: Curve,
+}
+
+impl Default for ContinuousConfig {
+    fn default() -> Self {
+        Self {
+            minimum_input: 0,
+            maximum_input: 127,
+            minimum_output: 0,
+            maximum_output: 127,
+            curve: Curve::default(),
+        }
+    }
+}
+
+impl ContinuousConfig {
+    /// Maps a 7-bit input value through the input range, the response curve and the output range.
+    pub fn apply(&self, value: u8) -> u8 {
+        let (minimum_input, maximum_input) = (self.minimum_input as i32, self.maximum_input as i32);
+
+        let normalized = if minimum_input == maximum_input {
+            if value as i32 >= maximum_input { 127 } else { 0 }
+        } else {
+            ((value as i32 - minimum_input) * 127 / (maximum_input - minimum_input)).clamp(0, 127)
+        };
+
+        scale(self.curve.evaluate(normalized as u8), self.minimum_output, self.maximum_output)
+    }
+
+    /// Maps a 12-bit raw value like [`apply`](Self::apply) to a 12-bit output, interpolating between neighbouring
+    /// 7-bit steps so the result follows the same curve without the coarse steps.
+    pub fn apply_fine(&self, raw_value: u16) -> u16 {
+        let raw_value = raw_value.min(RAW_MAX);
+        let step = (raw_value >> 5) as u8;
+        let fraction = (raw_value & 0x1F) as i32;
+
+        let low = self.apply(step) as i32;
+        let high = self.apply((step + 1).min(127)) as i32;
+        let fine = low * 32 + (high - low) * fraction;
+        (fine * RAW_MAX as i32 / (127 * 32)) as u16
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.bytes(&[self.minimum_input, self.maximum_input, self.minimum_output, self.maximum_output]);
+        self.curve.encode(writer);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let [minimum_input, maximum_input, minimum_output, maximum_output] =
+            reader.bytes()?.map(|value: u8| value.min(127));
+        Some(Self {
+            minimum_input,
+            maximum_input,
+            minimum_output,
+            maximum_output,
+            curve: Curve::decode(reader)?,
+        })
+    }
+}
+
+
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum InputMode {
+    #[default] Continuous,
+    Switch,
+    /// A momentary switch that latches, every press switches between the released and the pressed value.
+    #[strum(to_string="Momentary as Toggle")]
+    MomentaryAsToggle,
+    /// A latching switch that acts momentary, every change sends the pressed value briefly.
+    #[strum(to_string="Toggle as Momentary")]
+    ToggleAsMomentary,
+    /// Presses step through programs instead of sending the mappings, see [`ProgramConfig`].
+    #[strum(to_string="Program Navigation")]
+    ProgramNavigation,
+    /// Presses move the value along a ramp over time instead of jumping, see [`RampConfig`].
+    #[strum(to_string="Timed Ramp")]
+    Ramp,
+}
+
+impl InputMode {
+    /// Input mode matching a detected pedal, if anything usable was detected.
+    pub fn suggested(detection: &Detection) -> Option<Self> {
+        match detection.kind {
+            PedalKind::Expression => Some(Self::Continuous),
+            PedalKind::LatchingSwitch | PedalKind::MomentarySwitch => Some(Self::Switch),
+            PedalKind::Unknown | PedalKind::Disconnected => None,
+        }
+    }
+
+    /// Whether channels in this mode resend their value unless told otherwise. Switch messages may toggle something
+    /// downstream, so only modes whose value is a position resend by default.
+    pub fn resends(&self) -> bool {
+        matches!(self, Self::Continuous | Self::Ramp)
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct InputConfig {
+    pub mode: InputMode,
+    pub switch: SwitchConfig,
+    pub continuous: ContinuousConfig,
+}
+
+impl InputConfig {
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.variant(&self.mode);
+        self.switch.encode(writer);
+        self.continuous.encode(writer);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            mode: reader.variant()?,
+            switch: SwitchConfig::decode(reader)?,
+            continuous: ContinuousConfig::decode(reader)?,
+        })
+    }
+}
+
+
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum MessageType {
+    #[default]
+    #[strum(to_string="Control Change")]
+    ControlChange,
+    #[strum(to_string="Channel Pressure")]
+    ChannelPressure,
+    #[strum(to_string="Pitch Bend")]
+    PitchBend,
+    #[strum(to_string="Poly Aftertouch")]
+    PolyAftertouch,
+    Note,
+    #[strum(to_string="Program Change")]
+    ProgramChange,
+}
+
+impl MessageType {
+    /// Whether the message addresses a controller or note number.
+    pub fn has_number(&self) -> bool {
+        matches!(self, Self::ControlChange | Self::PolyAftertouch | Self::Note)
+    }
+
+    /// Builds a message of this type with a 7-bit value. The number is ignored by types without one.
+    pub fn message(&self, channel: u8, number: u8, value: u8) -> MidiMessage {
+        match self {
+            Self::ControlChange => MidiMessage::ControlChange(channel, number, value),
+            Self::ChannelPressure => MidiMessage::ChannelPressure(channel, value),
+            Self::PitchBend => MidiMessage::PitchBend(channel, (value as u16) << 7 | value as u16),
+            Self::PolyAftertouch => MidiMessage::PolyKeyPressure(channel, number, value),
+            Self::Note => MidiMessage::NoteOn(channel, number, value),
+            Self::ProgramChange => MidiMessage::ProgramChange(channel, value),
+        }
+    }
+
+    /// Splits a message into its type, channel, number and 7-bit value, the reverse of [`Self::message`]. Note offs
+    /// are notes with value 0.
+    pub fn split(message: &MidiMessage) -> (Self, u8, u8, u8) {
+        match *message {
+            MidiMessage::NoteOff(channel, number, _) => (Self::Note, channel, number, 0),
+            MidiMessage::NoteOn(channel, number, value) => (Self::Note, channel, number, value),
+            MidiMessage::PolyKeyPressure(channel, number, value) => (Self::PolyAftertouch, channel, number, value),
+            MidiMessage::ControlChange(channel, number, value) => (Self::ControlChange, channel, number, value),
+            MidiMessage::ProgramChange(channel, value) => (Self::ProgramChange, channel, 0, value),
+            MidiMessage::ChannelPressure(channel, value) => (Self::ChannelPressure, channel, 0, value),
+            MidiMessage::PitchBend(channel, value) => (Self::PitchBend, channel, 0, (value >> 7) as u8),
+        }
+    }
+}
+
+/// Maximum number of steps in the macro of a switch.
+pub const MAX_MACRO_STEPS: usize = 8;
+
+/// A message of a macro and the pause after it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MacroStep {
+    pub message: MidiMessage,
+    /// Pause before the next step, in milliseconds.
+    pub delay: u16,
+}
+
+impl Default for MacroStep {
+    fn default() -> Self {
+        // the modulation wheel at rest, a bank select would change what the next program change loads
+        Self { message: MidiMessage::ControlChange(0, 1, 0), delay: 0 }
+    }
+}
+
+impl MacroStep {
+    pub fn with_message(mut self, message: MidiMessage) -> Self {
+        self.message = message;
+        self
+    }
+
+    pub fn with_delay(mut self, value: u16) -> Self {
+        self.delay = value;
+        self
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        let mut bytes = [0; 3];
+        self.message.to_bytes(&mut bytes);
+        writer.bytes(&bytes);
+        writer.u16(self.delay);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let [status, data1, data2] = reader.bytes()?;
+        Some(Self {
+            message: MidiMessage::from_bytes(status, data1 & 0x7F, data2 & 0x7F)?,
+            delay: reader.u16()?,
+        })
+    }
+}
+
+/// Messages a switch sends in order when a gesture with the macro action fires, e.g. a bank select, a program change
+/// and the controllers of an amp scene.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct MacroSequence {
+    steps: [MacroStep; MAX_MACRO_STEPS],
+    len: u8,
+}
+
+impl MacroSequence {
+    pub fn steps(&self) -> &[MacroStep] {
+        &self.steps[..self.len as usize]
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len as usize == MAX_MACRO_STEPS
+    }
+
+    /// Appends a step, if there is room.
+    pub fn with_added(mut self, step: MacroStep) -> Self {
+        if !self.is_full() {
+            self.steps[self.len as usize] = step;
+            self.len += 1;
+        }
+        self
+    }
+
+    pub fn with_step(mut self, index: usize, step: MacroStep) -> Self {
+        if index < self.len as usize {
+            self.steps[index] = step;
+        }
+        self
+    }
+
+    pub fn with_removed(mut self, index: usize) -> Self {
+        if index < self.len as usize {
+            self.steps[index..self.len as usize].rotate_left(1);
+            self.len -= 1;
+            self.steps[self.len as usize] = MacroStep::default();
+        }
+        self
+    }
+
+    /// Moves a step to another position, shifting the steps in between.
+    pub fn with_moved(mut self, from: usize, to: usize) -> Self {
+        let len = self.len as usize;
+        if from < len && to < len {
+            if from < to {
+                self.steps[from..=to].rotate_left(1);
+            } else {
+                self.steps[to..=from].rotate_right(1);
+            }
+        }
+        self
+    }
+
+    /// Keeps the first `len` steps, adding default steps where there are fewer.
+    pub fn with_len(mut self, len: usize) -> Self {
+        let len = len.min(MAX_MACRO_STEPS);
+        self.steps[len..].fill(MacroStep::default());
+        self.len = len as u8;
+        self
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.u8(self.len);
+        for step in self.steps() {
+            step.encode(writer);
+        }
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let len = reader.u8()? as usize;
+        if len > MAX_MACRO_STEPS {
+            return None;
+        }
+
+        let mut sequence = Self::default();
+        for _ in 0..len {
+            sequence = sequence.with_added(MacroStep::decode(reader)?);
+        }
+        Some(sequence)
+    }
+}
+
+/// What a press of a switch in program navigation mode does to the current program.
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum ProgramStep {
+    #[default]
+    #[strum(to_string="Next Program")]
+    Increment,
+    #[strum(to_string="Previous Program")]
+    Decrement,
+    #[strum(to_string="Select Program")]
+    Direct,
+}
+
+/// Program changes of a switch in program navigation mode. Programs are numbered across banks of 128, so program 130
+/// is program 2 of bank 1. Switches that navigate the same MIDI channel share its current program, e.g. an up and a
+/// down switch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProgramConfig {
+    pub step: ProgramStep,
+    /// Zero based MIDI channel.
+    pub channel: u8,
+    /// Program the direct select step jumps to.
+    pub program: u16,
+    pub minimum: u16,
+    pub maximum: u16,
+    /// Continue at the other end of the range instead of stopping at its ends.
+    pub wrap: bool,
+}
+
+impl Default for ProgramConfig {
+    fn default() -> Self {
+        Self { step: ProgramStep::default(), channel: 0, program: 0, minimum: 0, maximum: 127, wrap: true }
+    }
+}
+
+impl ProgramConfig {
+    /// Number of programs in a bank, the range of a program change.
+    pub const BANK_SIZE: u16 = 128;
+
+    /// Whether the switch selects programs past the first bank, so its program changes need a bank select.
+    pub fn uses_banks(&self) -> bool {
+        let highest = match self.step {
+            ProgramStep::Direct => self.program,
+            ProgramStep::Increment | ProgramStep::Decrement => self.minimum.max(self.maximum),
+        };
+        highest >= Self::BANK_SIZE
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.variant(&self.step);
+        writer.u8(self.channel);
+        writer.u16(self.program);
+        writer.u16(self.minimum);
+        writer.u16(self.maximum);
+        writer.bool(self.wrap);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            step: reader.variant()?,
+            channel: reader.u8()? & 0x0F,
+            program: reader.u16()?,
+            minimum: reader.u16()?,
+            maximum: reader.u16()?,
+            wrap: reader.bool()?,
+        })
+    }
+}
+
+/// Ramp of a switch in timed ramp mode, e.g. for swells. A press moves the value from `start` to `end` over
+/// `duration`, shaped by the curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RampConfig {
+    pub start: u8,
+    pub end: u8,
+    /// Time from start to end, in milliseconds.
+    pub duration: u16,
+    pub curve: Curve,
+    /// Ramp back to the start when the switch is released, instead of staying at the end until the next press.
+    pub reverse: bool,
+}
+
+impl Default for RampConfig {
+    fn default() -> Self {
+        Self { start: 0, end: 127, duration: 1000, curve: Curve::default(), reverse: true }
+    }
+}
+
+impl RampConfig {
+    /// Value at `position` along the ramp, from 0 at the start to 127 at the end.
+    pub fn value(&self, position: u8) -> u8 {
+        scale(self.curve.evaluate(position), self.start, self.end)
+    }
+
+    pub fn duration_micros(&self) -> u64 {
+        self.duration as u64 * 1000
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.bytes(&[self.start, self.end]);
+        writer.u16(self.duration);
+        self.curve.encode(writer);
+        writer.bool(self.reverse);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let [start, end] = reader.bytes()?;
+        Some(Self {
+            start: start.min(127),
+            end: end.min(127),
+            duration: reader.u16()?,
+            curve: Curve::decode(reader)?,
+            reverse: reader.bool()?,
+        })
+    }
+}
+
+/// Physical MIDI ports a channel sends its messages to.
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum OutputPath {
+    #[strum(to_string="USB only")]
+    Usb,
+    #[strum(to_string="DIN only")]
+    Din,
+    #[default]
+    #[strum(to_string="USB and DIN")]
+    Both,
+}
+
+impl OutputPath {
+    pub fn usb(&self) -> bool {
+        matches!(self, Self::Usb | Self::Both)
+    }
+
+    pub fn din(&self) -> bool {
+        matches!(self, Self::Din | Self::Both)
+    }
+}
+
+/// Where a channel sends its value: MIDI messages, the USB HID interfaces or both.
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum Destination {
+    #[default]
+    #[strum(to_string="MIDI only")]
+    Midi,
+    #[strum(to_string="HID only")]
+    Hid,
+    #[strum(to_string="MIDI and HID")]
+    Both,
+}
+
+impl Destination {
+    pub fn midi(&self) -> bool {
+        matches!(self, Self::Midi | Self::Both)
+    }
+
+    pub fn hid(&self) -> bool {
+        matches!(self, Self::Hid | Self::Both)
+    }
+}
+
+/// What the pedal does when the parameter it controls was changed elsewhere, by a preset change or a controller
+/// message from the host.
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum Takeover {
+    /// The first movement sets the parameter to the pedal position.
+    #[default]
+    #[strum(to_string="Jump to Pedal")]
+    Jump,
+    /// Nothing is sent until the pedal passes the value of the parameter.
+    #[strum(to_string="Pickup at Value")]
+    Pickup,
+    /// The parameter moves from its value towards the end the pedal is moving to, so both meet there.
+    #[strum(to_string="Value Scaling")]
+    Scaling,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum HidOutput {
+    /// Continuous inputs drive the axis of their channel, switches its button.
+    #[default] Gamepad,
+    #[strum(to_string="Keyboard Key")]
+    Key,
+    #[strum(to_string="Media Key")]
+    Media,
+    /// The pedal position sets the host volume with repeated volume up and down steps.
+    Volume,
+}
+
+/// Output on the USB HID interfaces, used when the channel's destination includes HID. Keys are held while the input
+/// value is in the upper half of its range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HidConfig {
+    pub output: HidOutput,
+    pub key: KeyCombo,
+    pub media: MediaKey,
+    /// Number of volume steps between mute and full volume on the host.
+    pub volume_steps: u8,
+}
+
+impl Default for HidConfig {
+    fn default() -> Self {
+        Self {
+            output: HidOutput::default(),
+            key: KeyCombo::default(),
+            media: MediaKey::default(),
+            // Windows and most Linux desktops change the volume by 2% per step
+            volume_steps: 50,
+        }
+    }
+}
+
+impl HidConfig {
+    /// Input values from this threshold on count as pressed.
+    pub const THRESHOLD: u8 = 64;
+
+    /// Key combination to hold for an input value, if any.
+    pub fn key(&self, value: u8) -> Option<KeyCombo> {
+        match self.output {
+            HidOutput::Key if value >= Self::THRESHOLD && !self.key.is_empty() => Some(self.key),
+            _ => None,
+        }
+    }
+
+    /// Media key to hold for an input value, if any.
+    pub fn media_key(&self, value: u8) -> Option<MediaKey> {
+        (self.output == HidOutput::Media && value >= Self::THRESHOLD).then_some(self.media)
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.variant(&self.output);
+        self.key.encode(writer);
+        writer.variant(&self.media);
+        writer.u8(self.volume_steps);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            output: reader.variant()?,
+            key: KeyCombo::decode(reader)?,
+            media: reader.variant()?,
+            volume_steps: reader.u8()?.max(1),
+        })
+    }
+}
+
+/// Maximum number of output mappings fed by a single input.
+pub const MAX_MAPPINGS: usize = 4;
+
+/// A single output destination of an input, with its own value range and response curve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mapping {
+    pub enabled: bool,
+    /// Zero based MIDI channel (0 - 15).
+    pub channel: u8,
+    pub message_type: MessageType,
+    /// Controller number, or note number for poly aftertouch and notes.
+    pub number: u8,
+    pub minimum_output: u8,
+    pub maximum_output: u8,
+    pub curve: Curve,
+}
+
+impl Default for Mapping {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            channel: 0,
+            message_type: MessageType::default(),
+            number: 0,
+            minimum_output: 0,
+            maximum_output: 127,
+            curve: Curve::default(),
+        }
+    }
+}
+
+impl Mapping {
+    /// Maps the value of the input stage through the response curve and output range.
+    pub fn apply(&self, value: u8) -> u8 {
+        scale(self.curve.evaluate(value), self.minimum_output, self.maximum_output)
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    pub fn with_channel(mut self, value: u8) -> Self {
+        self.channel = value.min(15);
+        self
+    }
+
+    pub fn with_message_type(mut self, value: MessageType) -> Self {
+        self.message_type = value;
+        self
+    }
+
+    pub fn with_number(mut self, value: u8) -> Self {
+        self.number = value.min(127);
+        self
+    }
+
+    pub fn with_minimum_output(mut self, value: u8) -> Self {
+        self.minimum_output = value.min(127);
+        self
+    }
+
+    pub fn with_maximum_output(mut self, value: u8) -> Self {
+        self.maximum_output = value.min(127);
+        self
+    }
+
+    pub fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.bool(self.enabled);
+        writer.u8(self.channel);
+        writer.variant(&self.message_type);
+        writer.bytes(&[self.number, self.minimum_output, self.maximum_output]);
+        self.curve.encode(writer);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let enabled = reader.bool()?;
+        let channel = reader.u8()?.min(15);
+        let message_type = reader.variant()?;
+        let [number, minimum_output, maximum_output] = reader.bytes()?.map(|value: u8| value.min(127));
+        Some(Self {
+            enabled,
+            channel,
+            message_type,
+            number,
+            minimum_output,
+            maximum_output,
+            curve: Curve::decode(reader)?,
+        })
+    }
+}
+
+/// Scales a 7-bit value to the range between `minimum` and `maximum`, which may be reversed.
+fn scale(value: u8, minimum: u8, maximum: u8) -> u8 {
+    let (minimum, maximum) = (minimum as i32, maximum as i32);
+    (minimum + (maximum - minimum) * value as i32 / 127) as u8
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelConfig {
+    pub input: InputConfig,
+    pub mappings: [Mapping; MAX_MAPPINGS],
+    /// Maximum number of messages per second to each destination, 0 for no limit.
+    pub max_rate: u16,
+    pub output_path: OutputPath,
+    pub destination: Destination,
+    pub hid: HidConfig,
+    pub label: [u8; ChannelConfig::LABEL_SIZE],
+    /// Send the current value to USB again when the host connects or asks for it. Follows the input mode by default,
+    /// see [`InputMode::resends`].
+    pub resend: bool,
+    pub takeover: Takeover,
+    pub gestures: GestureConfig,
+    /// Macro sent by the gestures with the macro action.
+    pub sequence: MacroSequence,
+    pub program: ProgramConfig,
+    pub ramp: RampConfig,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            input: InputConfig::default(),
+            mappings: [Mapping::default(); MAX_MAPPINGS],
+            max_rate: 0,
+            output_path: OutputPath::default(),
+            destination: Destination::default(),
+            hid: HidConfig::default(),
+            label: [0; Self::LABEL_SIZE],
+            resend: true,
+            takeover: Takeover::default(),
+            gestures: GestureConfig::default(),
+            sequence: MacroSequence::default(),
+            program: ProgramConfig::default(),
+            ramp: RampConfig::default(),
+        }
+    }
+}
+
+impl ChannelConfig {
+    const LABEL_SIZE: usize = 32;
+
+    pub fn from_index(index: usize) -> Self {
+        Self::default().with_mapping(0, Mapping::default()
+            .with_enabled(true)
+            .with_number(index as u8))
+    }
+
+    /// Minimum time between two messages to the same destination in microseconds.
+    pub fn min_interval(&self) -> u64 {
+        match self.max_rate {
+            0 => 0,
+            rate => 1_000_000 / rate as u64,
+        }
+    }
+
+    pub fn active_mappings(&self) -> impl Iterator<Item = &Mapping> {
+        self.mappings.iter().filter(|mapping| mapping.enabled)
+    }
+
+    /// Also sets [`resend`](Self::resend) to the default of the mode, so set that afterwards to override it.
+    pub fn with_input_mode(mut self, mode: InputMode) -> Self {
+        self.input.mode = mode;
+        self.resend = mode.resends();
+        self
+    }
+
+    pub fn with_released_value(mut self, value: u8) -> Self {
+        self.input.switch.released_value = value.min(127);
+        self
+    }
+
+    pub fn with_pressed_value(mut self, value: u8) -> Self {
+        self.input.switch.pressed_value = value.min(127);
+        self
+    }
+
+    pub fn with_minimum_input(mut self, value: u8) -> Self {
+        self.input.continuous.minimum_input = value.min(127);
+        self
+    }
+
+    pub fn with_maximum_input(mut self, value: u8) -> Self {
+        self.input.continuous.maximum_input = value.min(127);
+        self
+    }
+
+    pub fn with_minimum_output(mut self, value: u8) -> Self {
+        self.input.continuous.minimum_output = value.min(127);
+        self
+    }
+
+    pub fn with_maximum_output(mut self, value: u8) -> Self {
+        self.input.continuous.maximum_output = value.min(127);
+        self
+    }
+
+    pub fn with_curve(mut self, curve: Curve) -> Self {
+        self.input.continuous.curve = curve;
+        self
+    }
+
+    pub fn with_mapping(mut self, index: usize, mapping: Mapping) -> Self {
+        if let Some(slot) = self.mappings.get_mut(index) {
+            *slot = mapping;
+        }
+        self
+    }
+
+    pub fn with_max_rate(mut self, value: u16) -> Self {
+        self.max_rate = value;
+        self
+    }
+
+    pub fn with_output_path(mut self, value: OutputPath) -> Self {
+        self.output_path = value;
+        self
+    }
+
+    pub fn with_destination(mut self, value: Destination) -> Self {
+        self.destination = value;
+        self
+    }
+
+    pub fn with_hid_output(mut self, value: HidOutput) -> Self {
+        self.hid.output = value;
+        self
+    }
+
+    pub fn with_hid_key(mut self, key: KeyCombo) -> Self {
+        self.hid.key = key;
+        self
+    }
+
+    pub fn with_media_key(mut self, value: MediaKey) -> Self {
+        self.hid.media = value;
+        self
+    }
+
+    pub fn with_volume_steps(mut self, value: u8) -> Self {
+        self.hid.volume_steps = value.max(1);
+        self
+    }
+
+    pub fn with_resend(mut self, value: bool) -> Self {
+        self.resend = value;
+        self
+    }
+
+    pub fn with_takeover(mut self, value: Takeover) -> Self {
+        self.takeover = value;
+        self
+    }
+
+    pub fn with_gesture_action(mut self, gesture: Gesture, action: GestureAction) -> Self {
+        self.gestures.actions[gesture as usize] = action;
+        self
+    }
+
+    pub fn with_double_tap_time(mut self, value: u16) -> Self {
+        self.gestures.double_tap_time = value;
+        self
+    }
+
+    pub fn with_long_press_time(mut self, value: u16) -> Self {
+        self.gestures.long_press_time = value;
+        self
+    }
+
+    pub fn with_repeat_interval(mut self, value: u16) -> Self {
+        self.gestures.repeat_interval = value.max(1);
+        self
+    }
+
+    pub fn with_sequence(mut self, sequence: MacroSequence) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    pub fn with_program_step(mut self, value: ProgramStep) -> Self {
+        self.program.step = value;
+        self
+    }
+
+    pub fn with_program_channel(mut self, value: u8) -> Self {
+        self.program.channel = value & 0x0F;
+        self
+    }
+
+    pub fn with_program(mut self, value: u16) -> Self {
+        self.program.program = value;
+        self
+    }
+
+    pub fn with_program_range(mut self, minimum: u16, maximum: u16) -> Self {
+        self.program.minimum = minimum;
+        self.program.maximum = maximum;
+        self
+    }
+
+    pub fn with_program_wrap(mut self, value: bool) -> Self {
+        self.program.wrap = value;
+        self
+    }
+
+    pub fn with_ramp_start(mut self, value: u8) -> Self {
+        self.ramp.start = value.min(127);
+        self
+    }
+
+    pub fn with_ramp_end(mut self, value: u8) -> Self {
+        self.ramp.end = value.min(127);
+        self
+    }
+
+    pub fn with_ramp_duration(mut self, value: u16) -> Self {
+        self.ramp.duration = value;
+        self
+    }
+
+    pub fn with_ramp_curve(mut self, curve: Curve) -> Self {
+        self.ramp.curve = curve;
+        self
+    }
+
+    pub fn with_ramp_reverse(mut self, value: bool) -> Self {
+        self.ramp.reverse = value;
+        self
+    }
+
+    pub fn with_label(mut self, label: [u8; Self::LABEL_SIZE]) -> Self {
+        self.label = label;
+        self
+    }
+
+    pub fn with_label_str(self, label_str: &str) -> Self {
+        self.with_label(core::array::from_fn(|i| label_str
+            .as_bytes()
+            .get(i)
+            .copied()
+            .unwrap_or(0)))
+    }
+
+    pub fn label_str(&self) -> &str {
+        // Find the first null byte or use the full length
+        let end = self.label.iter().position(|&b| b == 0).unwrap_or(Self::LABEL_SIZE);
+        core::str::from_utf8(&self.label[..end]).unwrap_or("")
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        self.input.encode(writer);
+        for mapping in &self.mappings {
+            mapping.encode(writer);
+        }
+        writer.u16(self.max_rate);
+        writer.variant(&self.output_path);
+        writer.variant(&self.destination);
+        self.hid.encode(writer);
+        writer.bytes(&self.label);
+        writer.bool(self.resend);
+        writer.variant(&self.takeover);
+        self.gestures.encode(writer);
+        self.sequence.encode(writer);
+        self.program.encode(writer);
+        self.ramp.encode(writer);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let input = InputConfig::decode(reader)?;
+        let mut mappings = [Mapping::default(); MAX_MAPPINGS];
+        for mapping in &mut mappings {
+            *mapping = Mapping::decode(reader)?;
+        }
+
+        Some(Self {
+            input,
+            mappings,
+            max_rate: reader.u16()?,
+            output_path: reader.variant()?,
+            destination: reader.variant()?,
+            hid: HidConfig::decode(reader)?,
+            label: reader.bytes()?,
+            resend: reader.bool()?,
+            takeover: reader.variant()?,
+            gestures: GestureConfig::decode(reader)?,
+            sequence: MacroSequence::decode(reader)?,
+            program: ProgramConfig::decode(reader)?,
+            ramp: RampConfig::decode(reader)?,
+        })
+    }
+}
+
+
+
+/// Number of configurations that can be switched with program changes.
+pub const NUM_PRESETS: usize = 4;
+
+/// Forwarding of incoming MIDI between the ports, and other settings of the whole device. Forwarded messages are
+/// merged with the pedal messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoutingConfig {
+    /// Forward DIN input to USB.
+    pub din_to_usb: bool,
+    /// Soft thru: forward DIN input to DIN output.
+    pub din_thru: bool,
+    /// Forward USB input to DIN output, so the device works as a USB-MIDI interface.
+    pub usb_to_din: bool,
+    /// Zero based MIDI channel on which incoming program changes select a preset instead of being forwarded.
+    pub preset_channel: Option<u8>,
+    /// Also send the messages of each channel on a USB cable of its own, next to the merged cable.
+    pub usb_cables: bool,
+    /// Seconds between two resends of the channel values, 0 to only resend on connect and on request.
+    pub refresh_interval: u8,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            din_to_usb: true,
+            din_thru: false,
+            usb_to_din: false,
+            preset_channel: None,
+            usb_cables: false,
+            refresh_interval: 0,
+        }
+    }
+}
+
+impl RoutingConfig {
+    const NO_CHANNEL: u8 = 0xFF;
+
+    /// USB cable carrying all messages, including forwarded ones and the protocol.
+    pub const MERGED_CABLE: u8 = 0;
+
+    /// USB cable of a channel, used if channels have cables of their own.
+    pub fn channel_cable(channel: usize) -> u8 {
+        Self::MERGED_CABLE + 1 + channel as u8
+    }
+
+    /// Time between two periodic resends in microseconds, if enabled.
+    pub fn refresh_interval_micros(&self) -> Option<u64> {
+        (self.refresh_interval > 0).then(|| self.refresh_interval as u64 * 1_000_000)
+    }
+
+    pub fn with_din_to_usb(mut self, value: bool) -> Self {
+        self.din_to_usb = value;
+        self
+    }
+
+    pub fn with_din_thru(mut self, value: bool) -> Self {
+        self.din_thru = value;
+        self
+    }
+
+    pub fn with_usb_to_din(mut self, value: bool) -> Self {
+        self.usb_to_din = value;
+        self
+    }
+
+    pub fn with_preset_channel(mut self, value: Option<u8>) -> Self {
+        self.preset_channel = value.map(|channel| channel.min(15));
+        self
+    }
+
+    pub fn with_usb_cables(mut self, value: bool) -> Self {
+        self.usb_cables = value;
+        self
+    }
+
+    pub fn with_refresh_interval(mut self, value: u8) -> Self {
+        self.refresh_interval = value;
+        self
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.bool(self.din_to_usb);
+        writer.bool(self.din_thru);
+        writer.bool(self.usb_to_din);
+        writer.u8(self.preset_channel.unwrap_or(Self::NO_CHANNEL));
+        writer.bool(self.usb_cables);
+        writer.u8(self.refresh_interval);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        Some(Self {
+            din_to_usb: reader.bool()?,
+            din_thru: reader.bool()?,
+            usb_to_din: reader.bool()?,
+            preset_channel: reader.u8().map(|channel| (channel < 16).then_some(channel))?,
+            usb_cables: reader.bool()?,
+            refresh_interval: reader.u8()?,
+        })
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceConfig<const C: usize> {
+    pub channels: [ChannelConfig; C],
+    pub routing: RoutingConfig,
+}
+
+impl<const C: usize> Default for DeviceConfig<C> {
+    fn default() -> Self {
+        Self {
+            channels: core::array::from_fn(ChannelConfig::from_index),
+            routing: RoutingConfig::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::curve::CurvePreset;
+
+    #[test]
+    fn test_continuous_apply() {
+        let config = ContinuousConfig {
+            minimum_input: 20,
+            maximum_input: 100,
+            minimum_output: 10,
+            maximum_output: 110,
+            ..ContinuousConfig::default()
+        };
+        assert_eq!(config.apply(0), 10);
+        assert_eq!(config.apply(20), 10);
+        assert_eq!(config.apply(100), 110);
+        assert_eq!(config.apply(127), 110);
+
+        let config = ContinuousConfig { curve: CurvePreset::Reverse.curve(), ..config };
+        assert_eq!(config.apply(0), 110);
+        assert_eq!(config.apply(127), 10);
+    }
+
+    #[test]
+    fn test_continuous_apply_fine() {
+        let config = ContinuousConfig { curve: CurvePreset::SCurve.curve(), ..ContinuousConfig::default() };
+        assert_eq!(config.apply_fine(0), 0);
+        assert_eq!(config.apply_fine(RAW_MAX), RAW_MAX);
+
+        // follows the 7-bit curve, but without its steps
+        let fine: heapless::Vec<u16, 4096> = (0..=RAW_MAX).map(|raw| config.apply_fine(raw)).collect();
+        assert!(fine.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!((0..=RAW_MAX).step_by(32).all(|raw| (fine[raw as usize] as i32 - config.apply((raw >> 5) as u8) as i32 * 32).abs() <= 32));
+        assert!(fine.windows(2).filter(|pair| pair[0] != pair[1]).count() > 1000);
+    }
+
+    #[test]
+    fn test_crossfade_mappings() {
+        let fade_in = Mapping::default().with_enabled(true).with_number(1);
+        let fade_out = fade_in.with_number(2).with_minimum_output(127).with_maximum_output(0);
+        let config = ChannelConfig::default()
+            .with_mapping(0, fade_in)
+            .with_mapping(2, fade_out);
+
+        let outputs = |value| config.active_mappings().map(|mapping| mapping.apply(value)).collect::<heapless::Vec<u8, MAX_MAPPINGS>>();
+        assert_eq!(outputs(0).as_slice(), &[0, 127]);
+        assert_eq!(outputs(127).as_slice(), &[127, 0]);
+    }
+
+    #[test]
+    fn test_gesture_messages() {
+        let action = GestureAction::default().with_kind(ActionKind::Message).with_channel(9).with_number(36);
+        let messages = |action: GestureAction| action.messages().collect::<heapless::Vec<MidiMessage, 2>>();
+
+        assert_eq!(messages(action).as_slice(), &[MidiMessage::ControlChange(9, 36, 127)]);
+        assert_eq!(messages(action.with_message_type(MessageType::Note)).as_slice(), &[
+            MidiMessage::NoteOn(9, 36, 127),
+            MidiMessage::NoteOff(9, 36, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_macro_editing() {
+        let step = |number| MacroStep::default().with_message(MidiMessage::ControlChange(0, number, 127));
+        let sequence = (0..3).fold(MacroSequence::default(), |sequence, number| sequence.with_added(step(number)));
+        let numbers = |sequence: MacroSequence| sequence
+            .steps()
+            .iter()
+            .map(|step| MessageType::split(&step.message).2)
+            .collect::<heapless::Vec<u8, MAX_MACRO_STEPS>>();
+
+        assert_eq!(numbers(sequence).as_slice(), &[0, 1, 2]);
+        assert_eq!(numbers(sequence.with_moved(0, 2)).as_slice(), &[1, 2, 0]);
+        assert_eq!(numbers(sequence.with_moved(2, 1)).as_slice(), &[0, 2, 1]);
+        assert_eq!(numbers(sequence.with_removed(1)).as_slice(), &[0, 2]);
+        assert_eq!(sequence.with_removed(0).with_removed(0).with_removed(0), MacroSequence::default());
+
+        let full = (0..10).fold(sequence, |sequence, number| sequence.with_added(step(number)));
+        assert!(full.is_full());
+        assert_eq!(numbers(full)[MAX_MACRO_STEPS - 1], 4);
+    }
+
+    #[test]
+    fn test_ramp_value() {
+        let ramp = RampConfig::default();
+        assert_eq!(ramp.value(0), 0);
+        assert_eq!(ramp.value(127), 127);
+
+        // ramps can go down as well
+        let ramp = ChannelConfig::default().with_ramp_start(100).with_ramp_end(20).ramp;
+        assert_eq!(ramp.value(0), 100);
+        assert_eq!(ramp.value(127), 20);
+    }
+
+    #[test]
+    fn test_resend_follows_mode() {
+        assert!(ChannelConfig::default().resend);
+        assert!(!ChannelConfig::default().with_input_mode(InputMode::Switch).resend);
+        assert!(ChannelConfig::default().with_input_mode(InputMode::Ramp).resend);
+
+        // an explicit choice afterwards wins
+        let config = ChannelConfig::default().with_input_mode(InputMode::Switch).with_resend(true);
+        assert!(config.resend);
+    }
+
+    #[test]
+    fn test_message_split() {
+        for message in [
+            MidiMessage::ControlChange(3, 7, 100),
+            MidiMessage::NoteOn(9, 36, 90),
+            MidiMessage::ProgramChange(0, 12),
+            MidiMessage::PitchBend(1, 64 << 7 | 64),
+        ] {
+            let (message_type, channel, number, value) = MessageType::split(&message);
+            assert_eq!(message_type.message(channel, number, value), message);
+        }
+    }
+
+    #[test]
+    fn test_channel_config_roundtrip() {
+        let config = ChannelConfig::from_index(3)
+            .with_input_mode(InputMode::Switch)
+            .with_pressed_value(100)
+            .with_curve(CurvePreset::SCurve.curve())
+            .with_mapping(1, Mapping::default().with_enabled(true).with_message_type(MessageType::PitchBend))
+            .with_max_rate(500)
+            .with_output_path(OutputPath::Din)
+            .with_destination(Destination::Both)
+            .with_long_press_time(1000)
+            .with_gesture_action(Gesture::DoubleTap, GestureAction::default()
+                .with_kind(ActionKind::Message)
+                .with_message_type(MessageType::Note)
+                .with_number(36))
+            .with_sequence(MacroSequence::default()
+                .with_added(MacroStep::default().with_message(MidiMessage::ControlChange(0, 0, 1)))
+                .with_added(MacroStep::default().with_message(MidiMessage::ProgramChange(0, 5)).with_delay(20)))
+            .with_program_step(ProgramStep::Decrement)
+            .with_program_range(128, 300)
+            .with_ramp_duration(2500)
+            .with_ramp_curve(CurvePreset::Exponential.curve())
+            .with_label_str("Volume");
+
+        let mut buffer = [0; 256];
+        let mut writer = Writer::new(&mut buffer);
+        config.encode(&mut writer);
+        let len = writer.finish().unwrap();
+
+        let mut reader = Reader::new(&buffer[..len]);
+        let decoded = ChannelConfig::decode(&mut reader).unwrap();
+        assert!(reader.is_empty());
+
+        assert_eq!(decoded.input.mode, InputMode::Switch);
+        assert_eq!(decoded.input.switch.pressed_value, 100);
+        assert_eq!(decoded.input.continuous.curve, config.input.continuous.curve);
+        assert_eq!(decoded.mappings[0].number, 3);
+        assert_eq!(decoded.mappings[1].message_type, MessageType::PitchBend);
+        assert_eq!(decoded.max_rate, 500);
+        assert_eq!(decoded.output_path, OutputPath::Din);
+        assert_eq!(decoded.destination, Destination::Both);
+        assert_eq!(decoded.program, config.program);
+        assert_eq!(decoded.ramp, config.ramp);
+        assert_eq!(decoded.label_str(), "Volume");
+
+        // truncated data is rejected
+        assert!(ChannelConfig::decode(&mut Reader::new(&buffer[..len - 1])).is_none());
+    }
+
+    #[test]
+    fn test_decode_clamps_data_bytes() {
+        // a host can send any byte, bypassing the builders
+        let mut config = ChannelConfig::default();
+        config.input.switch = SwitchConfig { released_value: 0x80, pressed_value: 0xFF };
+        config.input.continuous.minimum_input = 0x90;
+        config.input.continuous.maximum_output = 0xC0;
+        config.mappings[0] = Mapping { number: 0xB0, minimum_output: 0x81, maximum_output: 0xFF, ..config.mappings[0] };
+
+        let mut buffer = [0; 256];
+        let mut writer = Writer::new(&mut buffer);
+        config.encode(&mut writer);
+        let len = writer.finish().unwrap();
+        let decoded = ChannelConfig::decode(&mut Reader::new(&buffer[..len])).unwrap();
+
+        assert_eq!(decoded.input.switch, SwitchConfig { released_value: 127, pressed_value: 127 });
+        assert_eq!(decoded.input.continuous.minimum_input, 127);
+        assert_eq!(decoded.input.continuous.maximum_output, 127);
+        let mapping = decoded.mappings[0];
+        assert_eq!((mapping.number, mapping.minimum_output, mapping.maximum_output), (127, 127, 127));
+
+        // the messages stay within the data byte range
+        let mut bytes = [0; 3];
+        MidiMessage::ControlChange(0, mapping.number, mapping.apply(64)).to_bytes(&mut bytes);
+        assert!(bytes[1..].iter().all(|byte| *byte < 0x80));
+        assert_eq!(decoded.input.continuous.apply(127), 127);
+
+        // the builders clamp as well
+        let built = ChannelConfig::default().with_pressed_value(200).with_maximum_input(130);
+        assert_eq!((built.input.switch.pressed_value, built.input.continuous.maximum_input), (127, 127));
+        assert_eq!(Mapping::default().with_number(255).number, 127);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::codec::{Reader, Writer};
+
+/// Maximum number of breakpoints per curve, including both end points.
+pub const MAX_POINTS: usize = 8;
+
+const VALUE_MAX: u8 = 127;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub input: u8,
+    pub output: u8,
+}
+
+impl Breakpoint {
+    pub const fn new(input: u8, output: u8) -> Self {
+        Self { input, output }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum Interpolation {
+    #[default] Linear,
+    Smooth,
+}
+
+/// Response curve through up to `MAX_POINTS` breakpoints.
+///
+/// Breakpoints are sorted by input, and the first and last breakpoint are pinned to the inputs 0 and 127, so the
+/// curve always covers the full value range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Curve {
+    points: [Breakpoint; MAX_POINTS],
+    len: usize,
+    pub interpolation: Interpolation,
+}
+
+impl Default for Curve {
+    fn default() -> Self {
+        CurvePreset::Linear.curve()
+    }
+}
+
+impl Curve {
+    /// Creates a curve from the given breakpoints. Inputs must be ascending; excess breakpoints are dropped.
+    pub fn from_points(points: &[Breakpoint], interpolation: Interpolation) -> Self {
+        let len = points.len().clamp(2, MAX_POINTS);
+        let mut curve = Self {
+            points: [Breakpoint::new(VALUE_MAX, VALUE_MAX); MAX_POINTS],
+            len,
+            interpolation,
+        };
+        let count = points.len().min(len);
+        curve.points[..count].copy_from_slice(&points[..count]);
+        curve.points[0].input = 0;
+        curve.points[len - 1].input = VALUE_MAX;
+        curve
+    }
+
+    pub fn points(&self) -> &[Breakpoint] {
+        &self.points[..self.len]
+    }
+
+    pub fn with_interpolation(mut self, interpolation: Interpolation) -> Self {
+        self.interpolation = interpolation;
+        self
+    }
+
+    /// Moves a breakpoint, keeping the inputs ordered and the end points pinned.
+    pub fn with_point(mut self, index: usize, point: Breakpoint) -> Self {
+        if index >= self.len {
+            return self;
+        }
+
+        let input = if index == 0 {
+            0
+        } else if index == self.len - 1 {
+            VALUE_MAX
+        } else {
+            point.input.clamp(self.points[index - 1].input + 1, self.points[index + 1].input - 1)
+        };
+
+        self.points[index] = Breakpoint::new(input, point.output.min(VALUE_MAX));
+        self
+    }
+
+    /// Inserts a new breakpoint between the end points, if there is room for it.
+    pub fn with_inserted_point(mut self, point: Breakpoint) -> Self {
+        if self.len >= MAX_POINTS {
+            return self;
+        }
+
+        let index = self.points().partition_point(|p| p.input < point.input);
+        if index == 0 || index >= self.len || self.points[index].input == point.input {
+            return self;
+        }
+
+        self.points.copy_within(index..self.len, index + 1);
+        self.points[index] = Breakpoint::new(point.input, point.output.min(VALUE_MAX));
+        self.len += 1;
+        self
+    }
+
+    /// Removes a breakpoint between the end points.
+    pub fn with_removed_point(mut self, index: usize) -> Self {
+        if index == 0 || index >= self.len - 1 {
+            return self;
+        }
+
+        self.points.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        self
+    }
+
+    /// Slope of the straight line between breakpoints `k` and `k + 1`.
+    fn secant(&self, k: usize) -> f32 {
+        let a = self.points[k];
+        let b = self.points[k + 1];
+        (b.output as f32 - a.output as f32) / (b.input as f32 - a.input as f32)
+    }
+
+    /// Tangent at breakpoint `k` for monotone cubic interpolation (Fritsch-Carlson), so the smoothed curve never
+    /// overshoots between breakpoints.
+    fn tangent(&self, k: usize) -> f32 {
+        let tangent = if k == 0 {
+            self.secant(0)
+        } else if k == self.len - 1 {
+            self.secant(k - 1)
+        } else {
+            let (left, right) = (self.secant(k - 1), self.secant(k));
+            if left * right <= 0. { 0. } else { (left + right) / 2. }
+        };
+
+        // limit the tangent to three times the adjacent secants to preserve monotonicity
+        let tangent = if k > 0 { Self::limit(tangent, self.secant(k - 1)) } else { tangent };
+        if k < self.len - 1 { Self::limit(tangent, self.secant(k)) } else { tangent }
+    }
+
+    fn limit(tangent: f32, secant: f32) -> f32 {
+        let bound = 3. * if secant < 0. { -secant } else { secant };
+        tangent.clamp(-bound, bound)
+    }
+
+    pub fn evaluate(&self, input: u8) -> u8 {
+        let input = input.min(VALUE_MAX);
+        let k = self.points().partition_point(|p| p.input <= input).clamp(1, self.len - 1) - 1;
+        let a = self.points[k];
+        let b = self.points[k + 1];
+
+        let dx = b.input as f32 - a.input as f32;
+        let t = (input as f32 - a.input as f32) / dx;
+
+        let output = match self.interpolation {
+            Interpolation::Linear => a.output as f32 + (b.output as f32 - a.output as f32) * t,
+            Interpolation::Smooth => {
+                let (t2, t3) = (t * t, t * t * t);
+                let h00 = 2. * t3 - 3. * t2 + 1.;
+                let h10 = t3 - 2. * t2 + t;
+                let h01 = -2. * t3 + 3. * t2;
+                let h11 = t3 - t2;
+                h00 * a.output as f32 + h10 * dx * self.tangent(k) + h01 * b.output as f32 + h11 * dx * self.tangent(k + 1)
+            },
+        };
+
+        (output + 0.5).clamp(0., VALUE_MAX as f32) as u8
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.variant(&self.interpolation);
+        writer.u8(self.len as u8);
+        for point in self.points() {
+            writer.bytes(&[point.input, point.output]);
+        }
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let interpolation = reader.variant()?;
+        let len = reader.u8()? as usize;
+        if !(2..=MAX_POINTS).contains(&len) {
+            return None;
+        }
+
+        let mut points = [Breakpoint::default(); MAX_POINTS];
+        for point in &mut points[..len] {
+            let [input, output] = reader.bytes()?;
+            *point = Breakpoint::new(input, output.min(VALUE_MAX));
+        }
+
+        // reject anything the editor could not have produced
+        let ascending = points[..len].windows(2).all(|pair| pair[0].input < pair[1].input);
+        let pinned = points[0].input == 0 && points[len - 1].input == VALUE_MAX;
+        (ascending && pinned).then(|| Self::from_points(&points[..len], interpolation))
+    }
+}
+
+
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, strum::Display, strum::VariantArray)]
+pub enum CurvePreset {
+    #[default] Linear,
+    Logarithmic,
+    Exponential,
+    #[strum(to_string="S-Curve")]
+    SCurve,
+    Reverse,
+}
+
+impl CurvePreset {
+    const INPUTS: [u8; MAX_POINTS] = [0, 18, 36, 54, 73, 91, 109, 127];
+
+    const fn table(outputs: [u8; MAX_POINTS]) -> [Breakpoint; MAX_POINTS] {
+        let mut points = [Breakpoint::new(0, 0); MAX_POINTS];
+        let mut i = 0;
+        while i < MAX_POINTS {
+            points[i] = Breakpoint::new(Self::INPUTS[i], outputs[i]);
+            i += 1;
+        }
+        points
+    }
+
+    pub fn curve(self) -> Curve {
+        match self {
+            Self::Linear => Curve::from_points(&[Breakpoint::new(0, 0), Breakpoint::new(127, 127)], Interpolation::Linear),
+            Self::Logarithmic => Curve::from_points(&Self::table([0, 45, 70, 87, 100, 111, 119, 127]), Interpolation::Smooth),
+            Self::Exponential => Curve::from_points(&Self::table([0, 5, 13, 23, 39, 59, 88, 127]), Interpolation::Smooth),
+            Self::SCurve => Curve::from_points(&Self::table([0, 7, 25, 49, 78, 102, 120, 127]), Interpolation::Smooth),
+            Self::Reverse => Curve::from_points(&[Breakpoint::new(0, 127), Breakpoint::new(127, 0)], Interpolation::Linear),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear() {
+        let curve = Curve::default();
+        assert!((0..=127).all(|value| curve.evaluate(value) == value));
+        assert_eq!(curve.evaluate(200), 127);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let curve = CurvePreset::Reverse.curve();
+        assert!((0..=127).all(|value| curve.evaluate(value) == 127 - value));
+    }
+
+    #[test]
+    fn test_breakpoints_are_hit() {
+        for preset in [CurvePreset::Logarithmic, CurvePreset::Exponential, CurvePreset::SCurve] {
+            for interpolation in [Interpolation::Linear, Interpolation::Smooth] {
+                let curve = preset.curve().with_interpolation(interpolation);
+                for point in curve.points() {
+                    assert_eq!(curve.evaluate(point.input), point.output, "{preset} {interpolation}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_smooth_is_monotone() {
+        // a steep step between flat sections would overshoot with unlimited tangents
+        let curve = Curve::from_points(&[
+            Breakpoint::new(0, 0),
+            Breakpoint::new(60, 10),
+            Breakpoint::new(70, 120),
+            Breakpoint::new(127, 127),
+        ], Interpolation::Smooth);
+
+        let values: [u8; 128] = core::array::from_fn(|i| curve.evaluate(i as u8));
+        assert!(values.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn test_editing() {
+        let curve = Curve::default()
+            .with_inserted_point(Breakpoint::new(64, 100))
+            .with_inserted_point(Breakpoint::new(32, 20));
+        assert_eq!(curve.points(), &[
+            Breakpoint::new(0, 0),
+            Breakpoint::new(32, 20),
+            Breakpoint::new(64, 100),
+            Breakpoint::new(127, 127),
+        ]);
+
+        // inputs stay ordered and end points stay pinned
+        let curve = curve
+            .with_point(1, Breakpoint::new(90, 30))
+            .with_point(3, Breakpoint::new(100, 90));
+        assert_eq!(curve.points()[1], Breakpoint::new(63, 30));
+        assert_eq!(curve.points()[3], Breakpoint::new(127, 90));
+
+        let curve = curve.with_removed_point(0).with_removed_point(2);
+        assert_eq!(curve.points(), &[
+            Breakpoint::new(0, 0),
+            Breakpoint::new(63, 30),
+            Breakpoint::new(127, 90),
+        ]);
+    }
+}
@@ -0,0 +1,365 @@
+/// Raw 12-bit ADC reading of an input jack.
+pub type RawValue = u16;
+
+pub const RAW_MAX: RawValue = 4095;
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PedalKind {
+    #[default] Unknown,
+    Disconnected,
+    Expression,
+    LatchingSwitch,
+    MomentarySwitch,
+}
+
+impl PedalKind {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Unknown),
+            1 => Some(Self::Disconnected),
+            2 => Some(Self::Expression),
+            3 => Some(Self::LatchingSwitch),
+            4 => Some(Self::MomentarySwitch),
+            _ => None,
+        }
+    }
+
+    pub fn to_byte(self) -> u8 {
+        self as u8
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Detection {
+    pub kind: PedalKind,
+    /// The pedal rests at the high end of the range (ring-wiper expression pedal, normally closed switch).
+    pub reversed: bool,
+}
+
+/// Streaming classifier for the raw signal of a single input jack.
+///
+/// The detector assumes that the pedal is at rest when the trace starts: heel down for expression pedals and off
+/// for switches. A resting pedal that reads high is reported as reversed.
+#[derive(Debug, Clone, Copy)]
+pub struct PedalDetector {
+    samples: u32,
+    first: RawValue,
+    minimum: RawValue,
+    maximum: RawValue,
+    histogram: [u32; Self::BINS],
+
+    // switch level tracking
+    level: Option<bool>,
+    initial_level: bool,
+    run_length: u32,
+    edges: u32,
+    rest_runs: u32,
+    rest_time: u32,
+    active_runs: u32,
+    active_time: u32,
+}
+
+impl Default for PedalDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PedalDetector {
+    const BINS: usize = 32;
+    const BIN_SHIFT: u32 = 7; // 4096 / 32 bins
+
+    /// Minimum number of samples before anything is reported.
+    pub const MIN_SAMPLES: u32 = 64;
+
+    /// Minimum peak to peak amplitude for the pedal to count as moved.
+    const MOVEMENT: RawValue = RAW_MAX / 20;
+
+    /// Readings this close to either rail count as a switch level or an open jack.
+    const RAIL_MARGIN: RawValue = RAW_MAX / 16;
+
+    /// Switch level hysteresis thresholds.
+    const LOW_THRESHOLD: RawValue = RAW_MAX / 3;
+    const HIGH_THRESHOLD: RawValue = RAW_MAX / 3 * 2;
+
+    /// Share of samples (in percent) between the extremes above which the input is continuous.
+    const CONTINUOUS_SHARE: u32 = 15;
+
+    pub const fn new() -> Self {
+        Self {
+            samples: 0,
+            first: 0,
+            minimum: RawValue::MAX,
+            maximum: 0,
+            histogram: [0; Self::BINS],
+            level: None,
+            initial_level: false,
+            run_length: 0,
+            edges: 0,
+            rest_runs: 0,
+            rest_time: 0,
+            active_runs: 0,
+            active_time: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn push(&mut self, raw_value: RawValue) {
+        let raw_value = raw_value.min(RAW_MAX);
+
+        if self.samples == 0 {
+            self.first = raw_value;
+        }
+        self.samples = self.samples.saturating_add(1);
+        self.minimum = self.minimum.min(raw_value);
+        self.maximum = self.maximum.max(raw_value);
+
+        let bin = (raw_value >> Self::BIN_SHIFT) as usize;
+        self.histogram[bin] = self.histogram[bin].saturating_add(1);
+
+        self.track_level(raw_value);
+    }
+
+    fn track_level(&mut self, raw_value: RawValue) {
+        let level = match self.level {
+            None if raw_value >= Self::HIGH_THRESHOLD => true,
+            None if raw_value <= Self::LOW_THRESHOLD => false,
+            None => return,
+            Some(false) if raw_value >= Self::HIGH_THRESHOLD => true,
+            Some(true) if raw_value <= Self::LOW_THRESHOLD => false,
+            Some(_) => {
+                self.run_length = self.run_length.saturating_add(1);
+                return;
+            }
+        };
+
+        match self.level {
+            None => self.initial_level = level,
+            // the very first run started before the trace, so its length is meaningless
+            Some(previous) if self.edges > 0 => {
+                if previous == self.initial_level {
+                    self.rest_runs += 1;
+                    self.rest_time = self.rest_time.saturating_add(self.run_length);
+                } else {
+                    self.active_runs += 1;
+                    self.active_time = self.active_time.saturating_add(self.run_length);
+                }
+                self.edges += 1;
+            }
+            Some(_) => self.edges += 1,
+        }
+
+        self.level = Some(level);
+        self.run_length = 1;
+    }
+
+    fn at_rail(value: RawValue) -> bool {
+        value <= Self::RAIL_MARGIN || value >= RAW_MAX - Self::RAIL_MARGIN
+    }
+
+    /// Share of samples (in percent) that lie well between the observed extremes.
+    fn continuous_share(&self) -> u32 {
+        let margin = (self.maximum - self.minimum) / 5;
+        let low = self.minimum + margin;
+        let high = self.maximum - margin;
+
+        let inner: u32 = self.histogram
+            .iter()
+            .enumerate()
+            .filter(|(bin, _)| {
+                let center = ((*bin as RawValue) << Self::BIN_SHIFT) + (1 << (Self::BIN_SHIFT - 1));
+                center > low && center < high
+            })
+            .map(|(_, count)| *count)
+            .sum();
+
+        inner.saturating_mul(100) / self.samples
+    }
+
+    pub fn detection(&self) -> Detection {
+        if self.samples < Self::MIN_SAMPLES {
+            return Detection::default();
+        }
+
+        // a static signal at one of the rails means that nothing is plugged in
+        if self.maximum - self.minimum < Self::MOVEMENT {
+            return Detection {
+                kind: if Self::at_rail(self.first) { PedalKind::Disconnected } else { PedalKind::Unknown },
+                reversed: false,
+            };
+        }
+
+        if self.continuous_share() >= Self::CONTINUOUS_SHARE {
+            let midpoint = self.minimum + (self.maximum - self.minimum) / 2;
+            return Detection {
+                kind: PedalKind::Expression,
+                reversed: self.first > midpoint,
+            };
+        }
+
+        // a switch needs at least one complete press to tell momentary from latching
+        if self.rest_runs == 0 || self.active_runs == 0 {
+            return Detection::default();
+        }
+
+        // momentary switches spring back quickly, latching switches stay put until the next press
+        let rest_mean = self.rest_time / self.rest_runs;
+        let active_mean = self.active_time / self.active_runs;
+        let kind = if active_mean.saturating_mul(2) < rest_mean {
+            PedalKind::MomentarySwitch
+        } else {
+            PedalKind::LatchingSwitch
+        };
+
+        Detection {
+            kind,
+            reversed: self.initial_level,
+        }
+    }
+}
+
+/// Records the travel of a continuous pedal to set the input range of its channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RangeCalibration {
+    minimum: RawValue,
+    maximum: RawValue,
+}
+
+impl Default for RangeCalibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RangeCalibration {
+    /// Minimum travel for a usable range.
+    const MIN_TRAVEL: RawValue = RAW_MAX / 8;
+
+    pub const fn new() -> Self {
+        Self { minimum: RawValue::MAX, maximum: 0 }
+    }
+
+    pub fn push(&mut self, raw_value: RawValue) {
+        let raw_value = raw_value.min(RAW_MAX);
+        self.minimum = self.minimum.min(raw_value);
+        self.maximum = self.maximum.max(raw_value);
+    }
+
+    /// Minimum and maximum input as 7-bit values, or `None` if the pedal was not moved far enough. The range is
+    /// pulled in by one step on each end, so both ends are reliably reached despite noise.
+    pub fn input_range(&self) -> Option<(u8, u8)> {
+        if self.maximum < self.minimum || self.maximum - self.minimum < Self::MIN_TRAVEL {
+            return None;
+        }
+        Some(((self.minimum >> 5) as u8 + 1, (self.maximum >> 5) as u8 - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(trace: impl IntoIterator<Item = RawValue>) -> Detection {
+        let mut detector = PedalDetector::new();
+        trace.into_iter().for_each(|raw_value| detector.push(raw_value));
+        detector.detection()
+    }
+
+    /// Replays a trace of (level, number of samples) segments with some ADC noise.
+    fn segments(segments: &[(RawValue, usize)]) -> impl Iterator<Item = RawValue> + '_ {
+        segments
+            .iter()
+            .flat_map(|&(level, count)| (0..count).map(move |i| {
+                let noise = (i % 5) as RawValue * 3;
+                level.saturating_sub(6).saturating_add(noise).min(RAW_MAX)
+            }))
+    }
+
+    fn sweep(from: RawValue, to: RawValue, steps: usize) -> impl Iterator<Item = RawValue> {
+        (0..=steps).map(move |i| {
+            let delta = (to as i32 - from as i32) * i as i32 / steps as i32;
+            (from as i32 + delta) as RawValue
+        })
+    }
+
+    #[test]
+    fn test_too_short() {
+        assert_eq!(detect(segments(&[(2000, 10)])).kind, PedalKind::Unknown);
+    }
+
+    #[test]
+    fn test_disconnected() {
+        assert_eq!(detect(segments(&[(RAW_MAX, 500)])).kind, PedalKind::Disconnected);
+        assert_eq!(detect(segments(&[(0, 500)])).kind, PedalKind::Disconnected);
+        assert_eq!(detect(segments(&[(1800, 500)])).kind, PedalKind::Unknown);
+    }
+
+    #[test]
+    fn test_expression() {
+        let trace = segments(&[(120, 100)])
+            .chain(sweep(120, 3900, 300))
+            .chain(sweep(3900, 800, 200))
+            .chain(sweep(800, 2500, 100));
+        assert_eq!(detect(trace), Detection { kind: PedalKind::Expression, reversed: false });
+    }
+
+    #[test]
+    fn test_reversed_expression() {
+        let trace = segments(&[(3800, 100)])
+            .chain(sweep(3800, 300, 300))
+            .chain(sweep(300, 3800, 300));
+        assert_eq!(detect(trace), Detection { kind: PedalKind::Expression, reversed: true });
+    }
+
+    #[test]
+    fn test_momentary_switch() {
+        let trace = segments(&[
+            (0, 400), (RAW_MAX, 60), (0, 300), (RAW_MAX, 80), (0, 350), (RAW_MAX, 50), (0, 200),
+        ]);
+        assert_eq!(detect(trace), Detection { kind: PedalKind::MomentarySwitch, reversed: false });
+    }
+
+    #[test]
+    fn test_normally_closed_momentary_switch() {
+        let trace = segments(&[
+            (RAW_MAX, 400), (0, 60), (RAW_MAX, 300), (0, 80), (RAW_MAX, 350), (0, 50), (RAW_MAX, 200),
+        ]);
+        assert_eq!(detect(trace), Detection { kind: PedalKind::MomentarySwitch, reversed: true });
+    }
+
+    #[test]
+    fn test_latching_switch() {
+        let trace = segments(&[
+            (0, 400), (RAW_MAX, 350), (0, 420), (RAW_MAX, 380), (0, 300),
+        ]);
+        assert_eq!(detect(trace), Detection { kind: PedalKind::LatchingSwitch, reversed: false });
+    }
+
+    #[test]
+    fn test_switch_needs_a_full_press() {
+        let trace = segments(&[(0, 400), (RAW_MAX, 200)]);
+        assert_eq!(detect(trace).kind, PedalKind::Unknown);
+    }
+
+    #[test]
+    fn test_range_calibration() {
+        let mut calibration = RangeCalibration::new();
+        assert_eq!(calibration.input_range(), None);
+
+        segments(&[(1000, 50)]).for_each(|raw_value| calibration.push(raw_value));
+        assert_eq!(calibration.input_range(), None);
+
+        sweep(400, 3600, 100).for_each(|raw_value| calibration.push(raw_value));
+        assert_eq!(calibration.input_range(), Some((13, 111)));
+    }
+}
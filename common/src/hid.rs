@@ -0,0 +1,317 @@
+use crate::codec::{Reader, Writer};
+
+/// Modifier bits of a boot keyboard report.
+pub mod modifier {
+    pub const LEFT_CTRL: u8 = 0x01;
+    pub const LEFT_SHIFT: u8 = 0x02;
+    pub const LEFT_ALT: u8 = 0x04;
+    pub const LEFT_GUI: u8 = 0x08;
+    pub const RIGHT_CTRL: u8 = 0x10;
+    pub const RIGHT_SHIFT: u8 = 0x20;
+    pub const RIGHT_ALT: u8 = 0x40;
+    pub const RIGHT_GUI: u8 = 0x80;
+}
+
+/// Keyboard usage reported for every key slot when too many keys are held.
+const ERROR_ROLL_OVER: u8 = 0x01;
+
+const KEY_SLOTS: usize = 6;
+
+/// Boot keyboard input report: modifiers, a reserved byte and up to six key usages.
+pub type KeyboardReport = [u8; 2 + KEY_SLOTS];
+
+/// A key with modifiers, e.g. Ctrl+Shift+Z. The key is a usage ID of the HID keyboard page; a combination without key
+/// presses only the modifiers.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyCombo {
+    pub modifiers: u8,
+    pub key: u8,
+}
+
+impl KeyCombo {
+    pub const fn new(modifiers: u8, key: u8) -> Self {
+        Self { modifiers, key }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.modifiers == 0 && self.key == 0
+    }
+
+    pub fn encode(&self, writer: &mut Writer) {
+        writer.bytes(&[self.modifiers, self.key]);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Option<Self> {
+        let [modifiers, key] = reader.bytes()?;
+        Some(Self { modifiers, key })
+    }
+}
+
+/// Builds the report for all currently held combinations. Modifiers are combined and duplicate keys are reported
+/// once. More than six distinct keys are reported as roll over error, as the boot protocol requires.
+pub fn keyboard_report<'a>(held: impl IntoIterator<Item = &'a KeyCombo>) -> KeyboardReport {
+    let mut report = [0; 2 + KEY_SLOTS];
+    let mut len = 0;
+
+    for combo in held {
+        report[0] |= combo.modifiers;
+
+        if combo.key == 0 || report[2..2 + len].contains(&combo.key) {
+            continue;
+        }
+        if len == KEY_SLOTS {
+            report[2..].fill(ERROR_ROLL_OVER);
+            return report;
+        }
+        report[2 + len] = combo.key;
+        len += 1;
+    }
+
+    report
+}
+
+/// Number of axes and buttons of the gamepad, one of each per input channel.
+pub const GAMEPAD_CHANNELS: usize = 4;
+
+/// Largest axis value, axes have 12 bits like the ADC.
+pub const AXIS_MAX: u16 = 0x0FFF;
+
+/// Report descriptor of the gamepad: four 12-bit axes (X, Y, Z, Rx) followed by four buttons and padding.
+pub const GAMEPAD_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // usage page (generic desktop)
+    0x09, 0x05, // usage (game pad)
+    0xA1, 0x01, // collection (application)
+    0x09, 0x30, //   usage (X)
+    0x09, 0x31, //   usage (Y)
+    0x09, 0x32, //   usage (Z)
+    0x09, 0x33, //   usage (Rx)
+    0x15, 0x00, //   logical minimum (0)
+    0x26, 0xFF, 0x0F, //   logical maximum (4095)
+    0x75, 0x0C, //   report size (12)
+    0x95, 0x04, //   report count (4)
+    0x81, 0x02, //   input (data, variable, absolute)
+    0x05, 0x09, //   usage page (button)
+    0x19, 0x01, //   usage minimum (1)
+    0x29, 0x04, //   usage maximum (4)
+    0x15, 0x00, //   logical minimum (0)
+    0x25, 0x01, //   logical maximum (1)
+    0x75, 0x01, //   report size (1)
+    0x95, 0x04, //   report count (4)
+    0x81, 0x02, //   input (data, variable, absolute)
+    0x75, 0x04, //   report size (4)
+    0x95, 0x01, //   report count (1)
+    0x81, 0x03, //   input (constant), padding to a full byte
+    0xC0, // end collection
+];
+
+/// Size of a serialized gamepad report in bytes.
+pub const GAMEPAD_REPORT_SIZE: usize = (GAMEPAD_CHANNELS * 12).div_ceil(8) + 1;
+
+/// What a channel contributes to the gamepad: continuous inputs drive their axis, switches their button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum GamepadInput {
+    Axis(u16),
+    Button(bool),
+}
+
+/// Gamepad state as described by [`GAMEPAD_DESCRIPTOR`]. Unused axes rest at zero like a released pedal.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct GamepadReport {
+    pub axes: [u16; GAMEPAD_CHANNELS],
+    /// Bit mask of pressed buttons, bit 0 is button 1.
+    pub buttons: u8,
+}
+
+impl GamepadReport {
+    /// Builds the report from the inputs of all channels, `None` for channels not on the gamepad.
+    pub fn new<'a>(inputs: impl IntoIterator<Item = &'a Option<GamepadInput>>) -> Self {
+        let mut report = Self::default();
+        for (channel, input) in inputs.into_iter().take(GAMEPAD_CHANNELS).enumerate() {
+            match input {
+                Some(GamepadInput::Axis(value)) => report.axes[channel] = (*value).min(AXIS_MAX),
+                Some(GamepadInput::Button(true)) => report.buttons |= 1 << channel,
+                Some(GamepadInput::Button(false)) | None => {},
+            }
+        }
+        report
+    }
+
+    /// Packs the axes least significant bit first, as HID reports are laid out.
+    pub fn to_bytes(&self) -> [u8; GAMEPAD_REPORT_SIZE] {
+        let bits = self.axes.iter().rev().fold(0u64, |bits, axis| bits << 12 | (*axis & AXIS_MAX) as u64);
+
+        let mut bytes = [0; GAMEPAD_REPORT_SIZE];
+        for (i, byte) in bytes[..GAMEPAD_REPORT_SIZE - 1].iter_mut().enumerate() {
+            *byte = (bits >> (8 * i)) as u8;
+        }
+        bytes[GAMEPAD_REPORT_SIZE - 1] = self.buttons & 0x0F;
+        bytes
+    }
+}
+
+/// Report descriptor of the consumer control interface: a single 16-bit usage, 0 when nothing is pressed.
+pub const CONSUMER_DESCRIPTOR: &[u8] = &[
+    0x05, 0x0C, // usage page (consumer)
+    0x09, 0x01, // usage (consumer control)
+    0xA1, 0x01, // collection (application)
+    0x15, 0x00, //   logical minimum (0)
+    0x26, 0xFF, 0x03, //   logical maximum (1023)
+    0x19, 0x00, //   usage minimum (0)
+    0x2A, 0xFF, 0x03, //   usage maximum (1023)
+    0x75, 0x10, //   report size (16)
+    0x95, 0x01, //   report count (1)
+    0x81, 0x00, //   input (data, array, absolute)
+    0xC0, // end collection
+];
+
+pub type ConsumerReport = [u8; 2];
+
+/// Media keys of the consumer page, as understood by all desktop operating systems.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, strum::Display, strum::VariantArray)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MediaKey {
+    #[default]
+    #[strum(to_string="Play/Pause")]
+    PlayPause,
+    #[strum(to_string="Next Track")]
+    NextTrack,
+    #[strum(to_string="Previous Track")]
+    PreviousTrack,
+    Mute,
+    #[strum(to_string="Volume Up")]
+    VolumeUp,
+    #[strum(to_string="Volume Down")]
+    VolumeDown,
+}
+
+impl MediaKey {
+    pub fn usage(self) -> u16 {
+        match self {
+            Self::PlayPause => 0xCD,
+            Self::NextTrack => 0xB5,
+            Self::PreviousTrack => 0xB6,
+            Self::Mute => 0xE2,
+            Self::VolumeUp => 0xE9,
+            Self::VolumeDown => 0xEA,
+        }
+    }
+
+    pub fn report(key: Option<Self>) -> ConsumerReport {
+        key.map_or(0, Self::usage).to_le_bytes()
+    }
+}
+
+/// Turns the absolute position of a continuous pedal into relative volume steps.
+///
+/// Hosts only accept volume up and down, so the stepper tracks where it left the host volume and sends the difference
+/// to the pedal position. As the host volume is unknown at first, the first update steps all the way down before
+/// following the pedal. A position has to be passed by three quarters of a step before it changes, so a pedal
+/// resting on a step boundary does not toggle the volume back and forth.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VolumeStepper {
+    position: Option<u8>,
+}
+
+impl VolumeStepper {
+    pub const fn new() -> Self {
+        Self { position: None }
+    }
+
+    /// Forgets the host volume, e.g. after the host was reconnected.
+    pub fn reset(&mut self) {
+        self.position = None;
+    }
+
+    /// Number of volume steps to send for a 7-bit pedal `value`, where `steps` is the number of steps between mute and
+    /// full volume on the host. Positive numbers are volume up.
+    pub fn update(&mut self, value: u8, steps: u8) -> i16 {
+        let Some(position) = self.position else {
+            self.position = Some(0);
+            return -(steps as i16);
+        };
+
+        // in 1/127 steps
+        let scaled = value.min(127) as i32 * steps as i32;
+        let current = position as i32 * 127;
+
+        let target = if (scaled - current).abs() >= 127 * 3 / 4 {
+            ((scaled + 63) / 127) as u8
+        } else {
+            position
+        };
+
+        self.position = Some(target);
+        target as i16 - position as i16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE_DOWN: u8 = 0x4E;
+    const SPACE: u8 = 0x2C;
+    const Z: u8 = 0x1D;
+
+    #[test]
+    fn test_keyboard_report() {
+        assert_eq!(keyboard_report(&[]), [0; 8]);
+
+        let held = [
+            KeyCombo::new(0, PAGE_DOWN),
+            KeyCombo::new(modifier::LEFT_CTRL | modifier::LEFT_SHIFT, Z),
+            KeyCombo::new(0, SPACE),
+            KeyCombo::new(0, PAGE_DOWN),
+            KeyCombo::new(modifier::LEFT_ALT, 0),
+        ];
+        assert_eq!(keyboard_report(&held), [0x07, 0, PAGE_DOWN, Z, SPACE, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_roll_over() {
+        let held: [KeyCombo; 7] = core::array::from_fn(|i| KeyCombo::new(modifier::LEFT_GUI, 0x04 + i as u8));
+        assert_eq!(keyboard_report(&held), [modifier::LEFT_GUI, 0, 1, 1, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn test_volume_steps() {
+        let mut stepper = VolumeStepper::new();
+        // the host volume is unknown, so start from mute
+        assert_eq!(stepper.update(64, 16), -16);
+        assert_eq!(stepper.update(64, 16), 8);
+        assert_eq!(stepper.update(127, 16), 8);
+        assert_eq!(stepper.update(0, 16), -16);
+
+        // jitter around the boundary between step 1 and 2 does not toggle the volume
+        assert_eq!(stepper.update(12, 16), 2);
+        assert_eq!((0..10).map(|i| stepper.update(11 + i % 3, 16)).sum::<i16>(), 0);
+        assert_eq!(stepper.update(5, 16), -1);
+
+        stepper.reset();
+        assert_eq!(stepper.update(5, 16), -16);
+    }
+
+    #[test]
+    fn test_media_report() {
+        assert_eq!(MediaKey::report(Some(MediaKey::VolumeDown)), [0xEA, 0x00]);
+        assert_eq!(MediaKey::report(None), [0, 0]);
+    }
+
+    #[test]
+    fn test_gamepad_report() {
+        let inputs = [
+            Some(GamepadInput::Axis(0xABC)),
+            Some(GamepadInput::Button(true)),
+            None,
+            Some(GamepadInput::Axis(0xFFFF)),
+        ];
+        let report = GamepadReport::new(&inputs);
+
+        assert_eq!(report, GamepadReport { axes: [0xABC, 0, 0, AXIS_MAX], buttons: 0b0010 });
+        assert_eq!(report.to_bytes(), [0xBC, 0x0A, 0x00, 0x00, 0xF0, 0xFF, 0b0010]);
+    }
+}
@@ -0,0 +1,245 @@
+/// Something other tasks want to show on the status LEDs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LedEvent {
+    UsbConnected,
+    UsbDisconnected,
+    /// A MIDI message was sent or received.
+    MidiActivity,
+    /// Zero based index of the preset that was just selected.
+    Preset(u8),
+    Calibration(bool),
+    /// A switch input was latched on or off.
+    Latch { channel: u8, latched: bool },
+    Error(ErrorCode),
+}
+
+/// Errors are shown as a number of blinks on the status LED.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ErrorCode {
+    /// The channel processing could not keep up with the sampler.
+    SampleOverrun = 1,
+    /// An output buffer or queue was full and a message was dropped.
+    OutputOverflow = 2,
+    /// A framing or overrun error on the DIN input.
+    DinInput = 3,
+}
+
+impl ErrorCode {
+    pub fn blinks(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Blinks `count` times, pauses, and repeats `repeats` times or forever. All times are in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sequence {
+    start: u64,
+    count: u8,
+    on: u64,
+    off: u64,
+    pause: u64,
+    repeats: Option<u64>,
+}
+
+impl Sequence {
+    fn period(&self) -> u64 {
+        self.count as u64 * (self.on + self.off) + self.pause
+    }
+
+    /// LED level at `now`, or `None` once the sequence is over.
+    fn level(&self, now: u64) -> Option<bool> {
+        let elapsed = now.saturating_sub(self.start);
+        if self.repeats.is_some_and(|repeats| elapsed / self.period() >= repeats) {
+            return None;
+        }
+
+        let t = elapsed % self.period();
+        let blink = t / (self.on + self.off);
+        Some(blink < self.count as u64 && t % (self.on + self.off) < self.on)
+    }
+}
+
+/// Pattern logic for the two status LEDs.
+///
+/// LED 1 shows the device state. In order of priority: error codes, calibration mode, the selected preset and
+/// finally the USB connection, solid when connected and a short heartbeat otherwise. LED 2 is lit while a switch is
+/// latched and flickers on MIDI activity.
+#[derive(Debug, Clone, Copy)]
+pub struct LedEngine {
+    connected: bool,
+    calibrating: bool,
+    /// Bit mask of latched switch channels.
+    latched: u32,
+    error: Option<Sequence>,
+    preset: Option<Sequence>,
+    activity_until: u64,
+}
+
+impl Default for LedEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LedEngine {
+    const HEARTBEAT: Sequence = Sequence { start: 0, count: 1, on: 50, off: 0, pause: 1950, repeats: None };
+    const CALIBRATION: Sequence = Sequence { start: 0, count: 1, on: 100, off: 0, pause: 100, repeats: None };
+    const ACTIVITY: u64 = 30;
+
+    pub const fn new() -> Self {
+        Self {
+            connected: false,
+            calibrating: false,
+            latched: 0,
+            error: None,
+            preset: None,
+            activity_until: 0,
+        }
+    }
+
+    pub fn handle(&mut self, event: LedEvent, now: u64) {
+        match event {
+            LedEvent::UsbConnected => self.connected = true,
+            LedEvent::UsbDisconnected => self.connected = false,
+            LedEvent::MidiActivity => {
+                // leave a gap after each flash, so continuous traffic flickers instead of looking steady
+                if now >= self.activity_until + Self::ACTIVITY {
+                    self.activity_until = now + Self::ACTIVITY;
+                }
+            },
+            LedEvent::Preset(index) => self.preset = Some(Sequence {
+                start: now,
+                count: index.saturating_add(1),
+                on: 150,
+                off: 150,
+                pause: 0,
+                repeats: Some(1),
+            }),
+            LedEvent::Calibration(active) => self.calibrating = active,
+            LedEvent::Latch { channel, latched } => {
+                let mask = 1u32.checked_shl(channel as u32).unwrap_or(0);
+                if latched {
+                    self.latched |= mask;
+                } else {
+                    self.latched &= !mask;
+                }
+            },
+            LedEvent::Error(code) => {
+                // let a running error code finish, so repeated errors stay readable
+                if self.error.and_then(|error| error.level(now)).is_none() {
+                    self.error = Some(Sequence {
+                        start: now,
+                        count: code.blinks(),
+                        on: 200,
+                        off: 200,
+                        pause: 1000,
+                        repeats: Some(3),
+                    });
+                }
+            },
+        }
+    }
+
+    /// Levels of both LEDs at `now`.
+    pub fn levels(&mut self, now: u64) -> [bool; 2] {
+        let error = self.error.and_then(|sequence| sequence.level(now));
+        if error.is_none() {
+            self.error = None;
+        }
+        let preset = self.preset.and_then(|sequence| sequence.level(now));
+        if preset.is_none() {
+            self.preset = None;
+        }
+
+        let status = error
+            .or_else(|| self.calibrating.then(|| Self::CALIBRATION.level(now)).flatten())
+            .or(preset)
+            .unwrap_or_else(|| self.connected || Self::HEARTBEAT.level(now).unwrap_or(false));
+
+        let latched = self.latched != 0;
+        let activity = if now < self.activity_until { !latched } else { latched };
+
+        [status, activity]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Samples one LED every millisecond and returns the lengths of the on phases.
+    fn pulses(engine: &mut LedEngine, led: usize, from: u64, to: u64) -> Vec<u64> {
+        let mut pulses = Vec::new();
+        let mut length = 0;
+        for now in from..to {
+            if engine.levels(now)[led] {
+                length += 1;
+            } else if length > 0 {
+                pulses.push(length);
+                length = 0;
+            }
+        }
+        pulses
+    }
+
+    #[test]
+    fn test_usb_state() {
+        let mut engine = LedEngine::new();
+        assert_eq!(pulses(&mut engine, 0, 0, 4000), [50, 50]);
+
+        engine.handle(LedEvent::UsbConnected, 4000);
+        assert!((4000..5000).all(|now| engine.levels(now)[0]));
+    }
+
+    #[test]
+    fn test_preset_blinks() {
+        let mut engine = LedEngine::new();
+        engine.handle(LedEvent::UsbConnected, 0);
+        engine.handle(LedEvent::Preset(2), 100);
+
+        // three blinks, then solid again
+        assert_eq!(pulses(&mut engine, 0, 100, 1000), [150, 150, 150]);
+        assert!(engine.levels(1000)[0]);
+    }
+
+    #[test]
+    fn test_error_has_priority() {
+        let mut engine = LedEngine::new();
+        engine.handle(LedEvent::Calibration(true), 0);
+        engine.handle(LedEvent::Error(ErrorCode::OutputOverflow), 0);
+        // a second error while the first one is showing is ignored
+        engine.handle(LedEvent::Error(ErrorCode::DinInput), 10);
+
+        assert_eq!(pulses(&mut engine, 0, 0, 1800), [200, 200]);
+        assert_eq!(pulses(&mut engine, 0, 1800, 5400), [200, 200, 200, 200]);
+
+        // back to the calibration pattern
+        assert_eq!(pulses(&mut engine, 0, 5400, 5800), [100, 100]);
+    }
+
+    #[test]
+    fn test_activity_and_latch() {
+        let mut engine = LedEngine::new();
+
+        // a message every millisecond still shows as flicker
+        let mut on = 0;
+        for now in 0..600 {
+            engine.handle(LedEvent::MidiActivity, now);
+            on += engine.levels(now)[1] as u32;
+        }
+        assert_eq!(on, 300);
+
+        engine.handle(LedEvent::Latch { channel: 3, latched: true }, 1000);
+        assert!(engine.levels(1000)[1]);
+        engine.handle(LedEvent::MidiActivity, 1010);
+        assert!(!engine.levels(1010)[1]);
+        engine.handle(LedEvent::Latch { channel: 3, latched: false }, 1100);
+        assert!(!engine.levels(1100)[1]);
+    }
+}
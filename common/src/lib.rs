@@ -1,3 +1,12 @@
 #![no_std]
 
 pub mod midi;
+pub mod codec;
+pub mod config;
+pub mod curve;
+pub mod detect;
+pub mod hid;
+pub mod led;
+pub mod protocol;
+pub mod shell;
+pub mod update;
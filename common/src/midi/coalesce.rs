@@ -0,0 +1,299 @@
+use heapless::{Deque, Vec};
+
+use super::MidiMessage;
+
+/// Number of messages that can wait in order, enough for a macro without pauses and a program change with its bank
+/// select.
+pub const ORDERED_SLOTS: usize = 16;
+
+/// Destination of a message: status byte (message type and channel) and controller or note number.
+pub type Destination = (u8, u8);
+
+impl MidiMessage {
+    /// Messages with the same destination overwrite each other. Note on and off have destinations of their own, so a
+    /// fast press and release never drops the note on.
+    pub fn destination(&self) -> Destination {
+        match *self {
+            MidiMessage::NoteOff(channel, note, _) => (0x80 | channel, note),
+            MidiMessage::NoteOn(channel, note, _) => (0x90 | channel, note),
+            MidiMessage::PolyKeyPressure(channel, note, _) => (0xA0 | channel, note),
+            MidiMessage::ControlChange(channel, control, _) => (0xB0 | channel, control),
+            MidiMessage::ProgramChange(channel, _) => (0xC0 | channel, 0),
+            MidiMessage::ChannelPressure(channel, _) => (0xD0 | channel, 0),
+            MidiMessage::PitchBend(channel, _) => (0xE0 | channel, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    destination: Destination,
+    message: MidiMessage,
+    pending: bool,
+    /// Order in which destinations became pending, so no destination starves.
+    sequence: u64,
+    /// Minimum time between two messages to this destination in microseconds.
+    interval: u64,
+    last_sent: Option<u64>,
+}
+
+impl Entry {
+    fn ready_at(&self) -> u64 {
+        self.last_sent.map_or(0, |last_sent| last_sent.saturating_add(self.interval))
+    }
+}
+
+/// Output buffer that keeps only the latest message per destination and enforces a minimum interval between
+/// messages to the same destination.
+///
+/// Producers never block: a new value simply replaces the pending one. A slow consumer therefore always receives the
+/// newest value instead of a stale backlog. Messages that belong together, like a macro, are queued in order instead,
+/// see [`CoalescingBuffer::push_ordered`]. All times are in microseconds.
+#[derive(Debug)]
+pub struct CoalescingBuffer<const N: usize> {
+    entries: Vec<Entry, N>,
+    /// Messages sent as they are, with the sequence numbers they were queued with.
+    ordered: Deque<(u64, MidiMessage), ORDERED_SLOTS>,
+    sequence: u64,
+}
+
+impl<const N: usize> Default for CoalescingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> CoalescingBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            ordered: Deque::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Queues a message, replacing any pending message to the same destination. Returns `false` if the message was
+    /// dropped because all slots hold pending messages to other destinations.
+    pub fn push(&mut self, message: MidiMessage, interval: u64) -> bool {
+        self.sequence += 1;
+        let destination = message.destination();
+
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.destination == destination) {
+            // an already pending destination keeps its place in line, except for notes: a note on or off moves behind
+            // its pending counterpart, so the latest state of the note is sent last
+            if !entry.pending || matches!(message, MidiMessage::NoteOn(..) | MidiMessage::NoteOff(..)) {
+                entry.sequence = self.sequence;
+            }
+            entry.message = message;
+            entry.pending = true;
+            entry.interval = interval;
+            return true;
+        }
+
+        let entry = Entry {
+            destination,
+            message,
+            pending: true,
+            sequence: self.sequence,
+            interval,
+            last_sent: None,
+        };
+
+        let Err(entry) = self.entries.push(entry) else {
+            return true;
+        };
+
+        // reuse the slot of the destination that has been idle for the longest time
+        match self.entries.iter_mut().filter(|entry| !entry.pending).min_by_key(|entry| entry.last_sent) {
+            Some(slot) => {
+                *slot = entry;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Queues a message behind everything queued before. It is neither replaced nor rate limited, so steps of a
+    /// sequence keep their order even when they go to the same destination. Returns `false` if the message was
+    /// dropped because the ordered queue is full.
+    pub fn push_ordered(&mut self, message: MidiMessage) -> bool {
+        self.sequence += 1;
+        self.ordered.push_back((self.sequence, message)).is_ok()
+    }
+
+    /// Takes the longest waiting message whose destination is not rate limited at `now`.
+    pub fn pop(&mut self, now: u64) -> Option<MidiMessage> {
+        let next_ordered = self.ordered.front().map(|(sequence, _)| *sequence);
+        let entry = self.entries
+            .iter_mut()
+            .filter(|entry| entry.pending && entry.ready_at() <= now)
+            .filter(|entry| next_ordered.is_none_or(|sequence| entry.sequence < sequence))
+            .min_by_key(|entry| entry.sequence);
+
+        let Some(entry) = entry else {
+            return self.ordered.pop_front().map(|(_, message)| message);
+        };
+        entry.pending = false;
+        entry.last_sent = Some(now);
+        Some(entry.message)
+    }
+
+    /// Earliest time at which a pending message may be sent.
+    pub fn next_ready(&self) -> Option<u64> {
+        if !self.ordered.is_empty() {
+            return Some(0);
+        }
+        self.entries
+            .iter()
+            .filter(|entry| entry.pending)
+            .map(Entry::ready_at)
+            .min()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ordered.is_empty() && !self.entries.iter().any(|entry| entry.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Pushes one value per millisecond and lets a consumer take a message every `consumer_period` milliseconds.
+    fn simulate(
+        buffer: &mut CoalescingBuffer<8>,
+        producers: &[fn(u8) -> MidiMessage],
+        interval: u64,
+        consumer_period: u64,
+    ) -> Vec<(u64, MidiMessage)> {
+        let mut sent = Vec::new();
+
+        for ms in 0..=127u64 {
+            for producer in producers {
+                assert!(buffer.push(producer(ms as u8), interval));
+            }
+            if ms % consumer_period == 0 {
+                let now = ms * 1000;
+                sent.extend(buffer.pop(now).map(|message| (now, message)));
+            }
+        }
+
+        // drain the rest after the sweep
+        let mut now = 128_000;
+        while !buffer.is_empty() {
+            now = now.max(buffer.next_ready().unwrap());
+            sent.extend(buffer.pop(now).map(|message| (now, message)));
+        }
+
+        sent
+    }
+
+    #[test]
+    fn test_slow_consumer_gets_latest_value() {
+        let mut buffer = CoalescingBuffer::<8>::new();
+        let sent = simulate(&mut buffer, &[|v| MidiMessage::ControlChange(0, 11, v)], 0, 10);
+
+        // one message per consumer slot, always the value that was current at that time
+        let values: Vec<u8> = sent.iter().map(|(_, message)| match message {
+            MidiMessage::ControlChange(_, _, value) => *value,
+            _ => unreachable!(),
+        }).collect();
+        assert_eq!(values, [0, 10, 20, 30, 40, 50, 60, 70, 80, 90, 100, 110, 120, 127]);
+    }
+
+    #[test]
+    fn test_destinations_are_served_in_turn() {
+        let mut buffer = CoalescingBuffer::<8>::new();
+        let sent = simulate(&mut buffer, &[
+            |v| MidiMessage::ControlChange(0, 1, v),
+            |v| MidiMessage::ControlChange(0, 2, 127 - v),
+            |v| MidiMessage::PitchBend(3, (v as u16) << 7),
+        ], 0, 2);
+
+        let destinations: Vec<Destination> = sent.iter().map(|(_, message)| message.destination()).collect();
+        assert!(destinations.chunks(3).all(|chunk| {
+            chunk.iter().enumerate().all(|(i, d)| !chunk[..i].contains(d))
+        }));
+
+        // every destination ends on its final value
+        let last = |destination: Destination| sent.iter().rev().find(|(_, m)| m.destination() == destination).unwrap().1;
+        assert_eq!(last((0xB0, 1)), MidiMessage::ControlChange(0, 1, 127));
+        assert_eq!(last((0xB0, 2)), MidiMessage::ControlChange(0, 2, 0));
+        assert_eq!(last((0xE3, 0)), MidiMessage::PitchBend(3, 127 << 7));
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut buffer = CoalescingBuffer::<8>::new();
+
+        // the consumer polls every millisecond, but the destination may only be served every 5 milliseconds
+        let sent = simulate(&mut buffer, &[|v| MidiMessage::ChannelPressure(0, v)], 5000, 1);
+        assert!(sent.windows(2).all(|w| w[1].0 - w[0].0 >= 5000));
+        assert_eq!(sent.last().unwrap().1, MidiMessage::ChannelPressure(0, 127));
+    }
+
+    #[test]
+    fn test_note_on_and_off_are_kept() {
+        let mut buffer = CoalescingBuffer::<8>::new();
+        assert!(buffer.push(MidiMessage::NoteOn(0, 60, 100), 0));
+        assert!(buffer.push(MidiMessage::NoteOff(0, 60, 0), 0));
+        assert_eq!(buffer.pop(0), Some(MidiMessage::NoteOn(0, 60, 100)));
+        assert_eq!(buffer.pop(0), Some(MidiMessage::NoteOff(0, 60, 0)));
+
+        // pressed again before the consumer caught up: the note ends up on
+        assert!(buffer.push(MidiMessage::NoteOn(0, 60, 100), 0));
+        assert!(buffer.push(MidiMessage::NoteOff(0, 60, 0), 0));
+        assert!(buffer.push(MidiMessage::NoteOn(0, 60, 90), 0));
+        assert_eq!(buffer.pop(0), Some(MidiMessage::NoteOff(0, 60, 0)));
+        assert_eq!(buffer.pop(0), Some(MidiMessage::NoteOn(0, 60, 90)));
+        assert_eq!(buffer.pop(0), None);
+    }
+
+    #[test]
+    fn test_ordered() {
+        let mut buffer = CoalescingBuffer::<8>::new();
+        assert!(buffer.push(MidiMessage::ControlChange(0, 0, 1), 10_000));
+        assert_eq!(buffer.pop(0), Some(MidiMessage::ControlChange(0, 0, 1)));
+
+        // neither replaced nor held back by the rate limit of the destination
+        assert!(buffer.push(MidiMessage::ControlChange(0, 11, 5), 0));
+        assert!(buffer.push_ordered(MidiMessage::ControlChange(0, 0, 2)));
+        assert!(buffer.push_ordered(MidiMessage::ControlChange(0, 0, 3)));
+        assert!(buffer.push_ordered(MidiMessage::ProgramChange(0, 4)));
+        assert!(buffer.push(MidiMessage::ControlChange(0, 11, 6), 0));
+        assert_eq!(buffer.next_ready(), Some(0));
+        // the pending destination keeps its place in line, ahead of the queue
+        assert_eq!(buffer.pop(1000), Some(MidiMessage::ControlChange(0, 11, 6)));
+        assert_eq!(buffer.pop(1000), Some(MidiMessage::ControlChange(0, 0, 2)));
+        assert_eq!(buffer.pop(1000), Some(MidiMessage::ControlChange(0, 0, 3)));
+        assert_eq!(buffer.pop(1000), Some(MidiMessage::ProgramChange(0, 4)));
+        assert!(buffer.is_empty());
+
+        // a destination that became pending later waits behind the queue
+        assert!(buffer.push_ordered(MidiMessage::ControlChange(0, 0, 7)));
+        assert!(buffer.push(MidiMessage::ControlChange(0, 11, 8), 0));
+        assert_eq!(buffer.pop(2000), Some(MidiMessage::ControlChange(0, 0, 7)));
+        assert_eq!(buffer.pop(2000), Some(MidiMessage::ControlChange(0, 11, 8)));
+
+        assert!((0..ORDERED_SLOTS).all(|_| buffer.push_ordered(MidiMessage::ProgramChange(0, 0))));
+        assert!(!buffer.push_ordered(MidiMessage::ProgramChange(0, 0)));
+    }
+
+    #[test]
+    fn test_full_buffer() {
+        let mut buffer = CoalescingBuffer::<2>::new();
+        assert!(buffer.push(MidiMessage::ControlChange(0, 1, 0), 0));
+        assert!(buffer.push(MidiMessage::ControlChange(0, 2, 0), 0));
+        assert!(!buffer.push(MidiMessage::ControlChange(0, 3, 0), 0));
+
+        // once a destination is idle, its slot can be reused
+        assert_eq!(buffer.pop(0), Some(MidiMessage::ControlChange(0, 1, 0)));
+        assert!(buffer.push(MidiMessage::ControlChange(0, 3, 0), 0));
+        assert_eq!(buffer.pop(0), Some(MidiMessage::ControlChange(0, 2, 0)));
+        assert_eq!(buffer.pop(0), Some(MidiMessage::ControlChange(0, 3, 0)));
+        assert_eq!(buffer.pop(0), None);
+    }
+}
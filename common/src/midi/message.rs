@@ -8,7 +8,8 @@ pub type Program = u8;
 pub type Value = u8;
 pub type Value14 = u16;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum MidiMessage {
     NoteOff(Channel, Note, Velocity),
     NoteOn(Channel, Note, Velocity),
@@ -19,7 +20,64 @@ pub enum MidiMessage {
     PitchBend(Channel, Value14),
 }
 
+/// Anything that is passed through between ports: a channel message or a single system real-time byte (clock,
+/// start, stop, ...). Real-time bytes may be interleaved with other messages and never affect running status.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MidiEvent {
+    Message(MidiMessage),
+    Realtime(u8),
+}
+
+impl From<MidiMessage> for MidiEvent {
+    fn from(message: MidiMessage) -> Self {
+        Self::Message(message)
+    }
+}
+
 impl MidiMessage {
+    /// Builds a channel message from its status byte and data bytes. Returns `None` for system messages.
+    pub fn from_bytes(status: u8, data1: u8, data2: u8) -> Option<Self> {
+        let channel = status & 0x0f;
+
+        match status & 0xf0 {
+            0x80 => Some(MidiMessage::NoteOff(channel, data1, data2)),
+            0x90 => Some(MidiMessage::NoteOn(channel, data1, data2)),
+            0xA0 => Some(MidiMessage::PolyKeyPressure(channel, data1, data2)),
+            0xB0 => Some(MidiMessage::ControlChange(channel, data1, data2)),
+            0xC0 => Some(MidiMessage::ProgramChange(channel, data1)),
+            0xD0 => Some(MidiMessage::ChannelPressure(channel, data1)),
+            0xE0 => Some(MidiMessage::PitchBend(channel, (data1 as u16) | (data2 as u16) << 7)),
+            _ => None,
+        }
+    }
+
+    /// Number of data bytes following a channel status byte.
+    pub fn data_len(status: u8) -> usize {
+        match status & 0xf0 {
+            0xC0 | 0xD0 => 1,
+            _ => 2,
+        }
+    }
+
+    /// Writes the status and data bytes to `bytes` and returns the message length.
+    pub fn to_bytes(&self, bytes: &mut [u8; 3]) -> usize {
+        *bytes = match *self {
+            MidiMessage::NoteOff(channel, data1, data2) => [0x80 | channel, data1, data2],
+            MidiMessage::NoteOn(channel, data1, data2) => [0x90 | channel, data1, data2],
+            MidiMessage::PolyKeyPressure(channel, data1, data2) => [0xA0 | channel, data1, data2],
+            MidiMessage::ControlChange(channel, data1, data2) => [0xB0 | channel, data1, data2],
+            MidiMessage::ProgramChange(channel, data1) => [0xC0 | channel, data1, 0],
+            MidiMessage::ChannelPressure(channel, data1) => [0xD0 | channel, data1, 0],
+            MidiMessage::PitchBend(channel, data12) => [0xE0 | channel, (data12 & 0x7f) as u8, ((data12 >> 7) & 0x7f) as u8],
+        };
+
+        match self {
+            MidiMessage::ProgramChange(..) | MidiMessage::ChannelPressure(..) => 2,
+            _ => 3,
+        }
+    }
+
     // pub fn from_din_packet(packet: [u8; 3]) -> Option<Self> {
     //     let status = packet[0];
     //     let command = status & 0xf0;
@@ -1,3 +1,11 @@
 mod message;
+mod usb;
+mod coalesce;
+mod running_status;
+mod parser;
 
 pub use message::*;
+pub use usb::*;
+pub use coalesce::*;
+pub use running_status::*;
+pub use parser::*;
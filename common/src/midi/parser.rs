@@ -0,0 +1,101 @@
+use super::{MidiEvent, MidiMessage};
+
+/// Byte-wise parser for a serial (DIN) MIDI stream.
+///
+/// Handles running status and real-time bytes in the middle of a message. System exclusive and system common
+/// messages are skipped, as are data bytes without a preceding status.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MidiParser {
+    status: Option<u8>,
+    data: [u8; 2],
+    len: usize,
+}
+
+impl MidiParser {
+    pub const fn new() -> Self {
+        Self {
+            status: None,
+            data: [0; 2],
+            len: 0,
+        }
+    }
+
+    /// Drops any partially received message, e.g. after a framing error.
+    pub fn reset(&mut self) {
+        self.status = None;
+        self.len = 0;
+    }
+
+    /// Feeds one byte and returns the event it completes, if any.
+    pub fn push(&mut self, byte: u8) -> Option<MidiEvent> {
+        match byte {
+            // real-time, may appear anywhere
+            0xF8..=0xFF => Some(MidiEvent::Realtime(byte)),
+            // system exclusive and system common cancel running status
+            0xF0..=0xF7 => {
+                self.reset();
+                None
+            },
+            0x80..=0xEF => {
+                self.status = Some(byte);
+                self.len = 0;
+                None
+            },
+            _ => {
+                let status = self.status?;
+                self.data[self.len] = byte;
+                self.len += 1;
+
+                if self.len < MidiMessage::data_len(status) {
+                    return None;
+                }
+
+                // keep the status for running status
+                self.len = 0;
+                MidiMessage::from_bytes(status, self.data[0], self.data[1]).map(MidiEvent::Message)
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn parse(bytes: &[u8]) -> Vec<MidiEvent> {
+        let mut parser = MidiParser::new();
+        bytes.iter().filter_map(|byte| parser.push(*byte)).collect()
+    }
+
+    #[test]
+    fn test_running_status() {
+        assert_eq!(parse(&[0xB0, 11, 1, 11, 2, 0xD1, 5, 6]), [
+            MidiEvent::Message(MidiMessage::ControlChange(0, 11, 1)),
+            MidiEvent::Message(MidiMessage::ControlChange(0, 11, 2)),
+            MidiEvent::Message(MidiMessage::ChannelPressure(1, 5)),
+            MidiEvent::Message(MidiMessage::ChannelPressure(1, 6)),
+        ]);
+    }
+
+    #[test]
+    fn test_interleaved_realtime() {
+        assert_eq!(parse(&[0x92, 60, 0xF8, 100, 0xE0, 0x01, 0xFA, 0x40]), [
+            MidiEvent::Realtime(0xF8),
+            MidiEvent::Message(MidiMessage::NoteOn(2, 60, 100)),
+            MidiEvent::Realtime(0xFA),
+            MidiEvent::Message(MidiMessage::PitchBend(0, 0x2001)),
+        ]);
+    }
+
+    #[test]
+    fn test_skip_sysex() {
+        // stray data before the first status, then a sysex that cancels running status
+        assert_eq!(parse(&[0x12, 0xB0, 1, 2, 0xF0, 0x7D, 0x01, 0xF7, 3, 4, 0xC5, 7]), [
+            MidiEvent::Message(MidiMessage::ControlChange(0, 1, 2)),
+            MidiEvent::Message(MidiMessage::ProgramChange(5, 7)),
+        ]);
+    }
+}
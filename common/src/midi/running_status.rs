@@ -0,0 +1,76 @@
+use super::MidiMessage;
+
+/// Serial MIDI encoder that omits the status byte if it matches the previously sent one.
+///
+/// Running status saves a third of the bandwidth on a 31.25 kbaud line when a single controller is streaming. Call
+/// [`RunningStatusEncoder::reset`] whenever the receiver may have lost track of the status, e.g. after other data was
+/// sent on the same line or after a long pause.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RunningStatusEncoder {
+    status: Option<u8>,
+}
+
+impl RunningStatusEncoder {
+    pub const fn new() -> Self {
+        Self { status: None }
+    }
+
+    /// Forces the next message to include its status byte.
+    pub fn reset(&mut self) {
+        self.status = None;
+    }
+
+    /// Writes the bytes to send for `message` to `bytes` and returns their number.
+    pub fn encode(&mut self, message: &MidiMessage, bytes: &mut [u8; 3]) -> usize {
+        let len = message.to_bytes(bytes);
+        let status = bytes[0];
+
+        if self.status.replace(status) == Some(status) {
+            bytes.copy_within(1..len, 0);
+            len - 1
+        } else {
+            len
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn encode_all(encoder: &mut RunningStatusEncoder, messages: &[MidiMessage]) -> Vec<u8> {
+        let mut stream = Vec::new();
+        for message in messages {
+            let mut bytes = [0; 3];
+            let len = encoder.encode(message, &mut bytes);
+            stream.extend_from_slice(&bytes[..len]);
+        }
+        stream
+    }
+
+    #[test]
+    fn test_running_status() {
+        let mut encoder = RunningStatusEncoder::new();
+        let stream = encode_all(&mut encoder, &[
+            MidiMessage::ControlChange(0, 11, 1),
+            MidiMessage::ControlChange(0, 11, 2),
+            MidiMessage::ControlChange(0, 12, 3),
+            MidiMessage::ControlChange(1, 11, 4),
+            MidiMessage::ChannelPressure(1, 5),
+            MidiMessage::ChannelPressure(1, 6),
+        ]);
+        assert_eq!(stream, [0xB0, 11, 1, 11, 2, 12, 3, 0xB1, 11, 4, 0xD1, 5, 6]);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut encoder = RunningStatusEncoder::new();
+        encode_all(&mut encoder, &[MidiMessage::PitchBend(2, 0x2000)]);
+        encoder.reset();
+        let stream = encode_all(&mut encoder, &[MidiMessage::PitchBend(2, 0x2001)]);
+        assert_eq!(stream, [0xE2, 0x01, 0x40]);
+    }
+}
@@ -0,0 +1,202 @@
+use heapless::Vec;
+
+use super::{MidiEvent, MidiMessage};
+
+/// A USB-MIDI event packet: cable number and code index number followed by up to three MIDI bytes.
+/// Documentation: https://www.usb.org/sites/default/files/midi10.pdf
+pub type UsbPacket = [u8; 4];
+
+impl MidiMessage {
+    pub fn to_usb_packet(&self, cable: u8) -> UsbPacket {
+        let mut bytes = [0u8; 3];
+        self.to_bytes(&mut bytes);
+
+        // for channel voice messages, the code index number equals the upper status nibble
+        [(cable << 4) | (bytes[0] >> 4), bytes[0], bytes[1], bytes[2]]
+    }
+}
+
+impl MidiEvent {
+    pub fn to_usb_packet(&self, cable: u8) -> UsbPacket {
+        match self {
+            MidiEvent::Message(message) => message.to_usb_packet(cable),
+            // single byte code index number
+            MidiEvent::Realtime(byte) => [(cable << 4) | 0x0F, *byte, 0, 0],
+        }
+    }
+
+    /// Decodes a channel message or real-time packet. System exclusive and system common packets yield `None`.
+    pub fn from_usb_packet(packet: &UsbPacket) -> Option<Self> {
+        match packet[0] & 0x0F {
+            0x08..=0x0E => MidiMessage::from_bytes(packet[1], packet[2], packet[3]).map(MidiEvent::Message),
+            0x0F if packet[1] >= 0xF8 => Some(MidiEvent::Realtime(packet[1])),
+            _ => None,
+        }
+    }
+}
+
+/// The virtual cable a packet was sent on.
+pub fn packet_cable(packet: &UsbPacket) -> u8 {
+    packet[0] >> 4
+}
+
+/// The MIDI bytes carried by a packet, as many as its code index number says.
+pub fn packet_bytes(packet: &UsbPacket) -> &[u8] {
+    let len = match packet[0] & 0x0F {
+        0x00 | 0x01 => 0, // reserved
+        0x05 | 0x0F => 1,
+        0x02 | 0x06 | 0x0C | 0x0D => 2,
+        _ => 3,
+    };
+    &packet[1..=len]
+}
+
+/// Splits a complete system exclusive message (including 0xF0 and 0xF7) into USB-MIDI packets.
+pub fn sysex_packets(cable: u8, data: &[u8]) -> impl Iterator<Item = UsbPacket> + '_ {
+    let count = data.len().div_ceil(3);
+
+    data.chunks(3).enumerate().map(move |(i, chunk)| {
+        let cin = if i + 1 < count {
+            0x04 // sysex starts or continues
+        } else {
+            0x04 + chunk.len() as u8 // sysex ends with 1, 2 or 3 bytes
+        };
+
+        let mut packet = [(cable << 4) | cin, 0, 0, 0];
+        packet[1..=chunk.len()].copy_from_slice(chunk);
+        packet
+    })
+}
+
+/// Reassembles system exclusive messages from USB-MIDI packets into a buffer of `N` bytes.
+///
+/// Messages that do not fit are dropped as a whole, so a large foreign dump can never be mistaken for a shorter one.
+#[derive(Debug, Default)]
+pub struct SysexAssembler<const N: usize> {
+    buffer: Vec<u8, N>,
+    receiving: bool,
+    overflow: bool,
+}
+
+impl<const N: usize> SysexAssembler<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            receiving: false,
+            overflow: false,
+        }
+    }
+
+    /// Feeds a packet and returns the complete message (including 0xF0 and 0xF7) once its last packet arrived.
+    /// Packets other than system exclusive are ignored.
+    pub fn push(&mut self, packet: &UsbPacket) -> Option<&[u8]> {
+        let len = match packet[0] & 0x0F {
+            0x04 | 0x07 => 3,
+            0x05 => 1,
+            0x06 => 2,
+            _ => return None,
+        };
+        let data = &packet[1..=len];
+
+        if data[0] == 0xF0 {
+            self.buffer.clear();
+            self.receiving = true;
+            self.overflow = false;
+        }
+        if !self.receiving {
+            // single byte system common message or the tail of a message we missed the start of
+            return None;
+        }
+
+        if self.buffer.extend_from_slice(data).is_err() {
+            self.overflow = true;
+        }
+
+        if packet[0] & 0x0F == 0x04 {
+            return None;
+        }
+
+        self.receiving = false;
+        (!self.overflow).then_some(self.buffer.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sysex_packets() {
+        let packets: [UsbPacket; 3] = {
+            let mut iter = sysex_packets(1, &[0xF0, 0x7D, 0x01, 0x02, 0x04, 0x01, 0xF7]);
+            core::array::from_fn(|_| iter.next().unwrap())
+        };
+
+        assert_eq!(packets, [
+            [0x14, 0xF0, 0x7D, 0x01],
+            [0x14, 0x02, 0x04, 0x01],
+            [0x15, 0xF7, 0x00, 0x00],
+        ]);
+    }
+
+    #[test]
+    fn test_packet_cable() {
+        assert_eq!(packet_cable(&MidiMessage::ControlChange(0, 1, 2).to_usb_packet(3)), 3);
+        assert!(sysex_packets(4, &[0xF0, 0x7D, 0xF7]).all(|packet| packet_cable(&packet) == 4));
+    }
+
+    #[test]
+    fn test_channel_message_packet() {
+        assert_eq!(MidiMessage::ControlChange(3, 11, 64).to_usb_packet(0), [0x0B, 0xB3, 11, 64]);
+        assert_eq!(MidiMessage::PitchBend(0, 0x2000).to_usb_packet(0), [0x0E, 0xE0, 0x00, 0x40]);
+    }
+
+    #[test]
+    fn test_event_roundtrip() {
+        let events = [
+            MidiEvent::Message(MidiMessage::NoteOn(9, 36, 127)),
+            MidiEvent::Message(MidiMessage::ProgramChange(1, 5)),
+            MidiEvent::Realtime(0xF8),
+        ];
+        for event in events {
+            assert_eq!(MidiEvent::from_usb_packet(&event.to_usb_packet(2)), Some(event));
+        }
+        assert_eq!(MidiEvent::from_usb_packet(&[0x04, 0xF0, 0x7D, 0x01]), None);
+    }
+
+    #[test]
+    fn test_packet_bytes() {
+        assert_eq!(packet_bytes(&MidiMessage::ControlChange(3, 11, 64).to_usb_packet(1)), [0xB3, 11, 64]);
+        assert_eq!(packet_bytes(&MidiMessage::ProgramChange(0, 5).to_usb_packet(0)), [0xC0, 5]);
+        assert_eq!(packet_bytes(&MidiEvent::Realtime(0xF8).to_usb_packet(0)), [0xF8]);
+
+        let message = [0xF0, 0x7D, 0x01, 0x02, 0xF7];
+        let bytes: Vec<u8, 8> = sysex_packets(0, &message).flat_map(|packet| Vec::<u8, 3>::from_slice(packet_bytes(&packet)).unwrap()).collect();
+        assert_eq!(bytes, message);
+    }
+
+    #[test]
+    fn test_sysex_assembler() {
+        let message = [0xF0, 0x7D, 0x01, 0x02, 0x04, 0x01, 0x00, 0xF7];
+        let mut assembler = SysexAssembler::<16>::new();
+
+        let mut complete = None;
+        for packet in sysex_packets(0, &message) {
+            // interleaved channel messages do not disturb the reassembly
+            assert_eq!(assembler.push(&MidiMessage::ControlChange(0, 1, 2).to_usb_packet(0)), None);
+            complete = assembler.push(&packet).map(|data| data.len());
+        }
+        assert_eq!(complete, Some(message.len()));
+    }
+
+    #[test]
+    fn test_sysex_assembler_overflow() {
+        let mut assembler = SysexAssembler::<8>::new();
+        let large = [0xF0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0xF7];
+        assert!(sysex_packets(0, &large).all(|packet| assembler.push(&packet).is_none()));
+
+        // the next message fits again
+        let small = [0xF0, 0x7D, 0xF7];
+        assert_eq!(sysex_packets(0, &small).filter_map(|packet| assembler.push(&packet).map(<[u8]>::len)).next(), Some(3));
+    }
+}
@@ -0,0 +1,500 @@
+use heapless::Vec;
+
+use crate::codec::{Reader, Writer};
+use crate::config::{ChannelConfig, RoutingConfig};
+use crate::detect::{Detection, PedalKind};
+use crate::update::{ImageHeader, UpdateStatus, CHUNK_SIZE};
+
+/// Non-commercial manufacturer ID, used as long as the device has no registered ID.
+pub const MANUFACTURER_ID: u8 = 0x7D;
+
+pub const SYSEX_START: u8 = 0xF0;
+pub const SYSEX_END: u8 = 0xF7;
+
+/// Universal non-realtime SysEx ID, used for the identity request and reply.
+pub const UNIVERSAL_NON_REALTIME: u8 = 0x7E;
+
+/// Device family and model reported in the identity reply.
+pub const FAMILY: [u8; 2] = [0x01, 0x00];
+pub const MODEL: [u8; 2] = [0x01, 0x00];
+
+/// Maximum size of a complete protocol frame including the start and end bytes. The largest payload is a channel
+/// config with all curves and a full macro.
+pub const FRAME_SIZE: usize = 320;
+
+/// Maximum size of a binary payload before it is packed into 7-bit SysEx data.
+const PAYLOAD_SIZE: usize = (FRAME_SIZE - 4) / 8 * 7;
+
+// update messages have a fixed size, the size of the channel config is checked by the tests
+const _: () = assert!(4 + CHUNK_SIZE <= PAYLOAD_SIZE && ImageHeader::SIZE <= PAYLOAD_SIZE);
+
+pub type Frame = Vec<u8, FRAME_SIZE>;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    PedalDetection = 0x01,
+    ChannelConfig = 0x02,
+    Routing = 0x03,
+    GetConfig = 0x04,
+    Preset = 0x05,
+    UpdateBegin = 0x06,
+    UpdateChunk = 0x07,
+    UpdateFinish = 0x08,
+    UpdateAck = 0x09,
+    DumpState = 0x0A,
+    Program = 0x0B,
+}
+
+impl Command {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x01 => Some(Self::PedalDetection),
+            0x02 => Some(Self::ChannelConfig),
+            0x03 => Some(Self::Routing),
+            0x04 => Some(Self::GetConfig),
+            0x05 => Some(Self::Preset),
+            0x06 => Some(Self::UpdateBegin),
+            0x07 => Some(Self::UpdateChunk),
+            0x08 => Some(Self::UpdateFinish),
+            0x09 => Some(Self::UpdateAck),
+            0x0A => Some(Self::DumpState),
+            0x0B => Some(Self::Program),
+            _ => None,
+        }
+    }
+}
+
+/// Messages sent from the device to the desktop app.
+// there is no allocator on the device to box the config
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceMessage {
+    PedalDetection { channel: u8, detection: Detection },
+    ChannelConfig { channel: u8, config: ChannelConfig },
+    Routing(RoutingConfig),
+    /// Index of the active preset.
+    Preset(u8),
+    /// Answer of the bootloader to an update message, with the offset of the next expected chunk.
+    UpdateAck { offset: u32, status: UpdateStatus },
+    /// Current program of a MIDI channel navigated by switches, numbered across banks of 128.
+    Program { channel: u8, program: u16 },
+}
+
+impl DeviceMessage {
+    pub fn command(&self) -> Command {
+        match self {
+            Self::PedalDetection { .. } => Command::PedalDetection,
+            Self::ChannelConfig { .. } => Command::ChannelConfig,
+            Self::Routing(_) => Command::Routing,
+            Self::Preset(_) => Command::Preset,
+            Self::UpdateAck { .. } => Command::UpdateAck,
+            Self::Program { .. } => Command::Program,
+        }
+    }
+
+    pub fn encode(&self) -> Frame {
+        match self {
+            Self::PedalDetection { channel, detection } => raw_frame(self.command(), &[
+                *channel & 0x7f,
+                detection.kind.to_byte(),
+                detection.reversed as u8,
+            ]),
+            Self::ChannelConfig { channel, config } => packed_frame(self.command(), |writer| {
+                writer.u8(*channel);
+                config.encode(writer);
+            }),
+            Self::Routing(routing) => packed_frame(self.command(), |writer| routing.encode(writer)),
+            Self::Preset(index) => raw_frame(self.command(), &[*index & 0x7f]),
+            Self::UpdateAck { offset, status } => packed_frame(self.command(), |writer| {
+                writer.u32(*offset);
+                writer.u8(*status as u8);
+            }),
+            Self::Program { channel, program } => packed_frame(self.command(), |writer| {
+                writer.u8(*channel);
+                writer.u16(*program);
+            }),
+        }
+    }
+
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        let (command, payload) = split_frame(frame)?;
+
+        match command {
+            Command::PedalDetection => match payload {
+                [channel, kind, reversed] => Some(Self::PedalDetection {
+                    channel: *channel,
+                    detection: Detection {
+                        kind: PedalKind::from_byte(*kind)?,
+                        reversed: *reversed != 0,
+                    },
+                }),
+                _ => None,
+            },
+            Command::ChannelConfig => {
+                let (channel, config) = decode_channel_config(payload)?;
+                Some(Self::ChannelConfig { channel, config })
+            },
+            Command::Routing => decode_packed(payload, RoutingConfig::decode).map(Self::Routing),
+            Command::Preset => match payload {
+                [index] => Some(Self::Preset(*index)),
+                _ => None,
+            },
+            Command::UpdateAck => decode_packed(payload, |reader| {
+                Some(Self::UpdateAck { offset: reader.u32()?, status: UpdateStatus::from_byte(reader.u8()?)? })
+            }),
+            Command::Program => decode_packed(payload, |reader| {
+                Some(Self::Program { channel: reader.u8()?, program: reader.u16()? })
+            }),
+            Command::GetConfig | Command::UpdateBegin | Command::UpdateChunk | Command::UpdateFinish | Command::DumpState => None,
+        }
+    }
+}
+
+/// Messages sent from the desktop app to the device.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HostMessage {
+    /// Requests the complete configuration, answered with one message per setting.
+    GetConfig,
+    ChannelConfig { channel: u8, config: ChannelConfig },
+    Routing(RoutingConfig),
+    /// Activates the preset with the given index.
+    Preset(u8),
+    /// Starts a firmware update. The firmware answers by restarting into the bootloader, which acknowledges it.
+    UpdateBegin(ImageHeader),
+    /// Image data at `offset`, of which the first `len` bytes are used.
+    UpdateChunk { offset: u32, len: u8, data: [u8; CHUNK_SIZE] },
+    /// Asks the bootloader to check and install the received image.
+    UpdateFinish,
+    /// Asks for the current value of every channel, sent as its regular MIDI messages.
+    DumpState,
+}
+
+impl HostMessage {
+    pub fn command(&self) -> Command {
+        match self {
+            Self::GetConfig => Command::GetConfig,
+            Self::ChannelConfig { .. } => Command::ChannelConfig,
+            Self::Routing(_) => Command::Routing,
+            Self::Preset(_) => Command::Preset,
+            Self::UpdateBegin(_) => Command::UpdateBegin,
+            Self::UpdateChunk { .. } => Command::UpdateChunk,
+            Self::UpdateFinish => Command::UpdateFinish,
+            Self::DumpState => Command::DumpState,
+        }
+    }
+
+    pub fn encode(&self) -> Frame {
+        match self {
+            Self::GetConfig => raw_frame(self.command(), &[]),
+            Self::ChannelConfig { channel, config } => packed_frame(self.command(), |writer| {
+                writer.u8(*channel);
+                config.encode(writer);
+            }),
+            Self::Routing(routing) => packed_frame(self.command(), |writer| routing.encode(writer)),
+            Self::Preset(index) => raw_frame(self.command(), &[*index & 0x7f]),
+            Self::UpdateBegin(header) => packed_frame(self.command(), |writer| writer.bytes(&header.to_bytes())),
+            Self::UpdateChunk { offset, len, data } => packed_frame(self.command(), |writer| {
+                writer.u32(*offset);
+                writer.bytes(&data[..(*len as usize).min(CHUNK_SIZE)]);
+            }),
+            Self::UpdateFinish => raw_frame(self.command(), &[]),
+            Self::DumpState => raw_frame(self.command(), &[]),
+        }
+    }
+
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        let (command, payload) = split_frame(frame)?;
+
+        match command {
+            Command::GetConfig => payload.is_empty().then_some(Self::GetConfig),
+            Command::ChannelConfig => {
+                let (channel, config) = decode_channel_config(payload)?;
+                Some(Self::ChannelConfig { channel, config })
+            },
+            Command::Routing => decode_packed(payload, RoutingConfig::decode).map(Self::Routing),
+            Command::Preset => match payload {
+                [index] => Some(Self::Preset(*index)),
+                _ => None,
+            },
+            Command::UpdateBegin => decode_packed(payload, |reader| {
+                ImageHeader::from_bytes(reader.slice(ImageHeader::SIZE)?).map(Self::UpdateBegin)
+            }),
+            Command::UpdateChunk => decode_packed(payload, |reader| {
+                let offset = reader.u32()?;
+                let chunk = reader.slice(CHUNK_SIZE.min(reader.remaining()))?;
+                let mut data = [0; CHUNK_SIZE];
+                data[..chunk.len()].copy_from_slice(chunk);
+                Some(Self::UpdateChunk { offset, len: chunk.len() as u8, data })
+            }),
+            Command::UpdateFinish => payload.is_empty().then_some(Self::UpdateFinish),
+            Command::DumpState => payload.is_empty().then_some(Self::DumpState),
+            Command::PedalDetection | Command::UpdateAck | Command::Program => None,
+        }
+    }
+}
+
+/// Checks for a universal identity request addressed to any device.
+pub fn is_identity_request(frame: &[u8]) -> bool {
+    matches!(frame, [SYSEX_START, UNIVERSAL_NON_REALTIME, _, 0x06, 0x01, SYSEX_END])
+}
+
+/// Universal identity request addressed to any device.
+pub fn identity_request() -> Frame {
+    Frame::from_slice(&[SYSEX_START, UNIVERSAL_NON_REALTIME, 0x7F, 0x06, 0x01, SYSEX_END]).unwrap()
+}
+
+/// Length of the serial number, the 96-bit unique ID of the MCU in hexadecimal digits.
+pub const SERIAL_LEN: usize = 24;
+
+/// Firmware version and serial number, as reported in the identity reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Identity {
+    pub version: [u8; 4],
+    /// Hexadecimal ASCII digits, the same as the USB serial number.
+    pub serial: [u8; SERIAL_LEN],
+}
+
+impl Identity {
+    /// Set in the first version byte by the bootloader.
+    pub const BOOTLOADER: u8 = 0x40;
+
+    pub fn is_bootloader(&self) -> bool {
+        self.version[0] & Self::BOOTLOADER != 0
+    }
+
+    pub fn serial_str(&self) -> &str {
+        core::str::from_utf8(&self.serial).unwrap_or_default()
+    }
+
+    /// Universal identity reply. The serial number follows the standard fields.
+    pub fn encode(&self) -> Frame {
+        let mut frame = Frame::new();
+        let _ = frame.extend_from_slice(&[SYSEX_START, UNIVERSAL_NON_REALTIME, 0x7F, 0x06, 0x02, MANUFACTURER_ID]);
+        let _ = frame.extend_from_slice(&FAMILY);
+        let _ = frame.extend_from_slice(&MODEL);
+        let _ = frame.extend_from_slice(&self.version.map(|byte| byte & 0x7f));
+        let _ = frame.extend_from_slice(&self.serial.map(|byte| byte & 0x7f));
+        let _ = frame.push(SYSEX_END);
+        frame
+    }
+
+    pub fn decode(frame: &[u8]) -> Option<Self> {
+        match frame {
+            [SYSEX_START, UNIVERSAL_NON_REALTIME, _, 0x06, 0x02, MANUFACTURER_ID, rest @ .., SYSEX_END] => {
+                let (family, rest) = rest.split_at_checked(2)?;
+                let (model, rest) = rest.split_at_checked(2)?;
+                let (version, serial) = rest.split_at_checked(4)?;
+                (family == FAMILY && model == MODEL).then_some(Self {
+                    version: version.try_into().ok()?,
+                    serial: serial.try_into().ok()?,
+                })
+            },
+            _ => None,
+        }
+    }
+}
+
+/// USB `bcdDevice` for a firmware version, e.g. 0x0123 for version 1.2.3.
+pub fn device_release(version: [u8; 4]) -> u16 {
+    let bcd = |value: u8| value.min(9) as u16;
+    let [major, minor, patch, _] = version;
+    (bcd(major / 10) << 12) | (bcd(major % 10) << 8) | (bcd(minor) << 4) | bcd(patch)
+}
+
+/// Frame with a payload of 7-bit values.
+fn raw_frame(command: Command, payload: &[u8]) -> Frame {
+    let mut frame = Frame::new();
+    let _ = frame.extend_from_slice(&[SYSEX_START, MANUFACTURER_ID, command as u8]);
+    let _ = frame.extend_from_slice(payload);
+    let _ = frame.push(SYSEX_END);
+    frame
+}
+
+/// Frame with a binary payload, packed into 7-bit data.
+///
+/// The payload size covers the largest message, so this only fails if a config struct outgrew it. The frame is then
+/// left empty, which sends nothing instead of a valid looking frame with a truncated payload.
+fn packed_frame(command: Command, encode: impl FnOnce(&mut Writer)) -> Frame {
+    let mut payload = [0u8; PAYLOAD_SIZE];
+    let mut writer = Writer::new(&mut payload);
+    encode(&mut writer);
+    let Some(len) = writer.finish() else {
+        debug_assert!(false, "payload of {:?} exceeds {} bytes", command, PAYLOAD_SIZE);
+        #[cfg(feature = "defmt")]
+        defmt::error!("Payload of command {=u8} exceeds {=usize} bytes, dropping it", command as u8, PAYLOAD_SIZE);
+        return Frame::new();
+    };
+
+    // the payload size is derived from the frame size, so the packed data always fits
+    let mut frame = Frame::new();
+    let _ = frame.extend_from_slice(&[SYSEX_START, MANUFACTURER_ID, command as u8]);
+    for chunk in payload[..len].chunks(7) {
+        // each group of up to seven bytes is preceded by their most significant bits
+        let msbs = chunk.iter().enumerate().fold(0, |msbs, (i, byte)| msbs | (byte >> 7) << i);
+        let _ = frame.push(msbs);
+        for byte in chunk {
+            let _ = frame.push(byte & 0x7f);
+        }
+    }
+    let _ = frame.push(SYSEX_END);
+    frame
+}
+
+/// Unpacks 7-bit data into `payload` and returns the number of bytes.
+fn unpack(data: &[u8], payload: &mut [u8; PAYLOAD_SIZE]) -> Option<usize> {
+    let mut len = 0;
+
+    for group in data.chunks(8) {
+        let (msbs, bytes) = group.split_first()?;
+        for (i, byte) in bytes.iter().enumerate() {
+            *payload.get_mut(len)? = byte | ((msbs >> i) & 1) << 7;
+            len += 1;
+        }
+    }
+
+    Some(len)
+}
+
+/// Unpacks and decodes a payload that must be consumed completely.
+fn decode_packed<T>(data: &[u8], decode: impl FnOnce(&mut Reader) -> Option<T>) -> Option<T> {
+    let mut payload = [0u8; PAYLOAD_SIZE];
+    let len = unpack(data, &mut payload)?;
+
+    let mut reader = Reader::new(&payload[..len]);
+    let value = decode(&mut reader)?;
+    reader.is_empty().then_some(value)
+}
+
+fn decode_channel_config(data: &[u8]) -> Option<(u8, ChannelConfig)> {
+    decode_packed(data, |reader| Some((reader.u8()?, ChannelConfig::decode(reader)?)))
+}
+
+/// Checks the framing and manufacturer ID and splits a frame into its command and payload.
+fn split_frame(frame: &[u8]) -> Option<(Command, &[u8])> {
+    match frame {
+        [SYSEX_START, MANUFACTURER_ID, command, payload @ .., SYSEX_END] => Some((Command::from_byte(*command)?, payload)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{MAX_MACRO_STEPS, MAX_MAPPINGS, MacroSequence, MacroStep, Mapping, Takeover};
+    use crate::curve::CurvePreset;
+    use crate::midi::MidiMessage;
+
+    use super::*;
+
+    #[test]
+    fn test_pedal_detection_roundtrip() {
+        let message = DeviceMessage::PedalDetection {
+            channel: 2,
+            detection: Detection { kind: PedalKind::MomentarySwitch, reversed: true },
+        };
+        let frame = message.encode();
+
+        assert_eq!(frame.as_slice(), &[0xF0, 0x7D, 0x01, 0x02, 0x04, 0x01, 0xF7]);
+        assert_eq!(DeviceMessage::decode(&frame), Some(message));
+    }
+
+    #[test]
+    fn test_decode_foreign_sysex() {
+        assert_eq!(DeviceMessage::decode(&[0xF0, 0x43, 0x01, 0x02, 0xF7]), None);
+        assert_eq!(DeviceMessage::decode(&[0xF0, 0x7D, 0x01, 0x02]), None);
+    }
+
+    /// The largest config has curves with all points and a full macro.
+    fn largest_channel_config() -> ChannelConfig {
+        let curve = CurvePreset::SCurve.curve();
+        let mapping = Mapping::default().with_enabled(true).with_curve(curve);
+        (0..MAX_MAPPINGS).fold(ChannelConfig::from_index(1), |config, i| config.with_mapping(i, mapping))
+            .with_max_rate(1000)
+            .with_resend(false)
+            .with_takeover(Takeover::Scaling)
+            .with_curve(curve)
+            .with_ramp_curve(curve)
+            .with_sequence((0..MAX_MACRO_STEPS as u8).fold(MacroSequence::default(), |sequence, i| {
+                sequence.with_added(MacroStep::default().with_message(MidiMessage::PitchBend(15, 0x3FFF)).with_delay(i as u16))
+            }))
+            .with_program_range(0, 16383)
+            .with_label_str("Wah \u{e4}")
+    }
+
+    #[test]
+    fn test_largest_channel_config_fits() {
+        let mut payload = [0u8; PAYLOAD_SIZE];
+        let mut writer = Writer::new(&mut payload);
+        writer.u8(15);
+        largest_channel_config().encode(&mut writer);
+        assert!(writer.finish().is_some());
+    }
+
+    #[test]
+    fn test_channel_config_roundtrip() {
+        let config = largest_channel_config();
+        let message = HostMessage::ChannelConfig { channel: 1, config };
+        let frame = message.encode();
+
+        assert!(frame[1..frame.len() - 1].iter().all(|byte| *byte < 0x80));
+        assert_eq!(HostMessage::decode(&frame), Some(message));
+        assert_eq!(DeviceMessage::decode(&frame), Some(DeviceMessage::ChannelConfig { channel: 1, config }));
+    }
+
+    #[test]
+    fn test_program_roundtrip() {
+        let message = DeviceMessage::Program { channel: 15, program: 16383 };
+        assert_eq!(DeviceMessage::decode(&message.encode()), Some(message));
+    }
+
+    #[test]
+    fn test_routing_roundtrip() {
+        let routing = RoutingConfig::default()
+            .with_din_thru(true)
+            .with_preset_channel(Some(15))
+            .with_usb_cables(true)
+            .with_refresh_interval(30);
+        assert_eq!(RoutingConfig::channel_cable(2), 3);
+        let message = DeviceMessage::Routing(routing);
+        assert_eq!(DeviceMessage::decode(&message.encode()), Some(message));
+        assert_eq!(HostMessage::decode(&HostMessage::GetConfig.encode()), Some(HostMessage::GetConfig));
+        assert_eq!(HostMessage::decode(&HostMessage::DumpState.encode()), Some(HostMessage::DumpState));
+    }
+
+    #[test]
+    fn test_identity() {
+        assert!(is_identity_request(&identity_request()));
+        assert!(!is_identity_request(&[0xF0, 0x7E, 0x7F, 0x06, 0x02, 0xF7]));
+
+        let identity = Identity { version: [0, 3, 0, 0], serial: *b"0123456789ABCDEF01234567" };
+        let frame = identity.encode();
+        assert_eq!(&frame[..14], &[0xF0, 0x7E, 0x7F, 0x06, 0x02, 0x7D, 0x01, 0x00, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00]);
+        assert_eq!(Identity::decode(&frame), Some(identity));
+        assert_eq!(identity.serial_str(), "0123456789ABCDEF01234567");
+        assert!(!identity.is_bootloader());
+
+        assert_eq!(device_release([0, 3, 0, 0]), 0x0030);
+        assert_eq!(device_release([12, 4, 1, 0]), 0x1241);
+    }
+
+    #[test]
+    fn test_update_roundtrip() {
+        let header = ImageHeader { version: [0, 4, 0, 0], size: 70_000, crc: 0xDEAD_BEEF, signature: [0xA5; 64] };
+        let message = HostMessage::UpdateBegin(header);
+        assert_eq!(HostMessage::decode(&message.encode()), Some(message));
+
+        let data = core::array::from_fn(|i| i as u8);
+        let message = HostMessage::UpdateChunk { offset: 69_888, len: CHUNK_SIZE as u8, data };
+        let frame = message.encode();
+        assert!(frame.len() <= FRAME_SIZE);
+        assert_eq!(HostMessage::decode(&frame), Some(message));
+
+        // the last chunk is shorter, unused data is not sent
+        let message = HostMessage::UpdateChunk { offset: 69_888, len: 3, data: [0; CHUNK_SIZE] };
+        assert_eq!(HostMessage::decode(&message.encode()), Some(message));
+
+        let message = DeviceMessage::UpdateAck { offset: 192, status: UpdateStatus::WrongOffset };
+        assert_eq!(DeviceMessage::decode(&message.encode()), Some(message));
+    }
+}
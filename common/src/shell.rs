@@ -0,0 +1,573 @@
+use core::fmt::{self, Write};
+
+use crate::config::{
+    ActionKind, ChannelConfig, Destination, Gesture, HidOutput, InputMode, MAX_MACRO_STEPS, MAX_MAPPINGS, MacroStep,
+    MessageType, NUM_PRESETS, OutputPath, ProgramStep, Takeover,
+};
+use crate::curve::{Breakpoint, Curve, Interpolation, MAX_POINTS};
+use crate::hid::{KeyCombo, MediaKey};
+
+/// Collects typed characters into lines, handling backspace and CR, LF or CRLF line endings.
+#[derive(Debug, Clone)]
+pub struct LineBuffer<const N: usize> {
+    line: heapless::String<N>,
+    overflow: bool,
+    complete: bool,
+    after_cr: bool,
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> Self {
+        Self { line: heapless::String::new(), overflow: false, complete: false, after_cr: false }
+    }
+
+    /// Adds a typed byte and returns the line once it is complete. Lines that did not fit are returned empty, so
+    /// a truncated command is never run.
+    pub fn push(&mut self, byte: u8) -> Option<&str> {
+        if self.complete {
+            self.line.clear();
+            self.overflow = false;
+            self.complete = false;
+        }
+
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+
+        match byte {
+            b'\n' if after_cr => None,
+            b'\r' | b'\n' => {
+                self.complete = true;
+                if self.overflow {
+                    self.line.clear();
+                }
+                Some(self.line.trim())
+            },
+            0x08 | 0x7F => {
+                self.line.pop();
+                None
+            },
+            0x20..0x7F => {
+                self.overflow |= self.line.push(byte as char).is_err();
+                None
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Reasons a command line is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ShellError {
+    UnknownCommand,
+    UnknownParameter,
+    MissingArgument,
+    InvalidValue,
+    InvalidChannel,
+}
+
+impl ShellError {
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::UnknownCommand => "unknown command, try 'help'",
+            Self::UnknownParameter => "unknown parameter",
+            Self::MissingArgument => "missing argument",
+            Self::InvalidValue => "invalid value",
+            Self::InvalidChannel => "invalid channel",
+        }
+    }
+}
+
+pub const HELP: &str = "\
+help                          this text
+version                       firmware version and serial number
+uptime                        time since power on
+config                        dump the active config as set commands
+set <channel> <param> <value> change a parameter of a channel (1-based)
+stream on|off                 stream raw ADC values
+calibrate <channel>           record the input range of a channel
+calibrate done                apply the recorded range
+params: mode released pressed min-in max-in min-out max-out rate path dest resend takeover label
+        m<n>.enabled m<n>.channel m<n>.type m<n>.number m<n>.min m<n>.max
+        curve m<n>.curve ramp-curve <linear|smooth> <in>:<out> ... (2 to 8 points)
+        hid key <modifiers> <usage> media volume-steps
+        double-tap long-press repeat (ms)
+        <g>.action <g>.channel <g>.type <g>.number <g>.value <g>.preset (g: tap double long hold)
+        steps, s<n> <type> <channel> <number> <value> <delay ms> (macro)
+        program-step program-channel program program-range <first> <last> wrap
+        ramp-start ramp-end ramp-time (ms) ramp-reverse
+";
+
+/// A parsed command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command<'a> {
+    Help,
+    Version,
+    Uptime,
+    Config,
+    /// Zero based channel, parameter name and value.
+    Set { channel: usize, param: &'a str, value: &'a str },
+    Stream(bool),
+    /// Starts recording the input range of a zero based channel.
+    Calibrate(usize),
+    CalibrateDone,
+}
+
+impl<'a> Command<'a> {
+    /// Parses a line, `Ok(None)` for an empty line.
+    pub fn parse(line: &'a str, num_channels: usize) -> Result<Option<Self>, ShellError> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(None);
+        };
+
+        let command = match command {
+            "help" | "?" => Self::Help,
+            "version" => Self::Version,
+            "uptime" => Self::Uptime,
+            "config" => Self::Config,
+            "set" => {
+                let channel = parse_channel(words.next(), num_channels)?;
+                let param = words.next().ok_or(ShellError::MissingArgument)?;
+                // the value is the rest of the line, so labels may contain spaces
+                let end = param.as_ptr() as usize - line.as_ptr() as usize + param.len();
+                let value = Some(line[end..].trim()).filter(|value| !value.is_empty()).ok_or(ShellError::MissingArgument)?;
+                Self::Set { channel, param, value }
+            },
+            "stream" => Self::Stream(parse_switch(words.next().ok_or(ShellError::MissingArgument)?)?),
+            "calibrate" => match words.next() {
+                Some("done") => Self::CalibrateDone,
+                channel => Self::Calibrate(parse_channel(channel, num_channels)?),
+            },
+            _ => return Err(ShellError::UnknownCommand),
+        };
+        Ok(Some(command))
+    }
+}
+
+fn parse_channel(word: Option<&str>, num_channels: usize) -> Result<usize, ShellError> {
+    let channel: usize = word.ok_or(ShellError::MissingArgument)?.parse().map_err(|_| ShellError::InvalidChannel)?;
+    (1..=num_channels).contains(&channel).then(|| channel - 1).ok_or(ShellError::InvalidChannel)
+}
+
+fn parse_switch(word: &str) -> Result<bool, ShellError> {
+    match word {
+        "on" | "1" => Ok(true),
+        "off" | "0" => Ok(false),
+        _ => Err(ShellError::InvalidValue),
+    }
+}
+
+fn parse_number<T: core::str::FromStr + PartialOrd>(value: &str, minimum: T, maximum: T) -> Result<T, ShellError> {
+    value
+        .parse()
+        .ok()
+        .filter(|value| *value >= minimum && *value <= maximum)
+        .ok_or(ShellError::InvalidValue)
+}
+
+const MODES: &[(&str, InputMode)] = &[
+    ("continuous", InputMode::Continuous),
+    ("switch", InputMode::Switch),
+    ("momentary-as-toggle", InputMode::MomentaryAsToggle),
+    ("toggle-as-momentary", InputMode::ToggleAsMomentary),
+    ("program", InputMode::ProgramNavigation),
+    ("ramp", InputMode::Ramp),
+];
+
+const PATHS: &[(&str, OutputPath)] = &[
+    ("usb", OutputPath::Usb),
+    ("din", OutputPath::Din),
+    ("both", OutputPath::Both),
+];
+
+const DESTINATIONS: &[(&str, Destination)] = &[
+    ("midi", Destination::Midi),
+    ("hid", Destination::Hid),
+    ("both", Destination::Both),
+];
+
+const TAKEOVERS: &[(&str, Takeover)] = &[
+    ("jump", Takeover::Jump),
+    ("pickup", Takeover::Pickup),
+    ("scaling", Takeover::Scaling),
+];
+
+const PROGRAM_STEPS: &[(&str, ProgramStep)] = &[
+    ("next", ProgramStep::Increment),
+    ("previous", ProgramStep::Decrement),
+    ("select", ProgramStep::Direct),
+];
+
+const GESTURES: &[(&str, Gesture)] = &[
+    ("tap", Gesture::Tap),
+    ("double", Gesture::DoubleTap),
+    ("long", Gesture::LongPress),
+    ("hold", Gesture::Repeat),
+];
+
+const ACTIONS: &[(&str, ActionKind)] = &[
+    ("none", ActionKind::None),
+    ("message", ActionKind::Message),
+    ("preset", ActionKind::Preset),
+    ("toggle", ActionKind::Toggle),
+    ("macro", ActionKind::Macro),
+];
+
+const INTERPOLATIONS: &[(&str, Interpolation)] = &[
+    ("linear", Interpolation::Linear),
+    ("smooth", Interpolation::Smooth),
+];
+
+const HID_OUTPUTS: &[(&str, HidOutput)] = &[
+    ("gamepad", HidOutput::Gamepad),
+    ("key", HidOutput::Key),
+    ("media", HidOutput::Media),
+    ("volume", HidOutput::Volume),
+];
+
+const MEDIA_KEYS: &[(&str, MediaKey)] = &[
+    ("play", MediaKey::PlayPause),
+    ("next", MediaKey::NextTrack),
+    ("previous", MediaKey::PreviousTrack),
+    ("mute", MediaKey::Mute),
+    ("up", MediaKey::VolumeUp),
+    ("down", MediaKey::VolumeDown),
+];
+
+const MESSAGE_TYPES: &[(&str, MessageType)] = &[
+    ("cc", MessageType::ControlChange),
+    ("pressure", MessageType::ChannelPressure),
+    ("bend", MessageType::PitchBend),
+    ("poly", MessageType::PolyAftertouch),
+    ("note", MessageType::Note),
+    ("program", MessageType::ProgramChange),
+];
+
+fn lookup<T: Copy>(table: &[(&str, T)], value: &str) -> Result<T, ShellError> {
+    table.iter().find(|(name, _)| *name == value).map(|(_, value)| *value).ok_or(ShellError::InvalidValue)
+}
+
+fn name_of<T: PartialEq>(table: &[(&'static str, T)], value: &T) -> &'static str {
+    table.iter().find(|(_, v)| v == value).map_or("?", |(name, _)| name)
+}
+
+/// Applies a `set` command to a channel config.
+pub fn set(config: ChannelConfig, param: &str, value: &str) -> Result<ChannelConfig, ShellError> {
+    if let Some((index, param)) = param.strip_prefix('m').and_then(|param| param.split_once('.')) {
+        let index: usize = index.parse().map_err(|_| ShellError::UnknownParameter)?;
+        let index = index.checked_sub(1).filter(|index| *index < MAX_MAPPINGS).ok_or(ShellError::UnknownParameter)?;
+        let mapping = config.mappings[index];
+
+        let mapping = match param {
+            "enabled" => mapping.with_enabled(parse_switch(value)?),
+            "channel" => mapping.with_channel(parse_number(value, 1u8, 16)? - 1),
+            "type" => mapping.with_message_type(lookup(MESSAGE_TYPES, value)?),
+            "number" => mapping.with_number(parse_number(value, 0, 127)?),
+            "min" => mapping.with_minimum_output(parse_number(value, 0, 127)?),
+            "max" => mapping.with_maximum_output(parse_number(value, 0, 127)?),
+            "curve" => mapping.with_curve(parse_curve(value)?),
+            _ => return Err(ShellError::UnknownParameter),
+        };
+        return Ok(config.with_mapping(index, mapping));
+    }
+
+    if let Some(index) = param.strip_prefix('s').and_then(|index| index.parse::<usize>().ok()) {
+        let index = index.checked_sub(1).filter(|index| *index < config.sequence.steps().len());
+        let index = index.ok_or(ShellError::UnknownParameter)?;
+        let step = parse_step(value)?;
+        return Ok(config.with_sequence(config.sequence.with_step(index, step)));
+    }
+
+    if let Some((gesture, param)) = param.split_once('.') {
+        let gesture = lookup(GESTURES, gesture).map_err(|_| ShellError::UnknownParameter)?;
+        let action = *config.gestures.action(gesture);
+
+        let action = match param {
+            "action" => action.with_kind(lookup(ACTIONS, value)?),
+            "channel" => action.with_channel(parse_number(value, 1u8, 16)? - 1),
+            "type" => action.with_message_type(lookup(MESSAGE_TYPES, value)?),
+            "number" => action.with_number(parse_number(value, 0, 127)?),
+            "value" => action.with_value(parse_number(value, 0, 127)?),
+            "preset" => action.with_value(parse_number(value, 1, NUM_PRESETS as u8)? - 1),
+            _ => return Err(ShellError::UnknownParameter),
+        };
+        return Ok(config.with_gesture_action(gesture, action));
+    }
+
+    Ok(match param {
+        "mode" => config.with_input_mode(lookup(MODES, value)?),
+        "released" => config.with_released_value(parse_number(value, 0, 127)?),
+        "pressed" => config.with_pressed_value(parse_number(value, 0, 127)?),
+        "min-in" => config.with_minimum_input(parse_number(value, 0, 127)?),
+        "max-in" => config.with_maximum_input(parse_number(value, 0, 127)?),
+        "min-out" => config.with_minimum_output(parse_number(value, 0, 127)?),
+        "max-out" => config.with_maximum_output(parse_number(value, 0, 127)?),
+        "curve" => config.with_curve(parse_curve(value)?),
+        "rate" => config.with_max_rate(parse_number(value, 0, u16::MAX)?),
+        "path" => config.with_output_path(lookup(PATHS, value)?),
+        "dest" => config.with_destination(lookup(DESTINATIONS, value)?),
+        "hid" => config.with_hid_output(lookup(HID_OUTPUTS, value)?),
+        "key" => {
+            let (modifiers, key) = value.split_once(' ').ok_or(ShellError::MissingArgument)?;
+            config.with_hid_key(KeyCombo::new(parse_number(modifiers, 0, u8::MAX)?, parse_number(key.trim(), 0, u8::MAX)?))
+        },
+        "media" => config.with_media_key(lookup(MEDIA_KEYS, value)?),
+        "volume-steps" => config.with_volume_steps(parse_number(value, 1, u8::MAX)?),
+        "resend" => config.with_resend(parse_switch(value)?),
+        "takeover" => config.with_takeover(lookup(TAKEOVERS, value)?),
+        "double-tap" => config.with_double_tap_time(parse_number(value, 0, u16::MAX)?),
+        "long-press" => config.with_long_press_time(parse_number(value, 0, u16::MAX)?),
+        "repeat" => config.with_repeat_interval(parse_number(value, 1, u16::MAX)?),
+        "steps" => config.with_sequence(config.sequence.with_len(parse_number(value, 0, MAX_MACRO_STEPS)?)),
+        "program-step" => config.with_program_step(lookup(PROGRAM_STEPS, value)?),
+        "program-channel" => config.with_program_channel(parse_number(value, 1u8, 16)? - 1),
+        "program" => config.with_program(parse_number(value, 0, u16::MAX)?),
+        "program-range" => {
+            let (minimum, maximum) = value.split_once(' ').ok_or(ShellError::MissingArgument)?;
+            config.with_program_range(parse_number(minimum, 0, u16::MAX)?, parse_number(maximum.trim(), 0, u16::MAX)?)
+        },
+        "wrap" => config.with_program_wrap(parse_switch(value)?),
+        "ramp-start" => config.with_ramp_start(parse_number(value, 0, 127)?),
+        "ramp-end" => config.with_ramp_end(parse_number(value, 0, 127)?),
+        "ramp-time" => config.with_ramp_duration(parse_number(value, 0, u16::MAX)?),
+        "ramp-reverse" => config.with_ramp_reverse(parse_switch(value)?),
+        "ramp-curve" => config.with_ramp_curve(parse_curve(value)?),
+        "label" => config.with_label_str(value),
+        _ => return Err(ShellError::UnknownParameter),
+    })
+}
+
+/// Parses a macro step: message type, MIDI channel, number, value and delay.
+fn parse_step(value: &str) -> Result<MacroStep, ShellError> {
+    let mut words = value.split_whitespace();
+    let mut next = || words.next().ok_or(ShellError::MissingArgument);
+
+    let message_type = lookup(MESSAGE_TYPES, next()?)?;
+    let channel = parse_number(next()?, 1u8, 16)? - 1;
+    let number = parse_number(next()?, 0, 127)?;
+    let value = parse_number(next()?, 0, 127)?;
+    let delay = parse_number(next()?, 0, u16::MAX)?;
+    Ok(MacroStep::default().with_message(message_type.message(channel, number, value)).with_delay(delay))
+}
+
+/// Parses a curve: the interpolation followed by the breakpoints as `input:output` with ascending inputs. The first
+/// and last breakpoint are always at the inputs 0 and 127.
+fn parse_curve(value: &str) -> Result<Curve, ShellError> {
+    let mut words = value.split_whitespace();
+    let interpolation = lookup(INTERPOLATIONS, words.next().ok_or(ShellError::MissingArgument)?)?;
+
+    let mut points = heapless::Vec::<Breakpoint, MAX_POINTS>::new();
+    for word in words {
+        let (input, output) = word.split_once(':').ok_or(ShellError::InvalidValue)?;
+        let point = Breakpoint::new(parse_number(input, 0, 127)?, parse_number(output, 0, 127)?);
+        if points.last().is_some_and(|last| last.input >= point.input) {
+            return Err(ShellError::InvalidValue);
+        }
+        points.push(point).map_err(|_| ShellError::InvalidValue)?;
+    }
+
+    match points.as_slice() {
+        [] | [_] => Err(ShellError::MissingArgument),
+        [first, .., last] if first.input != 0 || last.input != 127 => Err(ShellError::InvalidValue),
+        points => Ok(Curve::from_points(points, interpolation)),
+    }
+}
+
+/// Writes a curve in the format read by [`parse_curve`].
+fn write_curve(out: &mut impl Write, curve: &Curve) -> fmt::Result {
+    write!(out, "{}", name_of(INTERPOLATIONS, &curve.interpolation))?;
+    for point in curve.points() {
+        write!(out, " {}:{}", point.input, point.output)?;
+    }
+    writeln!(out)
+}
+
+/// Writes a channel config as `set` commands, so a dump can be pasted back into the shell.
+pub fn write_channel(out: &mut impl Write, channel: usize, config: &ChannelConfig) -> fmt::Result {
+    let n = channel + 1;
+    let input = &config.input;
+
+    if !config.label_str().is_empty() {
+        writeln!(out, "set {n} label {}", config.label_str())?;
+    }
+    writeln!(out, "set {n} mode {}", name_of(MODES, &input.mode))?;
+    writeln!(out, "set {n} released {}", input.switch.released_value)?;
+    writeln!(out, "set {n} pressed {}", input.switch.pressed_value)?;
+    writeln!(out, "set {n} min-in {}", input.continuous.minimum_input)?;
+    writeln!(out, "set {n} max-in {}", input.continuous.maximum_input)?;
+    writeln!(out, "set {n} min-out {}", input.continuous.minimum_output)?;
+    writeln!(out, "set {n} max-out {}", input.continuous.maximum_output)?;
+    write!(out, "set {n} curve ")?;
+    write_curve(out, &input.continuous.curve)?;
+    writeln!(out, "set {n} rate {}", config.max_rate)?;
+    writeln!(out, "set {n} path {}", name_of(PATHS, &config.output_path))?;
+    writeln!(out, "set {n} dest {}", name_of(DESTINATIONS, &config.destination))?;
+    writeln!(out, "set {n} hid {}", name_of(HID_OUTPUTS, &config.hid.output))?;
+    writeln!(out, "set {n} key {} {}", config.hid.key.modifiers, config.hid.key.key)?;
+    writeln!(out, "set {n} media {}", name_of(MEDIA_KEYS, &config.hid.media))?;
+    writeln!(out, "set {n} volume-steps {}", config.hid.volume_steps)?;
+    writeln!(out, "set {n} resend {}", if config.resend { "on" } else { "off" })?;
+    writeln!(out, "set {n} takeover {}", name_of(TAKEOVERS, &config.takeover))?;
+
+    // disabled mappings are written as well, so they keep their settings when they are enabled again
+    for (i, mapping) in config.mappings.iter().enumerate() {
+        let m = i + 1;
+        writeln!(out, "set {n} m{m}.enabled {}", if mapping.enabled { "on" } else { "off" })?;
+        writeln!(out, "set {n} m{m}.channel {}", mapping.channel + 1)?;
+        writeln!(out, "set {n} m{m}.type {}", name_of(MESSAGE_TYPES, &mapping.message_type))?;
+        writeln!(out, "set {n} m{m}.number {}", mapping.number)?;
+        writeln!(out, "set {n} m{m}.min {}", mapping.minimum_output)?;
+        writeln!(out, "set {n} m{m}.max {}", mapping.maximum_output)?;
+        write!(out, "set {n} m{m}.curve ")?;
+        write_curve(out, &mapping.curve)?;
+    }
+
+    let gestures = &config.gestures;
+    writeln!(out, "set {n} double-tap {}", gestures.double_tap_time)?;
+    writeln!(out, "set {n} long-press {}", gestures.long_press_time)?;
+    writeln!(out, "set {n} repeat {}", gestures.repeat_interval)?;
+    for (g, gesture) in GESTURES {
+        let action = gestures.action(*gesture);
+        writeln!(out, "set {n} {g}.action {}", name_of(ACTIONS, &action.kind))?;
+        match action.kind {
+            ActionKind::Message => {
+                writeln!(out, "set {n} {g}.channel {}", action.channel + 1)?;
+                writeln!(out, "set {n} {g}.type {}", name_of(MESSAGE_TYPES, &action.message_type))?;
+                writeln!(out, "set {n} {g}.number {}", action.number)?;
+                writeln!(out, "set {n} {g}.value {}", action.value)?;
+            },
+            ActionKind::Preset => writeln!(out, "set {n} {g}.preset {}", action.value + 1)?,
+            ActionKind::None | ActionKind::Toggle | ActionKind::Macro => {},
+        }
+    }
+
+    writeln!(out, "set {n} steps {}", config.sequence.steps().len())?;
+    for (i, step) in config.sequence.steps().iter().enumerate() {
+        let (message_type, channel, number, value) = MessageType::split(&step.message);
+        let message_type = name_of(MESSAGE_TYPES, &message_type);
+        writeln!(out, "set {n} s{} {message_type} {} {number} {value} {}", i + 1, channel + 1, step.delay)?;
+    }
+
+    let program = &config.program;
+    writeln!(out, "set {n} program-step {}", name_of(PROGRAM_STEPS, &program.step))?;
+    writeln!(out, "set {n} program-channel {}", program.channel + 1)?;
+    writeln!(out, "set {n} program {}", program.program)?;
+    writeln!(out, "set {n} program-range {} {}", program.minimum, program.maximum)?;
+    writeln!(out, "set {n} wrap {}", if program.wrap { "on" } else { "off" })?;
+
+    let ramp = &config.ramp;
+    writeln!(out, "set {n} ramp-start {}", ramp.start)?;
+    writeln!(out, "set {n} ramp-end {}", ramp.end)?;
+    writeln!(out, "set {n} ramp-time {}", ramp.duration)?;
+    writeln!(out, "set {n} ramp-reverse {}", if ramp.reverse { "on" } else { "off" })?;
+    write!(out, "set {n} ramp-curve ")?;
+    write_curve(out, &ramp.curve)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_buffer() {
+        let mut buffer = LineBuffer::<8>::new();
+        for byte in b"setx\x7f 1" {
+            assert_eq!(buffer.push(*byte), None);
+        }
+        assert_eq!(buffer.push(b'\r'), Some("set 1"));
+        assert_eq!(buffer.push(b'\n'), None);
+        assert_eq!(buffer.push(b'\n'), Some(""));
+
+        // too long lines are dropped
+        for byte in b"calibrate 1" {
+            buffer.push(*byte);
+        }
+        assert_eq!(buffer.push(b'\n'), Some(""));
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::parse("  ", 4), Ok(None));
+        assert_eq!(Command::parse("stream on", 4), Ok(Some(Command::Stream(true))));
+        assert_eq!(Command::parse("calibrate 4", 4), Ok(Some(Command::Calibrate(3))));
+        assert_eq!(Command::parse("calibrate done", 4), Ok(Some(Command::CalibrateDone)));
+        assert_eq!(
+            Command::parse("set 2 label Wah Wah", 4),
+            Ok(Some(Command::Set { channel: 1, param: "label", value: "Wah Wah" })),
+        );
+        assert_eq!(Command::parse("set 5 rate 100", 4), Err(ShellError::InvalidChannel));
+        assert_eq!(Command::parse("set 1 rate", 4), Err(ShellError::MissingArgument));
+        assert_eq!(Command::parse("reboot", 4), Err(ShellError::UnknownCommand));
+    }
+
+    #[test]
+    fn test_dump_roundtrip() {
+        let config = set(ChannelConfig::from_index(1), "mode", "switch")
+            .and_then(|config| set(config, "m2.enabled", "on"))
+            .and_then(|config| set(config, "m2.type", "note"))
+            .and_then(|config| set(config, "m2.channel", "10"))
+            .and_then(|config| set(config, "rate", "250"))
+            .and_then(|config| set(config, "label", "Foot Switch"))
+            .and_then(|config| set(config, "resend", "off"))
+            .and_then(|config| set(config, "takeover", "pickup"))
+            .and_then(|config| set(config, "long-press", "800"))
+            .and_then(|config| set(config, "tap.action", "toggle"))
+            .and_then(|config| set(config, "double.action", "message"))
+            .and_then(|config| set(config, "double.type", "note"))
+            .and_then(|config| set(config, "double.number", "36"))
+            .and_then(|config| set(config, "long.action", "preset"))
+            .and_then(|config| set(config, "long.preset", "3"))
+            .and_then(|config| set(config, "hold.action", "macro"))
+            .and_then(|config| set(config, "steps", "2"))
+            .and_then(|config| set(config, "s1", "cc 1 0 5 0"))
+            .and_then(|config| set(config, "s2", "program 1 0 12 100"))
+            .and_then(|config| set(config, "program-step", "previous"))
+            .and_then(|config| set(config, "program-channel", "3"))
+            .and_then(|config| set(config, "program-range", "10 300"))
+            .and_then(|config| set(config, "wrap", "off"))
+            .and_then(|config| set(config, "ramp-start", "20"))
+            .and_then(|config| set(config, "ramp-time", "3000"))
+            .and_then(|config| set(config, "ramp-reverse", "off"))
+            .and_then(|config| set(config, "curve", "smooth 0:0 18:45 36:70 54:87 73:100 91:111 109:119 127:127"))
+            .and_then(|config| set(config, "m2.curve", "linear 0:127 127:0"))
+            .and_then(|config| set(config, "m3.number", "74"))
+            .and_then(|config| set(config, "ramp-curve", "smooth 0:0 64:20 127:127"))
+            .and_then(|config| set(config, "hid", "key"))
+            .and_then(|config| set(config, "key", "3 29"))
+            .and_then(|config| set(config, "media", "next"))
+            .and_then(|config| set(config, "volume-steps", "20"))
+            .unwrap();
+        assert_eq!(config.mappings[1].channel, 9);
+        assert_eq!(config.gestures.action(Gesture::LongPress).value, 2);
+        assert_eq!(set(config, "m5.min", "0"), Err(ShellError::UnknownParameter));
+        assert_eq!(set(config, "swipe.action", "toggle"), Err(ShellError::UnknownParameter));
+        assert_eq!(set(config, "s3", "cc 1 0 5 0"), Err(ShellError::UnknownParameter));
+        assert_eq!(set(config, "s1", "cc 1 0 5"), Err(ShellError::MissingArgument));
+        assert_eq!(set(config, "pressed", "128"), Err(ShellError::InvalidValue));
+        assert_eq!(set(config, "program-range", "10"), Err(ShellError::MissingArgument));
+        assert_eq!(set(config, "curve", "smooth 0:0"), Err(ShellError::MissingArgument));
+        assert_eq!(set(config, "curve", "smooth 0:0 64:10 64:20 127:127"), Err(ShellError::InvalidValue));
+        assert_eq!(set(config, "curve", "linear 10:0 127:127"), Err(ShellError::InvalidValue));
+
+        let mut dump = heapless::String::<2048>::new();
+        write_channel(&mut dump, 0, &config).unwrap();
+
+        let mut replayed = ChannelConfig::default();
+        for line in dump.lines() {
+            let Ok(Some(Command::Set { channel: 0, param, value })) = Command::parse(line, 4) else {
+                panic!("unexpected line {line}");
+            };
+            replayed = set(replayed, param, value).unwrap();
+        }
+        assert_eq!(replayed, config);
+        assert_eq!(replayed.label_str(), "Foot Switch");
+    }
+}
@@ -0,0 +1,426 @@
+//! Firmware updates over SysEx.
+//!
+//! A release file is an [`ImageHeader`] followed by the raw firmware image. The desktop app sends the header, then
+//! the image in chunks that are acknowledged one by one, and finally asks the bootloader to check the image. The
+//! bootloader only marks an image as installed after its CRC and Ed25519 signature checked out, and it never starts
+//! an image that is not installed, so an interrupted or rejected update leaves the device waiting in the bootloader
+//! for the next attempt.
+
+use ed25519_dalek::{Signature, VerifyingKey};
+
+use crate::protocol::HostMessage;
+
+/// Flash layout of the STM32G431CB, shared by the bootloader and the firmware. Offsets are relative to the start of
+/// the flash.
+pub mod layout {
+    pub const FLASH_BASE: u32 = 0x0800_0000;
+    pub const FLASH_SIZE: u32 = 128 * 1024;
+    pub const PAGE_SIZE: u32 = 2048;
+    /// Flash is written in double words.
+    pub const WRITE_SIZE: usize = 8;
+
+    pub const BOOTLOADER_SIZE: u32 = 40 * 1024;
+    /// Page holding the header of the installed image. It is erased first and written last during an update.
+    pub const HEADER_OFFSET: u32 = BOOTLOADER_SIZE;
+    pub const IMAGE_OFFSET: u32 = HEADER_OFFSET + PAGE_SIZE;
+    pub const IMAGE_CAPACITY: u32 = FLASH_SIZE - IMAGE_OFFSET;
+
+    /// RAM word at the end of the RAM, outside of both programs, that survives a reset. The firmware writes
+    /// [`UPDATE_REQUEST`] to it and resets to start an update.
+    pub const UPDATE_REQUEST_ADDRESS: u32 = 0x2000_7FFC;
+    pub const UPDATE_REQUEST: u32 = 0xB007_10AD;
+}
+
+/// Image bytes per chunk message. A multiple of the flash write size, so chunks can be written as they arrive.
+pub const CHUNK_SIZE: usize = 192;
+
+const _: () = assert!(CHUNK_SIZE.is_multiple_of(layout::WRITE_SIZE));
+
+/// CRC-32 (IEEE 802.3) of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg()))
+    })
+}
+
+/// Describes a signed firmware image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ImageHeader {
+    pub version: [u8; 4],
+    pub size: u32,
+    pub crc: u32,
+    /// Ed25519 signature over the other header fields followed by the image.
+    pub signature: [u8; 64],
+}
+
+impl ImageHeader {
+    pub const MAGIC: [u8; 4] = *b"EXPR";
+    pub const SIZE: usize = 80;
+    const SIGNED_SIZE: usize = 16;
+
+    fn signed_fields(&self) -> [u8; Self::SIGNED_SIZE] {
+        let mut bytes = [0; Self::SIGNED_SIZE];
+        bytes[..4].copy_from_slice(&Self::MAGIC);
+        bytes[4..8].copy_from_slice(&self.version);
+        bytes[8..12].copy_from_slice(&self.size.to_le_bytes());
+        bytes[12..16].copy_from_slice(&self.crc.to_le_bytes());
+        bytes
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..Self::SIGNED_SIZE].copy_from_slice(&self.signed_fields());
+        bytes[Self::SIGNED_SIZE..].copy_from_slice(&self.signature);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: &[u8; Self::SIZE] = bytes.get(..Self::SIZE)?.try_into().ok()?;
+        (bytes[..4] == Self::MAGIC).then(|| Self {
+            version: bytes[4..8].try_into().unwrap(),
+            size: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            crc: u32::from_le_bytes(bytes[12..16].try_into().unwrap()),
+            signature: bytes[16..].try_into().unwrap(),
+        })
+    }
+
+    /// Quick check of an installed image, done on every start.
+    pub fn check_crc(&self, image: &[u8]) -> bool {
+        image.len() == self.size as usize && crc32(image) == self.crc
+    }
+
+    /// Full check of a received image against the header and the signing key.
+    pub fn check(&self, image: &[u8], public_key: &[u8; 32]) -> Result<(), UpdateStatus> {
+        if !self.check_crc(image) {
+            return Err(UpdateStatus::BadCrc);
+        }
+        // the signed fields and the image are not next to each other in flash, so they are streamed into the check
+        let public_key = VerifyingKey::from_bytes(public_key)
+            .ok()
+            .filter(|public_key| !public_key.is_weak())
+            .ok_or(UpdateStatus::BadSignature)?;
+        let mut verifier = public_key
+            .verify_stream(&Signature::from_bytes(&self.signature))
+            .map_err(|_| UpdateStatus::BadSignature)?;
+        verifier.update(self.signed_fields());
+        verifier.update(image);
+        verifier.finalize_and_verify().map_err(|_| UpdateStatus::BadSignature)
+    }
+}
+
+/// Splits a release file into its header and image.
+pub fn split_image(file: &[u8]) -> Option<(ImageHeader, &[u8])> {
+    let header = ImageHeader::from_bytes(file)?;
+    let image = &file[ImageHeader::SIZE..];
+    (image.len() == header.size as usize).then_some((header, image))
+}
+
+/// Answer of the bootloader to each update message.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum UpdateStatus {
+    /// Ready for the chunk at the acknowledged offset.
+    Ok = 0,
+    /// The image was checked and installed, the device restarts into it.
+    Done = 1,
+    /// A chunk did not continue the image, the acknowledged offset is the one expected instead.
+    WrongOffset = 2,
+    TooLarge = 3,
+    NotStarted = 4,
+    Incomplete = 5,
+    BadCrc = 6,
+    BadSignature = 7,
+    FlashError = 8,
+    /// A chunk before the last one did not end on a flash write boundary.
+    Misaligned = 9,
+}
+
+impl UpdateStatus {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Ok),
+            1 => Some(Self::Done),
+            2 => Some(Self::WrongOffset),
+            3 => Some(Self::TooLarge),
+            4 => Some(Self::NotStarted),
+            5 => Some(Self::Incomplete),
+            6 => Some(Self::BadCrc),
+            7 => Some(Self::BadSignature),
+            8 => Some(Self::FlashError),
+            9 => Some(Self::Misaligned),
+            _ => None,
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Done => "update installed",
+            Self::WrongOffset => "chunk out of order",
+            Self::TooLarge => "image too large for the device",
+            Self::NotStarted => "no update in progress",
+            Self::Incomplete => "image incomplete",
+            Self::BadCrc => "image damaged (CRC mismatch)",
+            Self::BadSignature => "image not signed with the device key",
+            Self::FlashError => "flash write failed",
+            Self::Misaligned => "chunk size not a multiple of the flash write size",
+        }
+    }
+}
+
+/// Bootloader side of a transfer: checks that chunks arrive in order and fit the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Transfer {
+    header: ImageHeader,
+    received: u32,
+}
+
+impl Transfer {
+    pub fn begin(header: ImageHeader, capacity: u32) -> Result<Self, UpdateStatus> {
+        if header.size > capacity {
+            return Err(UpdateStatus::TooLarge);
+        }
+        Ok(Self { header, received: 0 })
+    }
+
+    pub fn header(&self) -> &ImageHeader {
+        &self.header
+    }
+
+    /// Offset of the next expected chunk.
+    pub fn received(&self) -> u32 {
+        self.received
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received == self.header.size
+    }
+
+    /// Checks a chunk, which then has to be written to the image at `offset` and committed.
+    pub fn accept(&self, offset: u32, data: &[u8]) -> Result<(), UpdateStatus> {
+        if offset != self.received {
+            return Err(UpdateStatus::WrongOffset);
+        }
+        let end = offset as u64 + data.len() as u64;
+        if end > self.header.size as u64 {
+            return Err(UpdateStatus::TooLarge);
+        }
+        // only the last chunk may end between two writes, so every chunk starts on a write boundary
+        if end < self.header.size as u64 && !data.len().is_multiple_of(layout::WRITE_SIZE) {
+            return Err(UpdateStatus::Misaligned);
+        }
+        Ok(())
+    }
+
+    /// Counts an accepted chunk of `len` bytes as received, once it was written to flash.
+    pub fn commit(&mut self, len: usize) {
+        self.received += len as u32;
+    }
+}
+
+/// Next thing for the desktop app to do during an upload.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Send(HostMessage),
+    Done,
+    Failed(UpdateStatus),
+}
+
+/// Desktop side of a transfer of a release `file`.
+#[derive(Debug, Clone)]
+pub struct Upload<T> {
+    file: T,
+    header: ImageHeader,
+    acknowledged: u32,
+}
+
+impl<T: AsRef<[u8]>> Upload<T> {
+    /// Starts an upload, or returns `None` if the file is not a release image.
+    pub fn new(file: T) -> Option<Self> {
+        let (header, _) = split_image(file.as_ref())?;
+        Some(Self { file, header, acknowledged: 0 })
+    }
+
+    pub fn header(&self) -> &ImageHeader {
+        &self.header
+    }
+
+    /// Message that starts or restarts the transfer.
+    pub fn begin(&mut self) -> HostMessage {
+        self.acknowledged = 0;
+        HostMessage::UpdateBegin(self.header)
+    }
+
+    /// Share of the image acknowledged by the device, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        self.acknowledged as f32 / self.header.size.max(1) as f32
+    }
+
+    fn chunk(&self, offset: u32) -> HostMessage {
+        let image = &self.file.as_ref()[ImageHeader::SIZE..];
+        let chunk = &image[offset as usize..(offset as usize + CHUNK_SIZE).min(image.len())];
+
+        let mut data = [0; CHUNK_SIZE];
+        data[..chunk.len()].copy_from_slice(chunk);
+        HostMessage::UpdateChunk { offset, len: chunk.len() as u8, data }
+    }
+
+    /// Handles an acknowledgement of the bootloader.
+    pub fn handle(&mut self, offset: u32, status: UpdateStatus) -> Step {
+        match status {
+            UpdateStatus::Ok | UpdateStatus::WrongOffset if offset <= self.header.size => {
+                self.acknowledged = offset;
+                if offset < self.header.size {
+                    Step::Send(self.chunk(offset))
+                } else {
+                    Step::Send(HostMessage::UpdateFinish)
+                }
+            },
+            UpdateStatus::Done => Step::Done,
+            UpdateStatus::Ok | UpdateStatus::WrongOffset => Step::Failed(UpdateStatus::TooLarge),
+            status => Step::Failed(status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec::Vec;
+
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+
+    /// Key pair of the test images. No bootloader trusts it, the bootloader key is set when it is built.
+    const TEST_SECRET_KEY: [u8; 32] = [
+        0xCB, 0x2F, 0xF0, 0x9C, 0xD3, 0x16, 0xC1, 0xD3, 0x8F, 0x1C, 0x15, 0xD1, 0x1F, 0x75, 0xF0, 0x22,
+        0x79, 0xE1, 0xD1, 0x51, 0xEC, 0x6B, 0x0B, 0xFB, 0x45, 0x95, 0xDB, 0x88, 0x18, 0xE0, 0x99, 0x1B,
+    ];
+    const TEST_PUBLIC_KEY: [u8; 32] = [
+        0x73, 0x88, 0xD2, 0x1D, 0xD8, 0xA9, 0x30, 0xFB, 0x6F, 0x68, 0xB3, 0x05, 0x62, 0xF0, 0x2E, 0xCC,
+        0x4B, 0xED, 0xA1, 0x61, 0xBE, 0x90, 0x85, 0x44, 0xDE, 0x6C, 0x50, 0x56, 0xCB, 0xD0, 0x32, 0x95,
+    ];
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    /// Transfers `file` from an upload to a transfer, dropping the chunk message number `drop`.
+    fn transfer(file: &[u8], drop: Option<usize>) -> (Step, Vec<u8>) {
+        let mut upload = Upload::new(file).unwrap();
+        let mut flash = Vec::new();
+        let mut transfer = None;
+        let mut message = upload.begin();
+        let mut sent = 0;
+
+        loop {
+            let (offset, status) = match message {
+                HostMessage::UpdateBegin(header) => {
+                    transfer = Some(Transfer::begin(header, 4096).unwrap());
+                    (0, UpdateStatus::Ok)
+                },
+                HostMessage::UpdateChunk { offset, len, data } => {
+                    sent += 1;
+                    let transfer = transfer.as_mut().unwrap();
+                    if Some(sent) == drop {
+                        // lost on the way, the next chunk reveals the gap
+                        message = upload.chunk(offset + len as u32);
+                        continue;
+                    }
+                    match transfer.accept(offset, &data[..len as usize]) {
+                        Ok(()) => {
+                            flash.extend_from_slice(&data[..len as usize]);
+                            transfer.commit(len as usize);
+                            (transfer.received(), UpdateStatus::Ok)
+                        },
+                        Err(status) => (transfer.received(), status),
+                    }
+                },
+                HostMessage::UpdateFinish => {
+                    let transfer = transfer.unwrap();
+                    let status = match transfer.header().check(&flash, &TEST_PUBLIC_KEY) {
+                        Ok(()) => UpdateStatus::Done,
+                        Err(status) => status,
+                    };
+                    (transfer.received(), status)
+                },
+                _ => unreachable!(),
+            };
+
+            match upload.handle(offset, status) {
+                Step::Send(next) => message = next,
+                step => return (step, flash),
+            }
+        }
+    }
+
+    #[test]
+    fn test_transfer_chunks() {
+        let header = ImageHeader { version: [0; 4], size: 100, crc: 0, signature: [0; 64] };
+        let mut transfer = Transfer::begin(header, 4096).unwrap();
+
+        assert_eq!(transfer.accept(0, &[0; 12]), Err(UpdateStatus::Misaligned));
+        assert_eq!(transfer.accept(8, &[0; 16]), Err(UpdateStatus::WrongOffset));
+        assert_eq!(transfer.accept(0, &[0; 96]), Ok(()));
+
+        // a chunk that did not make it into flash is not counted
+        assert_eq!(transfer.received(), 0);
+        transfer.commit(96);
+        assert_eq!(transfer.received(), 96);
+
+        // the last chunk may end anywhere
+        assert_eq!(transfer.accept(96, &[0; 5]), Err(UpdateStatus::TooLarge));
+        assert_eq!(transfer.accept(96, &[0; 4]), Ok(()));
+        transfer.commit(4);
+        assert!(transfer.is_complete());
+    }
+
+    /// Release file signed with the test key, as produced by the signing script.
+    const SIGNED_FILE: &[u8] = include_bytes!("test-image.bin");
+
+    #[test]
+    fn test_signing_script_format() {
+        let signing_key = SigningKey::from_bytes(&TEST_SECRET_KEY);
+        assert_eq!(signing_key.verifying_key().to_bytes(), TEST_PUBLIC_KEY);
+
+        // signatures are deterministic, so signing the same image again gives the same file
+        let (header, image) = split_image(SIGNED_FILE).unwrap();
+        let mut message = header.signed_fields().to_vec();
+        message.extend_from_slice(image);
+        assert_eq!(signing_key.sign(&message).to_bytes(), header.signature);
+
+        // an all zero key is a point of small order, which would accept forged signatures
+        assert_eq!(header.check(image, &[0; 32]), Err(UpdateStatus::BadSignature));
+    }
+
+    #[test]
+    fn test_upload() {
+        let (header, image) = split_image(SIGNED_FILE).unwrap();
+        assert!(header.size as usize > 2 * CHUNK_SIZE);
+
+        let (step, flash) = transfer(SIGNED_FILE, None);
+        assert_eq!(step, Step::Done);
+        assert_eq!(flash, image);
+
+        let (step, flash) = transfer(SIGNED_FILE, Some(2));
+        assert_eq!(step, Step::Done);
+        assert_eq!(flash, image);
+
+        // any change to the image invalidates the signature
+        let mut file = SIGNED_FILE.to_vec();
+        let last = file.len() - 1;
+        file[last] ^= 0x01;
+        let header = ImageHeader { crc: crc32(&file[ImageHeader::SIZE..]), ..header };
+        file[..ImageHeader::SIZE].copy_from_slice(&header.to_bytes());
+        assert_eq!(transfer(&file, None).0, Step::Failed(UpdateStatus::BadSignature));
+
+        file[last] ^= 0x01;
+        assert_eq!(transfer(&file, None).0, Step::Failed(UpdateStatus::BadCrc));
+    }
+}
@@ -0,0 +1,642 @@
+use expressor_common::config::{ActionKind, ChannelConfig, DeviceConfig, HidOutput, InputMode, OutputPath, RoutingConfig};
+use expressor_common::hid::{GamepadInput, KeyCombo, MediaKey};
+use expressor_common::led::{ErrorCode, LedEvent};
+use expressor_common::midi::MidiMessage;
+use expressor_common::protocol::{DeviceMessage, Frame};
+
+use crate::program::Programs;
+use crate::ramp::RampPlayer;
+use crate::sequence::SequencePlayer;
+use crate::strip::ChannelStrip;
+use crate::takeover::ControllerValues;
+
+/// What a channel holds on the HID interfaces, `None` where it does not feed an interface.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct HidState {
+    pub key: Option<KeyCombo>,
+    pub gamepad: Option<GamepadInput>,
+    pub media: Option<MediaKey>,
+    /// Pedal value and number of volume steps over the pedal range.
+    pub volume: Option<(u8, u8)>,
+}
+
+/// Everything the core produces. None of these may block, platforms queue what they can not pass on right away.
+pub trait Outputs {
+    /// Sends a message of `channel` on the ports and cables selected by its configuration.
+    fn midi(&mut self, channel: usize, message: MidiMessage, config: &ChannelConfig, routing: &RoutingConfig);
+
+    /// Like [`Outputs::midi`], but the message goes out after everything sent before, without being replaced or rate
+    /// limited. For messages that belong together, e.g. a note on and its note off.
+    fn midi_ordered(&mut self, channel: usize, message: MidiMessage, config: &ChannelConfig, routing: &RoutingConfig);
+
+    /// Updates what `channel` holds on the HID interfaces. Called on every sample, also when nothing changed.
+    fn hid(&mut self, channel: usize, state: HidState);
+
+    fn led(&mut self, event: LedEvent);
+
+    /// Sends a protocol frame to the desktop app.
+    fn reply(&mut self, frame: Frame);
+
+    /// Activates a preset once the current sample is processed, for switch gestures.
+    fn preset(&mut self, index: u8);
+}
+
+/// Turns input samples into MIDI messages, HID state and reports for the desktop app.
+pub struct Engine<const N: usize> {
+    strips: [ChannelStrip; N],
+    sequences: [SequencePlayer; N],
+    ramps: [RampPlayer; N],
+    /// Expected time between two samples in microseconds.
+    sample_period: u64,
+    previous_timestamp: Option<u64>,
+    /// Time of the last resend of the channel values.
+    last_resend: u64,
+    /// Controller values sent and received, for the soft takeover.
+    controllers: ControllerValues,
+    /// Current program of the channels navigated by switches.
+    programs: Programs,
+}
+
+impl<const N: usize> Engine<N> {
+    /// Creates the engine for inputs sampled at `sample_rate` Hz.
+    pub fn new(sample_rate: u64) -> Self {
+        Self {
+            strips: [ChannelStrip::default(); N],
+            sequences: [SequencePlayer::default(); N],
+            ramps: [RampPlayer::default(); N],
+            sample_period: 1_000_000 / sample_rate,
+            previous_timestamp: None,
+            last_resend: 0,
+            controllers: ControllerValues::default(),
+            programs: Programs::default(),
+        }
+    }
+
+    pub fn strip(&self, channel: usize) -> &ChannelStrip {
+        &self.strips[channel]
+    }
+
+    /// Current program of a MIDI channel, see [`Programs`].
+    pub fn program(&self, channel: u8) -> u16 {
+        self.programs.get(channel)
+    }
+
+    /// Processes one sample of all inputs, taken at `timestamp` microseconds.
+    pub fn process(&mut self, timestamp: u64, values: &[u16; N], config: &DeviceConfig<N>, outputs: &mut impl Outputs) {
+        if let Some(previous) = self.previous_timestamp.replace(timestamp)
+            && timestamp.saturating_sub(previous) > self.sample_period * 2
+        {
+            outputs.led(LedEvent::Error(ErrorCode::SampleOverrun));
+        }
+
+        for (i, strip) in self.strips.iter_mut().enumerate() {
+            let channel_config = &config.channels[i];
+            strip.process(timestamp, values[i], channel_config);
+
+            if let Some(gesture) = strip.gesture(timestamp, channel_config) {
+                let action = channel_config.gestures.action(gesture);
+                match action.kind {
+                    ActionKind::None => {},
+                    ActionKind::Message if channel_config.destination.midi() => {
+                        for message in action.messages() {
+                            self.controllers.record(&message);
+                            outputs.midi_ordered(i, message, channel_config, &config.routing);
+                        }
+                    },
+                    ActionKind::Message => {},
+                    ActionKind::Preset => outputs.preset(action.value),
+                    ActionKind::Toggle => strip.toggle(&channel_config.input.switch),
+                    ActionKind::Macro => self.sequences[i].start(timestamp),
+                }
+            }
+
+            while let Some(message) = self.sequences[i].next(timestamp, &channel_config.sequence) {
+                if channel_config.destination.midi() {
+                    self.controllers.record(&message);
+                    outputs.midi_ordered(i, message, channel_config, &config.routing);
+                }
+            }
+
+            if channel_config.input.mode == InputMode::Ramp {
+                let ramp = &channel_config.ramp;
+                if strip.just_pressed() {
+                    if ramp.reverse {
+                        self.ramps[i].start(true, timestamp, ramp);
+                    } else {
+                        self.ramps[i].restart(timestamp, ramp);
+                    }
+                } else if strip.just_released() && ramp.reverse {
+                    self.ramps[i].start(false, timestamp, ramp);
+                }
+                if let Some(value) = self.ramps[i].update(timestamp, ramp) {
+                    strip.set_value(value);
+                }
+            }
+
+            // a ramp moves through many values, only switches latch
+            if strip.changed() && !matches!(channel_config.input.mode, InputMode::Continuous | InputMode::Ramp) {
+                let latched = strip.value() == channel_config.input.switch.pressed_value;
+                outputs.led(LedEvent::Latch { channel: i as u8, latched });
+            }
+
+            if channel_config.input.mode == InputMode::ProgramNavigation {
+                if strip.just_pressed() && channel_config.destination.midi() {
+                    for message in self.programs.step(&channel_config.program) {
+                        self.controllers.record(&message);
+                        outputs.midi_ordered(i, message, channel_config, &config.routing);
+                    }
+                }
+            } else if channel_config.destination.midi() {
+                for message in strip.messages(channel_config) {
+                    self.controllers.record(&message);
+                    outputs.midi(i, message, channel_config, &config.routing);
+                }
+            }
+
+            outputs.hid(i, hid_state(strip, channel_config));
+
+            if let Some(detection) = strip.detection_changed() {
+                outputs.reply(DeviceMessage::PedalDetection { channel: i as u8, detection }.encode());
+            }
+        }
+
+        while let Some((channel, program)) = self.programs.take_changed() {
+            if navigated(config, channel) {
+                outputs.reply(DeviceMessage::Program { channel, program }.encode());
+            }
+        }
+
+        if let Some(interval) = config.routing.refresh_interval_micros()
+            && timestamp.saturating_sub(self.last_resend) >= interval
+        {
+            self.resend(timestamp, config, outputs);
+        }
+    }
+
+    /// Sends the current value of every channel that allows it again over USB, so a host that just connected knows
+    /// where the pedals are. Does nothing before the first sample. Program navigation is left out, a program change
+    /// would reload the program.
+    pub fn resend(&mut self, timestamp: u64, config: &DeviceConfig<N>, outputs: &mut impl Outputs) {
+        if self.previous_timestamp.is_none() {
+            return;
+        }
+        self.last_resend = timestamp;
+
+        for (i, strip) in self.strips.iter().enumerate() {
+            let channel_config = &config.channels[i];
+            if !channel_config.resend
+                || !channel_config.destination.midi()
+                || !channel_config.output_path.usb()
+                || channel_config.input.mode == InputMode::ProgramNavigation
+            {
+                continue;
+            }
+            // only hosts connect and ask for the state, devices on DIN already got every message
+            let channel_config = &channel_config.with_output_path(OutputPath::Usb);
+
+            for message in strip.state_messages(channel_config) {
+                self.controllers.record(&message);
+                outputs.midi(i, message, channel_config, &config.routing);
+            }
+        }
+    }
+
+    /// Takes a message from the host or the MIDI input. A control change moves its controller elsewhere, so the
+    /// mappings sending it are held until their pedal takes over. Program changes and bank selects move the program
+    /// navigation.
+    pub fn feedback(&mut self, message: &MidiMessage, config: &DeviceConfig<N>) {
+        self.programs.feedback(message);
+        let MidiMessage::ControlChange(channel, number, _) = *message else {
+            return;
+        };
+        self.controllers.record(message);
+
+        for (strip, channel_config) in self.strips.iter_mut().zip(&config.channels) {
+            strip.hold(channel_config, &self.controllers, |mapping| {
+                mapping.channel == channel && mapping.number == number
+            });
+        }
+    }
+
+    /// Tells the desktop app the current program of every MIDI channel navigated by a switch.
+    pub fn report(&self, config: &DeviceConfig<N>, outputs: &mut impl Outputs) {
+        for channel in 0..16 {
+            if navigated(config, channel) {
+                outputs.reply(DeviceMessage::Program { channel, program: self.programs.get(channel) }.encode());
+            }
+        }
+    }
+
+    /// Holds all mappings of the newly active preset whose controllers are somewhere else than their pedals.
+    pub fn preset_changed(&mut self, config: &DeviceConfig<N>) {
+        for (strip, channel_config) in self.strips.iter_mut().zip(&config.channels) {
+            strip.hold(channel_config, &self.controllers, |_| true);
+        }
+    }
+}
+
+/// Whether a switch navigates the programs of a MIDI channel.
+fn navigated<const N: usize>(config: &DeviceConfig<N>, channel: u8) -> bool {
+    config.channels.iter().any(|config| {
+        config.input.mode == InputMode::ProgramNavigation && config.program.channel == channel
+    })
+}
+
+fn hid_state(strip: &ChannelStrip, config: &ChannelConfig) -> HidState {
+    if !config.destination.hid() {
+        return HidState::default();
+    }
+
+    let value = strip.value();
+    HidState {
+        key: config.hid.key(value),
+        gamepad: (config.hid.output == HidOutput::Gamepad).then(|| strip.gamepad_input(&config.input)),
+        media: config.hid.media_key(value),
+        volume: (config.hid.output == HidOutput::Volume).then_some((value, config.hid.volume_steps)),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use expressor_common::config::{
+        Destination, Gesture, GestureAction, MacroSequence, MacroStep, Mapping, MessageType, ProgramStep, Takeover,
+    };
+    use expressor_common::detect::RAW_MAX;
+    use expressor_common::midi::CoalescingBuffer;
+
+    use super::*;
+
+    /// Keeps everything the core produced, and queues the MIDI messages like the platforms do.
+    #[derive(Default)]
+    pub(crate) struct Recorder {
+        pub midi: Vec<(usize, MidiMessage)>,
+        pub buffer: CoalescingBuffer<8>,
+        /// Port of each MIDI message.
+        pub paths: Vec<OutputPath>,
+        pub hid: Vec<(usize, HidState)>,
+        pub leds: Vec<LedEvent>,
+        pub replies: Vec<Frame>,
+        pub presets: Vec<u8>,
+    }
+
+    impl Recorder {
+        /// Takes the queued MIDI messages the way a port does, waiting out the rate limits from `now` on.
+        pub fn sent(&mut self, mut now: u64) -> Vec<MidiMessage> {
+            let mut sent = Vec::new();
+            while let Some(ready) = self.buffer.next_ready() {
+                now = now.max(ready);
+                sent.extend(self.buffer.pop(now));
+            }
+            sent
+        }
+    }
+
+    impl Outputs for Recorder {
+        fn midi(&mut self, channel: usize, message: MidiMessage, config: &ChannelConfig, _routing: &RoutingConfig) {
+            self.midi.push((channel, message));
+            self.paths.push(config.output_path);
+            self.buffer.push(message, config.min_interval());
+        }
+
+        fn midi_ordered(
+            &mut self,
+            channel: usize,
+            message: MidiMessage,
+            config: &ChannelConfig,
+            _routing: &RoutingConfig,
+        ) {
+            self.midi.push((channel, message));
+            self.paths.push(config.output_path);
+            self.buffer.push_ordered(message);
+        }
+
+        fn hid(&mut self, channel: usize, state: HidState) {
+            self.hid.push((channel, state));
+        }
+
+        fn led(&mut self, event: LedEvent) {
+            self.leds.push(event);
+        }
+
+        fn reply(&mut self, frame: Frame) {
+            self.replies.push(frame);
+        }
+
+        fn preset(&mut self, index: u8) {
+            self.presets.push(index);
+        }
+    }
+
+    #[test]
+    fn test_process_sends_changes() {
+        let mut engine = Engine::<2>::new(1000);
+        let config = DeviceConfig::<2>::default();
+        let mut recorder = Recorder::default();
+
+        engine.process(0, &[RAW_MAX, 0], &config, &mut recorder);
+        engine.process(1000, &[RAW_MAX, 0], &config, &mut recorder);
+        engine.process(2000, &[0, 0], &config, &mut recorder);
+
+        assert_eq!(recorder.midi, [
+            (0, MidiMessage::ControlChange(0, 0, 127)),
+            (0, MidiMessage::ControlChange(0, 0, 0)),
+        ]);
+        assert!(recorder.leds.is_empty());
+        assert_eq!(engine.strip(0).value(), 0);
+    }
+
+    #[test]
+    fn test_process_switch_latch() {
+        let mut engine = Engine::<1>::new(1000);
+        let mut config = DeviceConfig::<1>::default();
+        config.channels[0] = config.channels[0]
+            .with_input_mode(InputMode::Switch)
+            .with_mapping(0, Mapping::default().with_enabled(true).with_number(64));
+        let mut recorder = Recorder::default();
+
+        for millis in 0..40 {
+            engine.process(millis * 1000, &[if millis < 20 { RAW_MAX } else { 0 }], &config, &mut recorder);
+        }
+
+        assert_eq!(recorder.leds, [
+            LedEvent::Latch { channel: 0, latched: true },
+            LedEvent::Latch { channel: 0, latched: false },
+        ]);
+        assert_eq!(recorder.midi, [
+            (0, MidiMessage::ControlChange(0, 64, 127)),
+            (0, MidiMessage::ControlChange(0, 64, 0)),
+        ]);
+    }
+
+    #[test]
+    fn test_process_hid_only() {
+        let mut engine = Engine::<1>::new(1000);
+        let mut config = DeviceConfig::<1>::default();
+        config.channels[0] = config.channels[0]
+            .with_destination(Destination::Hid)
+            .with_hid_output(HidOutput::Gamepad);
+        let mut recorder = Recorder::default();
+
+        engine.process(0, &[RAW_MAX], &config, &mut recorder);
+
+        assert!(recorder.midi.is_empty());
+        assert_eq!(recorder.hid, [(0, HidState { gamepad: Some(GamepadInput::Axis(RAW_MAX)), ..HidState::default() })]);
+    }
+
+    #[test]
+    fn test_resend() {
+        let mut engine = Engine::<2>::new(1000);
+        let mut config = DeviceConfig::<2>::default();
+        config.channels[1] = config.channels[1].with_resend(false);
+        config.routing = config.routing.with_refresh_interval(1);
+        let mut recorder = Recorder::default();
+
+        // nothing was sampled yet
+        engine.resend(0, &config, &mut recorder);
+        assert!(recorder.midi.is_empty());
+
+        engine.process(0, &[RAW_MAX, RAW_MAX], &config, &mut recorder);
+        recorder.midi.clear();
+        recorder.paths.clear();
+        engine.resend(1000, &config, &mut recorder);
+        assert_eq!(recorder.midi, [(0, MidiMessage::ControlChange(0, 0, 127))]);
+        // to the host only
+        assert_eq!(recorder.paths, [OutputPath::Usb]);
+
+        // periodically, counted from the last resend
+        recorder.midi.clear();
+        engine.process(1_000_999, &[RAW_MAX, RAW_MAX], &config, &mut recorder);
+        assert!(recorder.midi.is_empty());
+        engine.process(1_001_000, &[RAW_MAX, RAW_MAX], &config, &mut recorder);
+        assert_eq!(recorder.midi, [(0, MidiMessage::ControlChange(0, 0, 127))]);
+    }
+
+    #[test]
+    fn test_takeover_after_preset_change() {
+        let mut engine = Engine::<1>::new(1000);
+        let mut first = DeviceConfig::<1>::default();
+        first.channels[0] = first.channels[0].with_takeover(Takeover::Pickup);
+        let mut second = first;
+        second.channels[0] = second.channels[0].with_mapping(0, Mapping::default().with_enabled(true).with_number(1));
+        let mut recorder = Recorder::default();
+
+        // leave the first controller at 127, then move the pedal down on the second one
+        engine.process(0, &[RAW_MAX], &first, &mut recorder);
+        engine.preset_changed(&second);
+        engine.process(1000, &[0], &second, &mut recorder);
+        engine.preset_changed(&first);
+        recorder.midi.clear();
+
+        // held until the pedal gets back to the top
+        engine.process(2000, &[RAW_MAX / 2], &first, &mut recorder);
+        assert!(recorder.midi.is_empty());
+        engine.process(3000, &[RAW_MAX], &first, &mut recorder);
+        engine.process(4000, &[RAW_MAX / 2], &first, &mut recorder);
+        assert_eq!(recorder.midi, [(0, MidiMessage::ControlChange(0, 0, 63))]);
+    }
+
+    #[test]
+    fn test_takeover_after_feedback() {
+        let mut engine = Engine::<1>::new(1000);
+        let mut config = DeviceConfig::<1>::default();
+        config.channels[0] = config.channels[0].with_takeover(Takeover::Pickup);
+        let mut recorder = Recorder::default();
+
+        engine.process(0, &[0], &config, &mut recorder);
+        engine.feedback(&MidiMessage::ControlChange(0, 1, 50), &config);
+        engine.feedback(&MidiMessage::ControlChange(0, 0, 50), &config);
+
+        engine.process(1000, &[RAW_MAX / 4], &config, &mut recorder);
+        assert!(recorder.midi.is_empty());
+        engine.process(2000, &[RAW_MAX / 2], &config, &mut recorder);
+        assert_eq!(recorder.midi, [(0, MidiMessage::ControlChange(0, 0, 63))]);
+
+        // jump mode sends right away
+        config.channels[0] = config.channels[0].with_takeover(Takeover::Jump);
+        engine.feedback(&MidiMessage::ControlChange(0, 0, 100), &config);
+        engine.process(3000, &[RAW_MAX], &config, &mut recorder);
+        assert_eq!(recorder.midi[1], (0, MidiMessage::ControlChange(0, 0, 127)));
+    }
+
+    #[test]
+    fn test_gestures() {
+        let mut engine = Engine::<1>::new(1000);
+        let mut config = DeviceConfig::<1>::default();
+        let note = GestureAction::default().with_kind(ActionKind::Message).with_message_type(MessageType::Note);
+        config.channels[0] = config.channels[0]
+            .with_input_mode(InputMode::Switch)
+            .with_gesture_action(Gesture::Tap, GestureAction::default().with_kind(ActionKind::Toggle))
+            .with_gesture_action(Gesture::DoubleTap, note.with_number(36))
+            .with_gesture_action(Gesture::LongPress, GestureAction::default().with_kind(ActionKind::Preset).with_value(2));
+        let mut recorder = Recorder::default();
+
+        let mut switch = |from: u64, to: u64, pressed: bool, recorder: &mut Recorder| {
+            for millis in from..to {
+                engine.process(millis * 1000, &[if pressed { RAW_MAX } else { 0 }], &config, recorder);
+            }
+        };
+
+        // a tap toggles the mappings, the switch itself sends nothing
+        switch(0, 10, false, &mut recorder);
+        switch(10, 60, true, &mut recorder);
+        switch(60, 400, false, &mut recorder);
+        assert_eq!(recorder.midi, [(0, MidiMessage::ControlChange(0, 0, 127))]);
+        assert_eq!(recorder.leds, [LedEvent::Latch { channel: 0, latched: true }]);
+
+        // a double tap sends its note, the note off does not replace the note on in the output queue
+        recorder.midi.clear();
+        recorder.sent(400_000);
+        switch(400, 450, true, &mut recorder);
+        switch(450, 500, false, &mut recorder);
+        switch(500, 550, true, &mut recorder);
+        switch(550, 1000, false, &mut recorder);
+        assert_eq!(recorder.midi, [(0, MidiMessage::NoteOn(0, 36, 127)), (0, MidiMessage::NoteOff(0, 36, 0))]);
+        assert_eq!(recorder.sent(1_000_000), [MidiMessage::NoteOn(0, 36, 127), MidiMessage::NoteOff(0, 36, 0)]);
+
+        // a long press selects a preset
+        switch(1000, 2000, true, &mut recorder);
+        assert_eq!(recorder.presets, [2]);
+    }
+
+    #[test]
+    fn test_macro() {
+        let mut engine = Engine::<1>::new(1000);
+        let mut config = DeviceConfig::<1>::default();
+        let step = |message, delay| MacroStep::default().with_message(message).with_delay(delay);
+        config.channels[0] = config.channels[0]
+            .with_input_mode(InputMode::Switch)
+            .with_max_rate(10)
+            .with_gesture_action(Gesture::Tap, GestureAction::default().with_kind(ActionKind::Macro))
+            .with_sequence(MacroSequence::default()
+                .with_added(step(MidiMessage::ControlChange(0, 0, 1), 0))
+                .with_added(step(MidiMessage::ControlChange(0, 32, 0), 0))
+                .with_added(step(MidiMessage::ProgramChange(0, 5), 5))
+                .with_added(step(MidiMessage::ControlChange(0, 7, 100), 0))
+                .with_added(step(MidiMessage::ControlChange(0, 7, 0), 0)));
+        let mut recorder = Recorder::default();
+
+        for millis in 0..60 {
+            let value = if (2..30).contains(&millis) { RAW_MAX } else { 0 };
+            engine.process(millis * 1000, &[value], &config, &mut recorder);
+            // the steps before the first pause go out with the tap
+            if millis == 30 {
+                assert_eq!(recorder.midi.len(), 3);
+            }
+        }
+        let steps = [
+            MidiMessage::ControlChange(0, 0, 1),
+            MidiMessage::ControlChange(0, 32, 0),
+            MidiMessage::ProgramChange(0, 5),
+            MidiMessage::ControlChange(0, 7, 100),
+            MidiMessage::ControlChange(0, 7, 0),
+        ];
+        assert_eq!(recorder.midi, steps.map(|message| (0, message)));
+        // every step leaves the output queue in order, despite the rate limit and the repeated destination
+        assert_eq!(recorder.sent(60_000), steps);
+    }
+
+    #[test]
+    fn test_program_navigation() {
+        let mut engine = Engine::<2>::new(1000);
+        let mut config = DeviceConfig::<2>::default();
+        config.channels[0] = config.channels[0]
+            .with_input_mode(InputMode::ProgramNavigation)
+            .with_program_channel(3)
+            .with_program_range(0, 2);
+        config.channels[1] = config.channels[0].with_program_step(ProgramStep::Decrement);
+        let mut recorder = Recorder::default();
+
+        let presses = [[0, 0], [RAW_MAX, 0], [RAW_MAX, 0], [0, 0], [0, RAW_MAX], [0, 0], [0, RAW_MAX]];
+        // each state is held for longer than the debounce time
+        for (i, values) in presses.iter().enumerate() {
+            for millis in 0..20 {
+                engine.process((i as u64 * 20 + millis) * 1000, values, &config, &mut recorder);
+            }
+        }
+
+        // both switches step the same program, the mappings send nothing
+        assert_eq!(recorder.midi, [
+            (0, MidiMessage::ProgramChange(3, 1)),
+            (1, MidiMessage::ProgramChange(3, 0)),
+            (1, MidiMessage::ProgramChange(3, 2)),
+        ]);
+        let programs = |recorder: &Recorder| recorder.replies.iter().filter_map(|frame| match DeviceMessage::decode(frame) {
+            Some(DeviceMessage::Program { channel, program }) => Some((channel, program)),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(programs(&recorder), [(3, 1), (3, 0), (3, 2)]);
+
+        // the host changes the program as well
+        engine.feedback(&MidiMessage::ProgramChange(3, 1), &config);
+        assert_eq!(engine.program(3), 1);
+        recorder.replies.clear();
+        engine.report(&config, &mut recorder);
+        assert_eq!(programs(&recorder), [(3, 1)]);
+    }
+
+    #[test]
+    fn test_program_bank_select_order() {
+        let mut engine = Engine::<2>::new(1000);
+        let mut config = DeviceConfig::<2>::default();
+        config.channels[0] = config.channels[0].with_max_rate(10);
+        config.channels[1] = config.channels[1]
+            .with_input_mode(InputMode::ProgramNavigation)
+            .with_program_step(ProgramStep::Direct)
+            .with_program(130)
+            .with_max_rate(10);
+        let mut recorder = Recorder::default();
+
+        // the pedal of the first channel just sent its bank select controller
+        engine.process(0, &[RAW_MAX, 0], &config, &mut recorder);
+        assert_eq!(recorder.sent(0), [MidiMessage::ControlChange(0, 0, 127)]);
+
+        // the program change still waits for its bank select
+        engine.process(1000, &[RAW_MAX, RAW_MAX], &config, &mut recorder);
+        assert_eq!(recorder.sent(1000), [
+            MidiMessage::ControlChange(0, 0, 0),
+            MidiMessage::ControlChange(0, 32, 1),
+            MidiMessage::ProgramChange(0, 2),
+        ]);
+    }
+
+    #[test]
+    fn test_ramp() {
+        let mut engine = Engine::<1>::new(1000);
+        let mut config = DeviceConfig::<1>::default();
+        config.channels[0] = config.channels[0].with_input_mode(InputMode::Ramp).with_ramp_duration(50);
+        let mut recorder = Recorder::default();
+
+        for millis in 0..200 {
+            let value = if (10..100).contains(&millis) { RAW_MAX } else { 0 };
+            engine.process(millis * 1000, &[value], &config, &mut recorder);
+            if millis == 99 {
+                assert_eq!(recorder.midi.len(), 5);
+                assert_eq!(recorder.midi.last(), Some(&(0, MidiMessage::ControlChange(0, 0, 127))));
+            }
+        }
+
+        // up at the fixed update rate, and back down on release
+        assert_eq!(recorder.midi[0], (0, MidiMessage::ControlChange(0, 0, 25)));
+        assert_eq!(recorder.midi.len(), 10);
+        assert_eq!(recorder.midi.last(), Some(&(0, MidiMessage::ControlChange(0, 0, 0))));
+        assert!(recorder.leds.is_empty());
+    }
+
+    #[test]
+    fn test_process_overrun() {
+        let mut engine = Engine::<1>::new(1000);
+        let config = DeviceConfig::<1>::default();
+        let mut recorder = Recorder::default();
+
+        engine.process(0, &[0], &config, &mut recorder);
+        engine.process(2000, &[0], &config, &mut recorder);
+        assert!(recorder.leds.is_empty());
+
+        engine.process(5000, &[0], &config, &mut recorder);
+        assert_eq!(recorder.leds, [LedEvent::Error(ErrorCode::SampleOverrun)]);
+    }
+}
@@ -0,0 +1,134 @@
+//! Tells taps, double taps, long presses and held switches apart.
+//!
+//! Gestures without an action are not waited for: a tap fires on release unless a double tap has an action, and a
+//! long held switch is a tap unless a long press or repeat has one.
+//!
+//! The switch state comes debounced from the [`ChannelStrip`](crate::strip::ChannelStrip), so every change is a real
+//! press or release.
+
+use expressor_common::config::{Gesture, GestureConfig};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+enum State {
+    #[default]
+    Released,
+    /// Pressed at `since`, not long enough for a long press yet.
+    Pressed { since: u64 },
+    /// Held past the long press time, repeating at `next`.
+    Held { next: u64 },
+    /// Released after a tap, waiting for a second one until `until`.
+    Tapped { until: u64 },
+    /// Second press of a double tap, which already fired.
+    DoubleTapped,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GestureDetector {
+    state: State,
+}
+
+impl GestureDetector {
+    /// Takes the switch state sampled at `now` microseconds and returns the gesture it completes, if any.
+    pub fn update(&mut self, pressed: bool, now: u64, config: &GestureConfig) -> Option<Gesture> {
+        let holds = config.has_action(Gesture::LongPress) || config.has_action(Gesture::Repeat);
+
+        let (state, gesture) = match (self.state, pressed) {
+            (State::Released, true) => (State::Pressed { since: now }, None),
+            (State::Pressed { since }, true) if holds && now - since >= config.long_press_micros() => {
+                let gesture = if config.has_action(Gesture::LongPress) { Gesture::LongPress } else { Gesture::Repeat };
+                (State::Held { next: now + config.repeat_micros() }, Some(gesture))
+            },
+            (State::Pressed { .. }, false) if config.has_action(Gesture::DoubleTap) => {
+                (State::Tapped { until: now + config.double_tap_micros() }, None)
+            },
+            (State::Pressed { .. }, false) => (State::Released, Some(Gesture::Tap)),
+            (State::Held { next }, true) if config.has_action(Gesture::Repeat) && now >= next => {
+                (State::Held { next: next + config.repeat_micros() }, Some(Gesture::Repeat))
+            },
+            (State::Tapped { .. }, true) => (State::DoubleTapped, Some(Gesture::DoubleTap)),
+            (State::Tapped { until }, false) if now >= until => (State::Released, Some(Gesture::Tap)),
+            (State::Held { .. } | State::DoubleTapped, false) => (State::Released, None),
+            (state, _) => (state, None),
+        };
+
+        self.state = state;
+        gesture
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use expressor_common::config::{ActionKind, GestureAction};
+
+    use super::*;
+
+    /// Runs the detector over switch changes at millisecond times, sampled every millisecond until `end`.
+    fn detect(config: &GestureConfig, changes: &[(u64, bool)], end: u64) -> Vec<(u64, Gesture)> {
+        let mut detector = GestureDetector::default();
+        let mut pressed = false;
+
+        (0..=end)
+            .filter_map(|millis| {
+                if let Some((_, state)) = changes.iter().find(|(time, _)| *time == millis) {
+                    pressed = *state;
+                }
+                detector.update(pressed, millis * 1000, config).map(|gesture| (millis, gesture))
+            })
+            .collect()
+    }
+
+    fn config(gestures: &[Gesture]) -> GestureConfig {
+        let mut config = GestureConfig::default();
+        for gesture in gestures {
+            config.actions[*gesture as usize] = GestureAction::default().with_kind(ActionKind::Toggle);
+        }
+        config
+    }
+
+    #[test]
+    fn test_tap() {
+        let config = config(&[Gesture::Tap]);
+        assert_eq!(detect(&config, &[(10, true), (60, false)], 1000), [(60, Gesture::Tap)]);
+        // without long press actions, holding is still a tap
+        assert_eq!(detect(&config, &[(10, true), (900, false)], 1000), [(900, Gesture::Tap)]);
+    }
+
+    #[test]
+    fn test_double_tap() {
+        let config = config(&[Gesture::Tap, Gesture::DoubleTap]);
+        let double = [(10, true), (60, false), (200, true), (250, false)];
+        assert_eq!(detect(&config, &double, 1000), [(200, Gesture::DoubleTap)]);
+
+        // a single tap waits for the double tap time
+        assert_eq!(detect(&config, &[(10, true), (60, false)], 1000), [(360, Gesture::Tap)]);
+
+        // too slow for a double tap
+        let slow = [(10, true), (60, false), (400, true), (450, false)];
+        assert_eq!(detect(&config, &slow, 1000), [(360, Gesture::Tap), (750, Gesture::Tap)]);
+    }
+
+    #[test]
+    fn test_long_press() {
+        let config = config(&[Gesture::Tap, Gesture::LongPress]);
+        assert_eq!(detect(&config, &[(10, true), (1500, false)], 2000), [(610, Gesture::LongPress)]);
+        assert_eq!(detect(&config, &[(10, true), (500, false)], 2000), [(500, Gesture::Tap)]);
+    }
+
+    #[test]
+    fn test_hold_repeat() {
+        let config = config(&[Gesture::LongPress, Gesture::Repeat]);
+        assert_eq!(detect(&config, &[(10, true), (1000, false)], 2000), [
+            (610, Gesture::LongPress),
+            (760, Gesture::Repeat),
+            (910, Gesture::Repeat),
+        ]);
+
+        // the first repeat comes at the long press time without a long press action
+        let config = self::config(&[Gesture::Repeat]);
+        assert_eq!(detect(&config, &[(0, true), (700, false)], 2000), [(600, Gesture::Repeat)]);
+    }
+}
@@ -0,0 +1,72 @@
+//! Hardware the core depends on. Each platform implements these for its peripherals.
+
+// the core runs on single threaded executors, so the futures of these traits do not have to be `Send`
+#![allow(async_fn_in_trait)]
+
+use expressor_common::config::{DeviceConfig, NUM_PRESETS};
+use expressor_common::detect::RAW_MAX;
+use expressor_common::midi::UsbPacket;
+
+/// Monotonic time since the start of the device.
+pub trait Clock {
+    fn now_micros(&self) -> u64;
+}
+
+/// The analog inputs of the channel jacks.
+pub trait AnalogInput<const N: usize> {
+    /// Converts all inputs at once, with values up to [`RAW_MAX`].
+    async fn sample(&mut self) -> [u16; N];
+}
+
+/// Digital inputs, e.g. footswitches wired to GPIOs.
+pub trait SwitchInput<const N: usize> {
+    fn pressed(&mut self) -> [bool; N];
+}
+
+/// Feeds digital switches into the channel processing like analog inputs at either end of their range.
+pub struct Switches<T>(pub T);
+
+impl<const N: usize, T: SwitchInput<N>> AnalogInput<N> for Switches<T> {
+    async fn sample(&mut self) -> [u16; N] {
+        self.0.pressed().map(|pressed| if pressed { RAW_MAX } else { 0 })
+    }
+}
+
+/// The status LEDs, in the order of [`LedEngine::levels`](expressor_common::led::LedEngine::levels).
+pub trait Leds<const N: usize> {
+    fn set(&mut self, levels: [bool; N]);
+}
+
+/// Sends USB-MIDI event packets to the host.
+pub trait MidiOut {
+    type Error;
+
+    async fn send(&mut self, packet: &UsbPacket) -> Result<(), Self::Error>;
+}
+
+/// Receives USB-MIDI event packets from the host.
+pub trait MidiIn {
+    type Error;
+
+    /// Waits for at least one packet and returns how many were stored in `packets`.
+    async fn receive(&mut self, packets: &mut [UsbPacket]) -> Result<usize, Self::Error>;
+}
+
+/// Keeps the presets across restarts.
+pub trait ConfigStorage<const N: usize> {
+    /// Returns the stored presets, or `None` if there are none or they are damaged.
+    fn load(&mut self) -> Option<[DeviceConfig<N>; NUM_PRESETS]>;
+
+    fn store(&mut self, configs: &[DeviceConfig<N>; NUM_PRESETS]);
+}
+
+/// Storage for platforms without one, every start begins with the default presets.
+pub struct NoStorage;
+
+impl<const N: usize> ConfigStorage<N> for NoStorage {
+    fn load(&mut self) -> Option<[DeviceConfig<N>; NUM_PRESETS]> {
+        None
+    }
+
+    fn store(&mut self, _configs: &[DeviceConfig<N>; NUM_PRESETS]) {}
+}
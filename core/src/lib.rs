@@ -0,0 +1,21 @@
+#![no_std]
+
+//! Device logic of the Expressor, independent of the hardware.
+//!
+//! The platform samples the inputs, moves MIDI packets and keeps the presets through the traits in [`hal`], and
+//! takes whatever the core produces through [`engine::Outputs`]. The firmware binds these to the STM32, tests and
+//! the simulator bind them to plain memory.
+
+pub mod hal;
+pub mod strip;
+pub mod gesture;
+pub mod sequence;
+pub mod program;
+pub mod ramp;
+pub mod takeover;
+pub mod presets;
+pub mod engine;
+pub mod protocol;
+
+/// Reported in the identity reply and as the USB device release.
+pub const FIRMWARE_VERSION: [u8; 4] = [0, 3, 0, 0];
@@ -0,0 +1,65 @@
+use expressor_common::config::{DeviceConfig, NUM_PRESETS};
+use expressor_common::midi::MidiMessage;
+
+use crate::hal::ConfigStorage;
+
+/// The configurations that can be switched with program changes, and the active one.
+#[derive(Debug, Clone, Copy)]
+pub struct Presets<const N: usize> {
+    configs: [DeviceConfig<N>; NUM_PRESETS],
+    active: usize,
+}
+
+impl<const N: usize> Default for Presets<N> {
+    fn default() -> Self {
+        Self {
+            configs: [DeviceConfig::default(); NUM_PRESETS],
+            active: 0,
+        }
+    }
+}
+
+impl<const N: usize> Presets<N> {
+    /// Starts with the stored presets, or the default ones if there are none.
+    pub fn load(storage: &mut impl ConfigStorage<N>) -> Self {
+        match storage.load() {
+            Some(configs) => Self { configs, active: 0 },
+            None => Self::default(),
+        }
+    }
+
+    pub fn store(&self, storage: &mut impl ConfigStorage<N>) {
+        storage.store(&self.configs);
+    }
+
+    pub fn active(&self) -> u8 {
+        self.active as u8
+    }
+
+    pub fn config(&self) -> &DeviceConfig<N> {
+        &self.configs[self.active]
+    }
+
+    pub fn config_mut(&mut self) -> &mut DeviceConfig<N> {
+        &mut self.configs[self.active]
+    }
+
+    /// Activates a preset. Returns `false` if there is no such preset.
+    pub fn select(&mut self, index: u8) -> bool {
+        let valid = (index as usize) < NUM_PRESETS;
+        if valid {
+            self.active = index as usize;
+        }
+        valid
+    }
+
+    /// Returns the preset selected by a program change on the preset channel.
+    pub fn preset_change(&self, message: &MidiMessage) -> Option<u8> {
+        match *message {
+            MidiMessage::ProgramChange(channel, program) if self.config().routing.preset_channel == Some(channel) => {
+                Some(program)
+            },
+            _ => None,
+        }
+    }
+}
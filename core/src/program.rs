@@ -0,0 +1,165 @@
+//! Program navigation: switches stepping through the programs of a MIDI channel.
+//!
+//! Programs are numbered across banks of 128. A bank select goes out with the first program change of a channel and
+//! whenever a step crosses into another bank, as long as a switch of the channel reaches past the first bank.
+
+use expressor_common::config::{ProgramConfig, ProgramStep};
+use expressor_common::midi::MidiMessage;
+use heapless::Vec;
+
+/// Bank select controllers, most and least significant byte.
+const BANK_SELECT_MSB: u8 = 0;
+const BANK_SELECT_LSB: u8 = 32;
+
+/// Current program of every MIDI channel, shared by all switches that navigate it.
+#[derive(Debug, Default)]
+pub struct Programs {
+    current: [u16; 16],
+    /// Bank last selected on each channel, `None` before the first bank select.
+    bank: [Option<u16>; 16],
+    /// Channels whose program changed since the last [`Programs::take_changed`], one bit each.
+    changed: u16,
+}
+
+impl Programs {
+    pub fn get(&self, channel: u8) -> u16 {
+        self.current[channel as usize & 0x0F]
+    }
+
+    /// Applies a switch press and returns the messages that select the new program. Nothing is sent when a range
+    /// without wrapping is already at its end.
+    pub fn step(&mut self, config: &ProgramConfig) -> Vec<MidiMessage, 3> {
+        let channel = config.channel & 0x0F;
+        let (minimum, maximum) = (config.minimum.min(config.maximum), config.minimum.max(config.maximum));
+        let current = self.get(channel);
+
+        let program = match config.step {
+            ProgramStep::Direct => config.program,
+            ProgramStep::Increment if current < minimum => minimum,
+            ProgramStep::Increment if current >= maximum => if config.wrap { minimum } else { maximum },
+            ProgramStep::Increment => current + 1,
+            ProgramStep::Decrement if current > maximum => maximum,
+            ProgramStep::Decrement if current <= minimum => if config.wrap { maximum } else { minimum },
+            ProgramStep::Decrement => current - 1,
+        };
+
+        let mut messages = Vec::new();
+        if program == current && config.step != ProgramStep::Direct {
+            return messages;
+        }
+        self.set(channel, program);
+
+        let bank = program / ProgramConfig::BANK_SIZE;
+        // once a bank was selected, the other switches of the channel have to select theirs as well
+        let selected = self.bank[channel as usize];
+        if (config.uses_banks() || selected.is_some()) && selected != Some(bank) {
+            self.bank[channel as usize] = Some(bank);
+            let _ = messages.push(MidiMessage::ControlChange(channel, BANK_SELECT_MSB, (bank >> 7) as u8 & 0x7F));
+            let _ = messages.push(MidiMessage::ControlChange(channel, BANK_SELECT_LSB, bank as u8 & 0x7F));
+        }
+        let _ = messages.push(MidiMessage::ProgramChange(channel, (program % ProgramConfig::BANK_SIZE) as u8));
+        messages
+    }
+
+    /// Follows the bank selects and program changes of the host, so the next step continues from its program.
+    pub fn feedback(&mut self, message: &MidiMessage) {
+        match *message {
+            MidiMessage::ControlChange(channel, BANK_SELECT_MSB, value) => {
+                let bank = &mut self.bank[channel as usize & 0x0F];
+                *bank = Some((value as u16 & 0x7F) << 7 | bank.unwrap_or(0) & 0x7F);
+            },
+            MidiMessage::ControlChange(channel, BANK_SELECT_LSB, value) => {
+                let bank = &mut self.bank[channel as usize & 0x0F];
+                *bank = Some(bank.unwrap_or(0) & !0x7F | value as u16 & 0x7F);
+            },
+            MidiMessage::ProgramChange(channel, program) => {
+                let bank = self.bank[channel as usize & 0x0F].unwrap_or(self.get(channel) / ProgramConfig::BANK_SIZE);
+                self.set(channel, bank.saturating_mul(ProgramConfig::BANK_SIZE).saturating_add(program as u16));
+            },
+            _ => {},
+        }
+    }
+
+    /// Returns a channel whose program changed since it was last taken, with its program.
+    pub fn take_changed(&mut self) -> Option<(u8, u16)> {
+        let channel = self.changed.trailing_zeros();
+        if channel >= 16 {
+            return None;
+        }
+        self.changed &= !(1 << channel);
+        Some((channel as u8, self.current[channel as usize]))
+    }
+
+    fn set(&mut self, channel: u8, program: u16) {
+        self.current[channel as usize & 0x0F] = program;
+        self.changed |= 1 << (channel & 0x0F);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_and_clamp() {
+        let mut programs = Programs::default();
+        let up = ProgramConfig { minimum: 3, maximum: 5, ..Default::default() };
+        let down = ProgramConfig { step: ProgramStep::Decrement, ..up };
+
+        // outside the range, the first step goes to its start
+        assert_eq!(programs.step(&up).as_slice(), &[MidiMessage::ProgramChange(0, 3)]);
+        programs.step(&up);
+        programs.step(&up);
+        assert_eq!(programs.get(0), 5);
+        assert_eq!(programs.step(&up).as_slice(), &[MidiMessage::ProgramChange(0, 3)]);
+        assert_eq!(programs.step(&down).as_slice(), &[MidiMessage::ProgramChange(0, 5)]);
+
+        let clamped = ProgramConfig { wrap: false, ..up };
+        assert!(programs.step(&clamped).is_empty());
+        assert_eq!(programs.get(0), 5);
+        assert_eq!(programs.take_changed(), Some((0, 5)));
+        assert_eq!(programs.take_changed(), None);
+    }
+
+    #[test]
+    fn test_bank_select() {
+        let mut programs = Programs::default();
+        let up = ProgramConfig { channel: 2, minimum: 126, maximum: 300, ..Default::default() };
+
+        assert_eq!(programs.step(&up).as_slice(), &[
+            MidiMessage::ControlChange(2, 0, 0),
+            MidiMessage::ControlChange(2, 32, 0),
+            MidiMessage::ProgramChange(2, 126),
+        ]);
+        assert_eq!(programs.step(&up).as_slice(), &[MidiMessage::ProgramChange(2, 127)]);
+        assert_eq!(programs.step(&up).as_slice(), &[
+            MidiMessage::ControlChange(2, 0, 0),
+            MidiMessage::ControlChange(2, 32, 1),
+            MidiMessage::ProgramChange(2, 0),
+        ]);
+
+        // switches in the first bank select it again once another bank was selected
+        let direct = ProgramConfig { channel: 2, step: ProgramStep::Direct, program: 7, ..Default::default() };
+        assert_eq!(programs.step(&direct).as_slice(), &[
+            MidiMessage::ControlChange(2, 0, 0),
+            MidiMessage::ControlChange(2, 32, 0),
+            MidiMessage::ProgramChange(2, 7),
+        ]);
+
+        // ranges in the first bank never select one
+        let up = ProgramConfig::default();
+        assert_eq!(programs.step(&up).as_slice(), &[MidiMessage::ProgramChange(0, 1)]);
+    }
+
+    #[test]
+    fn test_feedback() {
+        let mut programs = Programs::default();
+        programs.feedback(&MidiMessage::ControlChange(1, 32, 2));
+        programs.feedback(&MidiMessage::ProgramChange(1, 5));
+        assert_eq!(programs.get(1), 261);
+
+        let up = ProgramConfig { channel: 1, maximum: 1000, ..Default::default() };
+        // the host already selected the bank
+        assert_eq!(programs.step(&up).as_slice(), &[MidiMessage::ProgramChange(1, 6)]);
+    }
+}
@@ -0,0 +1,158 @@
+use expressor_common::led::LedEvent;
+use expressor_common::protocol::{DeviceMessage, HostMessage, Identity, is_identity_request};
+
+use crate::engine::Outputs;
+use crate::presets::Presets;
+
+/// Requests from the desktop app that only the platform can carry out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Request {
+    /// Restart into the bootloader, which takes over the firmware update.
+    EnterBootloader,
+    /// Send the current channel values again, see [`Engine::resend`](crate::engine::Engine::resend).
+    ResendState,
+    /// Tell the desktop app the state the engine keeps, see [`Engine::report`](crate::engine::Engine::report).
+    ReportState,
+}
+
+/// Answers identity requests and applies config commands from the desktop app.
+pub fn handle_sysex<const N: usize>(
+    frame: &[u8],
+    identity: &Identity,
+    presets: &mut Presets<N>,
+    outputs: &mut impl Outputs,
+) -> Option<Request> {
+    if is_identity_request(frame) {
+        outputs.reply(identity.encode());
+        return None;
+    }
+
+    match HostMessage::decode(frame)? {
+        HostMessage::GetConfig => {
+            let config = presets.config();
+            for (channel, config) in config.channels.iter().enumerate() {
+                outputs.reply(DeviceMessage::ChannelConfig { channel: channel as u8, config: *config }.encode());
+            }
+            outputs.reply(DeviceMessage::Routing(config.routing).encode());
+            outputs.reply(DeviceMessage::Preset(presets.active()).encode());
+            return Some(Request::ReportState);
+        },
+        HostMessage::ChannelConfig { channel, config } => {
+            if let Some(slot) = presets.config_mut().channels.get_mut(channel as usize) {
+                *slot = config;
+            }
+        },
+        HostMessage::Routing(routing) => presets.config_mut().routing = routing,
+        HostMessage::Preset(index) => {
+            select_preset(presets, index, outputs);
+        },
+        HostMessage::DumpState => return Some(Request::ResendState),
+        HostMessage::UpdateBegin(_) => return Some(Request::EnterBootloader),
+        // only the bootloader takes image data
+        HostMessage::UpdateChunk { .. } | HostMessage::UpdateFinish => {},
+    }
+    None
+}
+
+/// Activates a preset and tells the desktop app about it. Returns `false` if there is no such preset.
+pub fn select_preset<const N: usize>(presets: &mut Presets<N>, index: u8, outputs: &mut impl Outputs) -> bool {
+    let selected = presets.select(index);
+    if selected {
+        outputs.led(LedEvent::Preset(index));
+        outputs.reply(DeviceMessage::Preset(index).encode());
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use expressor_common::config::{ChannelConfig, NUM_PRESETS, RoutingConfig};
+    use expressor_common::midi::MidiMessage;
+    use expressor_common::protocol::{SERIAL_LEN, identity_request};
+    use expressor_common::update::ImageHeader;
+
+    use super::*;
+    use crate::engine::tests::Recorder;
+
+    const IDENTITY: Identity = Identity { version: [0, 3, 0, 0], serial: [b'0'; SERIAL_LEN] };
+
+    fn replies(recorder: &Recorder) -> impl Iterator<Item = DeviceMessage> + '_ {
+        recorder.replies.iter().map(|frame| DeviceMessage::decode(frame).unwrap())
+    }
+
+    #[test]
+    fn test_identity() {
+        let mut presets = Presets::<2>::default();
+        let mut recorder = Recorder::default();
+
+        assert_eq!(handle_sysex(&identity_request(), &IDENTITY, &mut presets, &mut recorder), None);
+        assert_eq!(recorder.replies.len(), 1);
+        assert_eq!(Identity::decode(&recorder.replies[0]), Some(IDENTITY));
+    }
+
+    #[test]
+    fn test_config_round_trip() {
+        let mut presets = Presets::<2>::default();
+        let mut recorder = Recorder::default();
+        let config = ChannelConfig::default().with_label_str("Volume");
+        let routing = RoutingConfig::default().with_preset_channel(Some(15));
+
+        let messages = [
+            HostMessage::ChannelConfig { channel: 1, config },
+            HostMessage::Routing(routing),
+            // out of range, ignored
+            HostMessage::ChannelConfig { channel: 2, config },
+        ];
+        for message in messages {
+            assert_eq!(handle_sysex(&message.encode(), &IDENTITY, &mut presets, &mut recorder), None);
+        }
+        let get_config = HostMessage::GetConfig.encode();
+        assert_eq!(handle_sysex(&get_config, &IDENTITY, &mut presets, &mut recorder), Some(Request::ReportState));
+
+        assert!(replies(&recorder).eq([
+            DeviceMessage::ChannelConfig { channel: 0, config: ChannelConfig::from_index(0) },
+            DeviceMessage::ChannelConfig { channel: 1, config },
+            DeviceMessage::Routing(routing),
+            DeviceMessage::Preset(0),
+        ]));
+    }
+
+    #[test]
+    fn test_presets() {
+        let mut presets = Presets::<2>::default();
+        let mut recorder = Recorder::default();
+        presets.config_mut().routing = RoutingConfig::default().with_preset_channel(Some(3));
+
+        handle_sysex(&HostMessage::Preset(2).encode(), &IDENTITY, &mut presets, &mut recorder);
+        assert_eq!(presets.active(), 2);
+        // every preset has its own configuration
+        assert_eq!(presets.config().routing.preset_channel, None);
+
+        assert!(!select_preset(&mut presets, NUM_PRESETS as u8, &mut recorder));
+        assert_eq!(presets.active(), 2);
+
+        assert_eq!(recorder.leds, [LedEvent::Preset(2)]);
+        assert!(replies(&recorder).eq([DeviceMessage::Preset(2)]));
+
+        assert!(select_preset(&mut presets, 0, &mut recorder));
+        assert_eq!(presets.preset_change(&MidiMessage::ProgramChange(3, 1)), Some(1));
+        assert_eq!(presets.preset_change(&MidiMessage::ProgramChange(2, 1)), None);
+    }
+
+    #[test]
+    fn test_requests() {
+        let mut presets = Presets::<2>::default();
+        let mut recorder = Recorder::default();
+
+        let header = ImageHeader { version: [0, 4, 0, 0], size: 1024, crc: 0, signature: [0; 64] };
+        let begin = HostMessage::UpdateBegin(header).encode();
+        assert_eq!(handle_sysex(&begin, &IDENTITY, &mut presets, &mut recorder), Some(Request::EnterBootloader));
+
+        assert_eq!(handle_sysex(&HostMessage::UpdateFinish.encode(), &IDENTITY, &mut presets, &mut recorder), None);
+        assert!(recorder.replies.is_empty());
+
+        let dump = HostMessage::DumpState.encode();
+        assert_eq!(handle_sysex(&dump, &IDENTITY, &mut presets, &mut recorder), Some(Request::ResendState));
+    }
+}
@@ -0,0 +1,115 @@
+//! Timed ramps: a switch moves the channel value from one end of a ramp to the other over a set time.
+//!
+//! The value moves at a fixed update rate rather than with every sample. It goes out through the mappings like a
+//! pedal movement, so the rate limit of the channel still applies.
+
+use expressor_common::config::RampConfig;
+
+/// Time between two values of a moving ramp, in microseconds.
+pub const UPDATE_INTERVAL: u64 = 10_000;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RampPlayer {
+    /// Time moved along the ramp from its start, in microseconds.
+    progress: u64,
+    /// Direction the ramp moves in, towards the end if `true`, `None` while it stands still.
+    forward: Option<bool>,
+    /// Time the progress was last updated.
+    last: u64,
+    /// Time the next value is due.
+    due: u64,
+}
+
+impl RampPlayer {
+    /// Starts moving towards the end or back to the start, from wherever the ramp is.
+    pub fn start(&mut self, forward: bool, timestamp: u64, config: &RampConfig) {
+        self.advance(timestamp, config);
+        self.forward = Some(forward);
+        self.due = timestamp;
+    }
+
+    /// Starts the ramp over from its start value.
+    pub fn restart(&mut self, timestamp: u64, config: &RampConfig) {
+        self.progress = 0;
+        self.start(true, timestamp, config);
+    }
+
+    /// Returns the value at `timestamp` if the ramp moves and the next update is due.
+    pub fn update(&mut self, timestamp: u64, config: &RampConfig) -> Option<u8> {
+        let forward = self.forward.filter(|_| timestamp >= self.due)?;
+        let duration = config.duration_micros();
+        self.advance(timestamp, config);
+        self.due = timestamp + UPDATE_INTERVAL;
+
+        let position = match duration {
+            0 => if forward { 127 } else { 0 },
+            _ => (self.progress * 127 / duration) as u8,
+        };
+        if position == if forward { 127 } else { 0 } {
+            self.progress = if forward { duration } else { 0 };
+            self.forward = None;
+        }
+        Some(config.value(position))
+    }
+
+    /// Moves the progress on to `timestamp` in the current direction.
+    fn advance(&mut self, timestamp: u64, config: &RampConfig) {
+        let elapsed = timestamp.saturating_sub(self.last);
+        self.progress = match self.forward {
+            Some(true) => (self.progress + elapsed).min(config.duration_micros()),
+            Some(false) => self.progress.saturating_sub(elapsed),
+            None => self.progress.min(config.duration_micros()),
+        };
+        self.last = timestamp;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Runs the ramp sampled every millisecond, starting it at the given times.
+    fn run(config: &RampConfig, starts: &[(u64, bool)], end: u64) -> Vec<(u64, u8)> {
+        let mut player = RampPlayer::default();
+        (0..=end)
+            .filter_map(|millis| {
+                if let Some((_, forward)) = starts.iter().find(|(time, _)| *time == millis) {
+                    player.start(*forward, millis * 1000, config);
+                }
+                player.update(millis * 1000, config).map(|value| (millis, value))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fixed_rate() {
+        let config = RampConfig { duration: 100, ..Default::default() };
+        let values = run(&config, &[(5, true)], 300);
+
+        assert_eq!(values.len(), 11);
+        assert_eq!(values[0], (5, 0));
+        assert_eq!(values[5], (55, 63));
+        assert_eq!(values[10], (105, 127));
+        assert!(values.windows(2).all(|pair| pair[1].0 - pair[0].0 == 10));
+    }
+
+    #[test]
+    fn test_reverse() {
+        let config = RampConfig { start: 20, end: 120, duration: 100, ..Default::default() };
+        // back from half way takes half the time
+        let values = run(&config, &[(0, true), (50, false)], 300);
+
+        assert_eq!(values[5], (50, 69));
+        assert_eq!(values.last(), Some(&(100, 20)));
+    }
+
+    #[test]
+    fn test_zero_duration() {
+        let config = RampConfig { duration: 0, ..Default::default() };
+        assert_eq!(run(&config, &[(3, true)], 100), [(3, 127)]);
+    }
+}
@@ -0,0 +1,71 @@
+//! Plays the macro of a switch, one step after the other with the pauses in between.
+
+use expressor_common::config::MacroSequence;
+use expressor_common::midi::MidiMessage;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SequencePlayer {
+    /// Next step to send, `None` while the macro is not playing.
+    next: Option<usize>,
+    /// Time the next step is due, in microseconds.
+    due: u64,
+}
+
+impl SequencePlayer {
+    /// Starts the macro from its first step, also when it is still playing.
+    pub fn start(&mut self, timestamp: u64) {
+        self.next = Some(0);
+        self.due = timestamp;
+    }
+
+    /// Returns the next step due at `timestamp`. Called until it returns `None`, so steps without a pause go out
+    /// with the same sample.
+    pub fn next(&mut self, timestamp: u64, sequence: &MacroSequence) -> Option<MidiMessage> {
+        let index = self.next.filter(|_| timestamp >= self.due)?;
+        let Some(step) = sequence.steps().get(index) else {
+            self.next = None;
+            return None;
+        };
+
+        self.next = Some(index + 1);
+        self.due += step.delay as u64 * 1000;
+        Some(step.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use expressor_common::config::MacroStep;
+
+    use super::*;
+
+    #[test]
+    fn test_play() {
+        let step = |message, delay| MacroStep::default().with_message(message).with_delay(delay);
+        let sequence = MacroSequence::default()
+            .with_added(step(MidiMessage::ControlChange(0, 0, 1), 0))
+            .with_added(step(MidiMessage::ProgramChange(0, 5), 20))
+            .with_added(step(MidiMessage::ControlChange(0, 7, 100), 0));
+        let mut player = SequencePlayer::default();
+        let mut sent = Vec::new();
+
+        for millis in 0..100 {
+            if millis == 10 {
+                player.start(millis * 1000);
+            }
+            while let Some(message) = player.next(millis * 1000, &sequence) {
+                sent.push((millis, message));
+            }
+        }
+
+        assert_eq!(sent, [
+            (10, MidiMessage::ControlChange(0, 0, 1)),
+            (10, MidiMessage::ProgramChange(0, 5)),
+            (30, MidiMessage::ControlChange(0, 7, 100)),
+        ]);
+    }
+}
@@ -0,0 +1,329 @@
+use expressor_common::config::{
+    ChannelConfig, Gesture, InputConfig, InputMode, MAX_MAPPINGS, Mapping, MessageType, SwitchConfig, Takeover,
+};
+use expressor_common::detect::{Detection, PedalDetector, RAW_MAX};
+use expressor_common::hid::GamepadInput;
+use expressor_common::midi::MidiMessage;
+
+use crate::gesture::GestureDetector;
+use crate::takeover::{self, ControllerValues};
+
+/// Raw levels a switch has to cross to count as pressed and released again. They lie apart, so noise around a single
+/// level does not flip the switch.
+const PRESS_LEVEL: u16 = RAW_MAX / 5 * 3;
+const RELEASE_LEVEL: u16 = RAW_MAX / 5 * 2;
+
+/// Time a switch keeps its state after a change, so contact bounce is not taken for more presses.
+const DEBOUNCE_MICROS: u64 = 10_000;
+
+/// Time a toggle as momentary switch holds its pressed value after a change. Shorter than the debounce time, so every
+/// change gets a pulse of its own.
+const PULSE_MICROS: u64 = 5_000;
+
+#[derive(Default, Clone, Copy)]
+pub struct ChannelStrip {
+    current_value: u8,
+    previous_value: u8,
+    /// Current value in full ADC resolution, for the gamepad axis.
+    fine_value: u16,
+    detector: PedalDetector,
+    detection: Detection,
+    /// Whether the switch is closed, for the gestures and the program navigation.
+    pressed: bool,
+    previously_pressed: bool,
+    /// Time until which `pressed` ignores the input after its last change.
+    debounce_until: u64,
+    /// Whether the switch was read before. The first sample only sets its state, it is no press or release.
+    sampled: bool,
+    /// State of a momentary as toggle switch, flipped by every press.
+    latched: bool,
+    /// Time until which a toggle as momentary switch sends its pressed value.
+    pulse_until: u64,
+    gestures: GestureDetector,
+    /// Value each mapping is held at until the pedal takes it over, see [`takeover`].
+    held: [Option<u8>; MAX_MAPPINGS],
+}
+
+impl ChannelStrip {
+    /// Takes a sample of the input, taken at `timestamp` microseconds.
+    pub fn process(&mut self, timestamp: u64, raw_value: u16, config: &ChannelConfig) {
+        self.detector.push(raw_value);
+        self.debounce(timestamp, raw_value);
+
+        // update the new value
+        let gestures = uses_gestures(config);
+        let config = &config.input;
+        self.previous_value = self.current_value;
+        self.current_value = match config.mode {
+            InputMode::Continuous => config.continuous.apply((raw_value.min(RAW_MAX) >> 5) as u8),
+            // the ramp moves the value, see `set_value`
+            InputMode::Ramp => self.current_value,
+            // the toggle action of the gestures changes the value
+            _ if gestures => self.current_value,
+            InputMode::MomentaryAsToggle => {
+                if self.just_pressed() {
+                    self.latched = !self.latched;
+                }
+                if self.latched { config.switch.pressed_value } else { config.switch.released_value }
+            },
+            InputMode::ToggleAsMomentary => {
+                if self.just_pressed() || self.just_released() {
+                    self.pulse_until = timestamp + PULSE_MICROS;
+                }
+                if timestamp < self.pulse_until { config.switch.pressed_value } else { config.switch.released_value }
+            },
+            _ if self.pressed => config.switch.pressed_value,
+            _ => config.switch.released_value,
+        };
+        self.fine_value = match config.mode {
+            InputMode::Continuous => config.continuous.apply_fine(raw_value),
+            _ => (self.current_value as u32 * RAW_MAX as u32 / 127) as u16,
+        };
+    }
+
+    /// Updates the switch state with hysteresis, holding it for [`DEBOUNCE_MICROS`] after each change.
+    fn debounce(&mut self, timestamp: u64, raw_value: u16) {
+        if !self.sampled {
+            self.sampled = true;
+            self.pressed = raw_value >= PRESS_LEVEL;
+        }
+        self.previously_pressed = self.pressed;
+        let pressed = if self.pressed { raw_value > RELEASE_LEVEL } else { raw_value >= PRESS_LEVEL };
+        if pressed != self.pressed && timestamp >= self.debounce_until {
+            self.pressed = pressed;
+            self.debounce_until = timestamp + DEBOUNCE_MICROS;
+        }
+    }
+
+    /// Runs the gesture detection of a switch input, returning the gesture completed at `timestamp`.
+    pub fn gesture(&mut self, timestamp: u64, config: &ChannelConfig) -> Option<Gesture> {
+        if !uses_gestures(config) {
+            return None;
+        }
+        self.gestures.update(self.pressed, timestamp, &config.gestures)
+    }
+
+    /// Switches between the released and the pressed value.
+    pub fn toggle(&mut self, config: &SwitchConfig) {
+        self.current_value = if self.current_value == config.pressed_value {
+            config.released_value
+        } else {
+            config.pressed_value
+        };
+    }
+
+    /// Whether the switch was closed with the last sample.
+    pub fn just_pressed(&self) -> bool {
+        self.pressed && !self.previously_pressed
+    }
+
+    /// Whether the switch was opened with the last sample.
+    pub fn just_released(&self) -> bool {
+        !self.pressed && self.previously_pressed
+    }
+
+    /// Sets the value of a switch that moves along a ramp, sent like a change of the input.
+    pub fn set_value(&mut self, value: u8) {
+        self.current_value = value;
+        self.fine_value = (value as u32 * RAW_MAX as u32 / 127) as u16;
+    }
+
+    pub fn previous_value(&self) -> u8 {
+        self.previous_value
+    }
+
+    pub fn value(&self) -> u8 {
+        self.current_value
+    }
+
+    pub fn changed(&self) -> bool {
+        self.current_value != self.previous_value
+    }
+
+    /// Gamepad state for the current value: continuous inputs move an axis, switches press a button.
+    pub fn gamepad_input(&self, config: &InputConfig) -> GamepadInput {
+        match config.mode {
+            InputMode::Continuous | InputMode::Ramp => GamepadInput::Axis(self.fine_value),
+            _ => GamepadInput::Button(self.current_value == config.switch.pressed_value),
+        }
+    }
+
+    /// Builds the output messages of all active mappings for the last change. Held mappings move according to the
+    /// takeover mode of the channel.
+    pub fn messages<'a>(&'a mut self, config: &'a ChannelConfig) -> impl Iterator<Item = MidiMessage> + 'a {
+        let (previous_value, current_value) = (self.previous_value, self.current_value);
+
+        self.held.iter_mut().zip(&config.mappings).filter(|(_, mapping)| mapping.enabled).filter_map(
+            move |(held, mapping)| {
+                let previous_value = mapping.apply(previous_value);
+                let value = mapping.apply(current_value);
+                if value == previous_value {
+                    return None;
+                }
+
+                let Some(target) = *held else {
+                    return change_message(mapping, previous_value, value);
+                };
+                let step = takeover::step(
+                    config.takeover,
+                    target,
+                    previous_value,
+                    value,
+                    mapping.minimum_output,
+                    mapping.maximum_output,
+                );
+                *held = step.held;
+                step.output.and_then(|value| value_message(mapping, value))
+            },
+        )
+    }
+
+    /// Builds the messages that set the targets of all active mappings to their current value again. Notes are events
+    /// rather than values, so they are not repeated.
+    pub fn state_messages<'a>(&'a self, config: &'a ChannelConfig) -> impl Iterator<Item = MidiMessage> + 'a {
+        self.held.iter().zip(&config.mappings).filter(|(_, mapping)| mapping.enabled).filter_map(|(held, mapping)| {
+            value_message(mapping, held.unwrap_or_else(|| mapping.apply(self.current_value)))
+        })
+    }
+
+    /// Holds the control change mappings selected by `filter` at the last known value of their controller, if that
+    /// differs from the pedal position. Channels in jump mode are never held.
+    pub fn hold(&mut self, config: &ChannelConfig, controllers: &ControllerValues, filter: impl Fn(&Mapping) -> bool) {
+        for (held, mapping) in self.held.iter_mut().zip(&config.mappings) {
+            if !mapping.enabled || mapping.message_type != MessageType::ControlChange || !filter(mapping) {
+                continue;
+            }
+
+            let value = mapping.apply(self.current_value);
+            *held = match config.takeover {
+                Takeover::Jump => None,
+                _ => controllers.get(mapping.channel, mapping.number).filter(|target| *target != value),
+            };
+        }
+    }
+
+    /// Restarts the pedal detection, e.g. after a jack was replugged.
+    pub fn restart_detection(&mut self) {
+        self.detector.reset();
+        self.detection = Detection::default();
+    }
+
+    /// Returns the detected pedal type if it differs from the last reported one.
+    pub fn detection_changed(&mut self) -> Option<Detection> {
+        let detection = self.detector.detection();
+        if detection == self.detection {
+            return None;
+        }
+
+        self.detection = detection;
+        Some(detection)
+    }
+}
+
+/// Message for a mapping whose value changed from `previous_value` to `value`, if there is anything to send.
+fn change_message(mapping: &Mapping, previous_value: u8, value: u8) -> Option<MidiMessage> {
+    match mapping.message_type {
+        // only send note on and off when crossing zero, the note is already playing otherwise
+        MessageType::Note => match (previous_value, value) {
+            (0, velocity) => Some(MidiMessage::NoteOn(mapping.channel, mapping.number, velocity)),
+            (_, 0) => Some(MidiMessage::NoteOff(mapping.channel, mapping.number, 0)),
+            _ => None,
+        },
+        _ => value_message(mapping, value),
+    }
+}
+
+/// Message that sets the target of a mapping to `value`, `None` for notes.
+fn value_message(mapping: &Mapping, value: u8) -> Option<MidiMessage> {
+    let channel = mapping.channel;
+
+    match mapping.message_type {
+        MessageType::ControlChange => Some(MidiMessage::ControlChange(channel, mapping.number, value)),
+        MessageType::ChannelPressure => Some(MidiMessage::ChannelPressure(channel, value)),
+        MessageType::PitchBend => Some(MidiMessage::PitchBend(channel, (value as u16) << 7 | value as u16)),
+        MessageType::PolyAftertouch => Some(MidiMessage::PolyKeyPressure(channel, mapping.number, value)),
+        MessageType::ProgramChange => Some(MidiMessage::ProgramChange(channel, value)),
+        MessageType::Note => None,
+    }
+}
+
+/// Whether the gestures of a channel replace its switch values. Program navigation and ramps have a fixed use for the
+/// presses.
+fn uses_gestures(config: &ChannelConfig) -> bool {
+    let switch = matches!(config.input.mode, InputMode::Switch | InputMode::MomentaryAsToggle | InputMode::ToggleAsMomentary);
+    switch && config.gestures.enabled()
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use expressor_common::config::{ActionKind, GestureAction};
+
+    use super::*;
+
+    /// Feeds raw samples taken every millisecond and collects the gestures.
+    fn gestures(strip: &mut ChannelStrip, config: &ChannelConfig, samples: Vec<u16>) -> Vec<Gesture> {
+        samples
+            .into_iter()
+            .enumerate()
+            .filter_map(|(millis, raw_value)| {
+                strip.process(millis as u64 * 1000, raw_value, config);
+                strip.gesture(millis as u64 * 1000, config)
+            })
+            .collect()
+    }
+
+    /// Feeds switch states held for the given milliseconds and collects the value changes with their times.
+    fn value_changes(config: &ChannelConfig, states: &[(bool, u64)]) -> Vec<(u64, u8)> {
+        let mut strip = ChannelStrip::default();
+        let samples = states.iter().flat_map(|&(pressed, millis)| (0..millis).map(move |_| pressed));
+        samples
+            .enumerate()
+            .filter_map(|(millis, pressed)| {
+                strip.process(millis as u64 * 1000, if pressed { RAW_MAX } else { 0 }, config);
+                strip.changed().then_some((millis as u64, strip.value()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_momentary_as_toggle() {
+        let config = ChannelConfig::default().with_input_mode(InputMode::MomentaryAsToggle).with_released_value(10);
+        let presses = [(false, 10), (true, 50), (false, 50), (true, 50), (false, 50)];
+
+        // every press flips the value, releases do nothing
+        assert_eq!(value_changes(&config, &presses), [(0, 10), (10, 127), (110, 10)]);
+    }
+
+    #[test]
+    fn test_toggle_as_momentary() {
+        let config = ChannelConfig::default().with_input_mode(InputMode::ToggleAsMomentary);
+        let changes = [(false, 10), (true, 100), (false, 100)];
+
+        // turning the switch on and off again each send a short press
+        assert_eq!(value_changes(&config, &changes), [(10, 127), (15, 0), (110, 127), (115, 0)]);
+
+        // a switch that is already on at the start is no change
+        assert_eq!(value_changes(&config, &[(true, 100)]), []);
+    }
+
+    #[test]
+    fn test_bouncing_press_is_one_tap() {
+        let toggle = GestureAction::default().with_kind(ActionKind::Toggle);
+        let config = ChannelConfig::default()
+            .with_input_mode(InputMode::Switch)
+            .with_gesture_action(Gesture::Tap, toggle)
+            .with_gesture_action(Gesture::DoubleTap, toggle);
+        let mut strip = ChannelStrip::default();
+
+        // the contacts bounce on press and release, and the level wobbles around the middle while held
+        let press = [RAW_MAX, 0, RAW_MAX, 0, RAW_MAX];
+        let held = [RAW_MAX / 2 + 100, RAW_MAX / 2 - 100].repeat(20);
+        let release = press.map(|raw_value| RAW_MAX - raw_value);
+        let samples = [&[0; 10][..], &press, &held, &release, &[0; 500]].concat();
+        assert_eq!(gestures(&mut strip, &config, samples), [Gesture::Tap]);
+    }
+}
@@ -0,0 +1,120 @@
+//! Soft takeover: keeps a pedal from jumping a parameter that was changed elsewhere.
+//!
+//! The core remembers the last value of every controller, from its own messages and from the host. When a preset
+//! change or a controller message from the host leaves a parameter at a different value than the pedal, the mapping
+//! is held at that value until the pedal takes it over again.
+
+use expressor_common::config::Takeover;
+use expressor_common::midi::MidiMessage;
+
+/// Last known value of every controller on every MIDI channel.
+pub struct ControllerValues([[u8; 128]; 16]);
+
+impl Default for ControllerValues {
+    fn default() -> Self {
+        Self([[Self::UNKNOWN; 128]; 16])
+    }
+}
+
+impl ControllerValues {
+    const UNKNOWN: u8 = 0xFF;
+
+    pub fn get(&self, channel: u8, number: u8) -> Option<u8> {
+        let value = self.0[channel as usize & 0x0F][number as usize & 0x7F];
+        (value != Self::UNKNOWN).then_some(value)
+    }
+
+    /// Remembers the value of a control change, other messages are ignored.
+    pub fn record(&mut self, message: &MidiMessage) {
+        if let MidiMessage::ControlChange(channel, number, value) = *message {
+            self.0[channel as usize & 0x0F][number as usize & 0x7F] = value;
+        }
+    }
+}
+
+/// Result of a pedal movement while a mapping is held.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    /// Value the mapping is still held at, `None` once the pedal took over.
+    pub held: Option<u8>,
+    /// Value to send, if the parameter changes.
+    pub output: Option<u8>,
+}
+
+/// Moves the pedal of a mapping held at `target` from `previous` to `value`, all in the output range `minimum` to
+/// `maximum` of the mapping.
+pub fn step(takeover: Takeover, target: u8, previous: u8, value: u8, minimum: u8, maximum: u8) -> Step {
+    let taken_over = Step { held: None, output: (value != target).then_some(value) };
+    let crossed = (previous <= target && target <= value) || (value <= target && target <= previous);
+
+    match takeover {
+        Takeover::Jump => taken_over,
+        _ if crossed => taken_over,
+        Takeover::Pickup => Step { held: Some(target), output: None },
+        Takeover::Scaling => {
+            let moved = scale_towards(target, previous, value, minimum.min(maximum), minimum.max(maximum));
+            if moved == value || (moved < value) != (target < previous) {
+                taken_over
+            } else {
+                Step { held: Some(moved), output: (moved != target).then_some(moved) }
+            }
+        },
+    }
+}
+
+/// Moves `target` towards the end of the range the pedal moves to, by the same share of the remaining distance.
+fn scale_towards(target: u8, previous: u8, value: u8, minimum: u8, maximum: u8) -> u8 {
+    let (target, previous, value) = (target as i32, previous as i32, value as i32);
+    let end = if value > previous { maximum as i32 } else { minimum as i32 };
+    let remaining = (end - previous).abs();
+    if remaining == 0 {
+        return value as u8;
+    }
+
+    let distance = (end - target) * (value - previous).abs();
+    (target + (distance.abs() + remaining / 2) / remaining * distance.signum()) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_controller_values() {
+        let mut values = ControllerValues::default();
+        assert_eq!(values.get(0, 7), None);
+
+        values.record(&MidiMessage::ControlChange(2, 7, 100));
+        values.record(&MidiMessage::NoteOn(2, 7, 90));
+        assert_eq!(values.get(2, 7), Some(100));
+        assert_eq!(values.get(0, 7), None);
+    }
+
+    #[test]
+    fn test_jump() {
+        assert_eq!(step(Takeover::Jump, 100, 10, 11, 0, 127), Step { held: None, output: Some(11) });
+    }
+
+    #[test]
+    fn test_pickup() {
+        assert_eq!(step(Takeover::Pickup, 100, 10, 50, 0, 127), Step { held: Some(100), output: None });
+        assert_eq!(step(Takeover::Pickup, 100, 90, 105, 0, 127), Step { held: None, output: Some(105) });
+        assert_eq!(step(Takeover::Pickup, 100, 110, 100, 0, 127), Step { held: None, output: None });
+        // from above
+        assert_eq!(step(Takeover::Pickup, 20, 60, 30, 0, 127), Step { held: Some(20), output: None });
+        assert_eq!(step(Takeover::Pickup, 20, 30, 10, 0, 127), Step { held: None, output: Some(10) });
+    }
+
+    #[test]
+    fn test_scaling() {
+        // half way to the top of the range, so the parameter moves half the remaining distance as well
+        assert_eq!(step(Takeover::Scaling, 100, 0, 63, 0, 126), Step { held: Some(113), output: Some(113) });
+        // down from the parameter's side meets at the bottom
+        assert_eq!(step(Takeover::Scaling, 40, 80, 40, 0, 127), Step { held: None, output: None });
+        assert_eq!(step(Takeover::Scaling, 40, 80, 60, 0, 127), Step { held: Some(30), output: Some(30) });
+        // both end at the top
+        assert_eq!(step(Takeover::Scaling, 100, 120, 127, 0, 127), Step { held: None, output: Some(127) });
+        // too small a step to move the parameter
+        assert_eq!(step(Takeover::Scaling, 126, 0, 1, 0, 127), Step { held: Some(126), output: None });
+    }
+}
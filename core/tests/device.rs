@@ -0,0 +1,142 @@
+//! Runs the core against in-memory hardware, the way the firmware drives it.
+
+use expressor_common::config::{ChannelConfig, DeviceConfig, InputMode, NUM_PRESETS, RoutingConfig};
+use expressor_common::hid::GamepadInput;
+use expressor_common::led::LedEvent;
+use expressor_common::midi::{MidiMessage, SysexAssembler, UsbPacket, sysex_packets};
+use expressor_common::protocol::{DeviceMessage, FRAME_SIZE, Frame, HostMessage, Identity, SERIAL_LEN};
+use expressor_core::engine::{Engine, HidState, Outputs};
+use expressor_core::hal::{AnalogInput, ConfigStorage, SwitchInput, Switches};
+use expressor_core::presets::Presets;
+use expressor_core::protocol::handle_sysex;
+
+const CHANNELS: usize = 2;
+
+struct Pedals([bool; CHANNELS]);
+
+impl SwitchInput<CHANNELS> for &Pedals {
+    fn pressed(&mut self) -> [bool; CHANNELS] {
+        self.0
+    }
+}
+
+#[derive(Default)]
+struct Storage(Option<[DeviceConfig<CHANNELS>; NUM_PRESETS]>);
+
+impl ConfigStorage<CHANNELS> for Storage {
+    fn load(&mut self) -> Option<[DeviceConfig<CHANNELS>; NUM_PRESETS]> {
+        self.0
+    }
+
+    fn store(&mut self, configs: &[DeviceConfig<CHANNELS>; NUM_PRESETS]) {
+        self.0 = Some(*configs);
+    }
+}
+
+#[derive(Default)]
+struct Host {
+    midi: Vec<UsbPacket>,
+    leds: Vec<LedEvent>,
+    gamepad: [Option<GamepadInput>; CHANNELS],
+}
+
+impl Outputs for Host {
+    fn midi(&mut self, channel: usize, message: MidiMessage, _config: &ChannelConfig, routing: &RoutingConfig) {
+        self.midi.push(message.to_usb_packet(RoutingConfig::MERGED_CABLE));
+        if routing.usb_cables {
+            self.midi.push(message.to_usb_packet(RoutingConfig::channel_cable(channel)));
+        }
+    }
+
+    fn midi_ordered(&mut self, channel: usize, message: MidiMessage, config: &ChannelConfig, routing: &RoutingConfig) {
+        self.midi(channel, message, config, routing);
+    }
+
+    fn hid(&mut self, channel: usize, state: HidState) {
+        self.gamepad[channel] = state.gamepad;
+    }
+
+    fn led(&mut self, event: LedEvent) {
+        self.leds.push(event);
+    }
+
+    fn reply(&mut self, frame: Frame) {
+        self.midi.extend(sysex_packets(RoutingConfig::MERGED_CABLE, &frame));
+    }
+
+    fn preset(&mut self, _index: u8) {}
+}
+
+/// Passes a host message through the USB packets, as the device receives it.
+fn send(message: HostMessage, presets: &mut Presets<CHANNELS>, host: &mut Host) {
+    let identity = Identity { version: [0, 3, 0, 0], serial: [b'0'; SERIAL_LEN] };
+    let mut sysex = SysexAssembler::<FRAME_SIZE>::new();
+    for packet in sysex_packets(0, &message.encode()) {
+        if let Some(frame) = sysex.push(&packet) {
+            handle_sysex(frame, &identity, presets, host);
+        }
+    }
+}
+
+/// Reassembles the protocol replies the host received.
+fn replies(host: &Host) -> Vec<DeviceMessage> {
+    let mut sysex = SysexAssembler::<FRAME_SIZE>::new();
+    host.midi.iter().filter_map(|packet| sysex.push(packet).and_then(DeviceMessage::decode)).collect()
+}
+
+#[test]
+fn test_switch_session() {
+    let mut storage = Storage::default();
+    let mut presets = Presets::load(&mut storage);
+    let mut engine = Engine::<CHANNELS>::new(1000);
+    let mut host = Host::default();
+
+    let switch = ChannelConfig::from_index(0).with_input_mode(InputMode::Switch);
+    send(HostMessage::ChannelConfig { channel: 1, config: switch }, &mut presets, &mut host);
+    send(HostMessage::Routing(RoutingConfig::default().with_usb_cables(true)), &mut presets, &mut host);
+    presets.store(&mut storage);
+
+    let mut pedals = Pedals([false, true]);
+    // held for longer than the debounce time
+    for millis in 0..=20 {
+        pedals.0[1] = millis < 20;
+        let values = futures::executor::block_on(Switches(&pedals).sample());
+        engine.process(millis * 1000, &values, presets.config(), &mut host);
+    }
+
+    let cc = |value| MidiMessage::ControlChange(0, 0, value);
+    assert_eq!(host.midi, [
+        cc(127).to_usb_packet(0),
+        cc(127).to_usb_packet(2),
+        cc(0).to_usb_packet(0),
+        cc(0).to_usb_packet(2),
+    ]);
+    assert_eq!(host.gamepad, [None, None]);
+    assert_eq!(host.leds, [
+        LedEvent::Latch { channel: 1, latched: true },
+        LedEvent::Latch { channel: 1, latched: false },
+    ]);
+
+    // the stored presets come back after a restart
+    let presets = Presets::load(&mut storage);
+    assert_eq!(presets.config().channels[1], switch);
+    assert!(presets.config().routing.usb_cables);
+}
+
+#[test]
+fn test_get_config() {
+    let mut presets = Presets::load(&mut Storage::default());
+    let mut host = Host::default();
+
+    send(HostMessage::Preset(1), &mut presets, &mut host);
+    send(HostMessage::GetConfig, &mut presets, &mut host);
+
+    assert_eq!(replies(&host), [
+        DeviceMessage::Preset(1),
+        DeviceMessage::ChannelConfig { channel: 0, config: ChannelConfig::from_index(0) },
+        DeviceMessage::ChannelConfig { channel: 1, config: ChannelConfig::from_index(1) },
+        DeviceMessage::Routing(RoutingConfig::default()),
+        DeviceMessage::Preset(1),
+    ]);
+    assert_eq!(host.leds, [LedEvent::Preset(1)]);
+}
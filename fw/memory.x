@@ -0,0 +1,8 @@
+/* The bootloader occupies the first 40K of the flash, followed by the 2K page with the image header,
+   see `expressor_common::update::layout`. */
+MEMORY
+{
+    FLASH : ORIGIN = 0x0800A800, LENGTH =  86K
+    /* the last word holds the update request for the bootloader, the stack stays 8-byte aligned */
+    RAM   : ORIGIN = 0x20000000, LENGTH =  32K - 8
+}
@@ -0,0 +1,34 @@
+use core::cell::RefCell;
+
+use defmt::*;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use expressor_common::detect::RangeCalibration;
+use expressor_common::led::LedEvent;
+
+use crate::{NUM_CHANNELS, status};
+
+/// Channel whose input range is being recorded.
+static CALIBRATION: Mutex<ThreadModeRawMutex, RefCell<Option<(usize, RangeCalibration)>>> = Mutex::new(RefCell::new(None));
+
+/// Starts recording the input range of a channel, replacing a calibration in progress.
+pub fn start(channel: usize) {
+    info!("Calibrating channel {}", channel);
+    CALIBRATION.lock(|calibration| calibration.replace(Some((channel, RangeCalibration::new()))));
+    status::notify(LedEvent::Calibration(true));
+}
+
+pub fn sample(values: &[u16; NUM_CHANNELS]) {
+    CALIBRATION.lock(|calibration| {
+        if let Some((channel, calibration)) = calibration.borrow_mut().as_mut() {
+            calibration.push(values[*channel]);
+        }
+    });
+}
+
+/// Stops the calibration and returns the channel with its recorded range, or `None` if no calibration was running.
+pub fn finish() -> Option<(usize, Option<(u8, u8)>)> {
+    let (channel, calibration) = CALIBRATION.lock(|calibration| calibration.take())?;
+    status::notify(LedEvent::Calibration(false));
+    Some((channel, calibration.input_range()))
+}
@@ -0,0 +1,166 @@
+use core::cell::Cell;
+use core::fmt::Write;
+
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_time::{Duration, Instant, Ticker};
+use embassy_usb::class::cdc_acm::CdcAcmClass;
+use embassy_usb::driver::{Driver, EndpointError};
+use expressor_common::shell::{self, Command, HELP, LineBuffer, ShellError};
+use heapless::{String, Vec};
+
+use crate::{NUM_CHANNELS, calibration, identity, presets};
+
+/// Latest raw ADC values, for streaming.
+static LATEST: Mutex<ThreadModeRawMutex, Cell<[u16; NUM_CHANNELS]>> = Mutex::new(Cell::new([0; NUM_CHANNELS]));
+
+/// Interval between two lines of streamed ADC values.
+const STREAM_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Long enough for the curve lines of a config dump.
+const LINE_SIZE: usize = 96;
+const PROMPT: &str = "> ";
+
+pub fn sample(values: &[u16; NUM_CHANNELS]) {
+    LATEST.lock(|latest| latest.set(*values));
+}
+
+/// Line based command shell on the CDC-ACM serial interface. Typed characters are echoed, so any terminal program
+/// works without local echo.
+pub async fn console_session<'d, D: Driver<'d>>(class: &mut CdcAcmClass<'d, D>) -> Result<(), EndpointError> {
+    let mut buf = [0; 64];
+    let mut line = LineBuffer::<LINE_SIZE>::new();
+    let mut streaming = false;
+    let mut ticker = Ticker::every(STREAM_INTERVAL);
+
+    write(class, "Midi Expressor console, type 'help' for a list of commands\n").await?;
+    write(class, PROMPT).await?;
+
+    loop {
+        let len = if streaming {
+            match select(class.read_packet(&mut buf), ticker.next()).await {
+                Either::First(len) => len?,
+                Either::Second(()) => {
+                    let mut out = String::<64>::new();
+                    let values = LATEST.lock(|latest| latest.get());
+                    for value in values {
+                        let _ = write!(out, "{:5}", value);
+                    }
+                    let _ = out.push('\n');
+                    write(class, &out).await?;
+                    continue;
+                },
+            }
+        } else {
+            class.read_packet(&mut buf).await?
+        };
+
+        for &byte in &buf[..len] {
+            match byte {
+                0x08 | 0x7F => write(class, "\x08 \x08").await?,
+                b'\r' | b'\n' => {},
+                _ => class.write_packet(&[byte]).await?,
+            }
+
+            let Some(command) = line.push(byte) else {
+                continue;
+            };
+            write(class, "\n").await?;
+
+            match Command::parse(command, NUM_CHANNELS) {
+                Ok(Some(Command::Stream(on))) => streaming = on,
+                Ok(Some(command)) => run(class, command).await?,
+                Ok(None) => {},
+                Err(error) => error_line(class, error).await?,
+            }
+            write(class, PROMPT).await?;
+        }
+    }
+}
+
+async fn run<'d, D: Driver<'d>>(class: &mut CdcAcmClass<'d, D>, command: Command<'_>) -> Result<(), EndpointError> {
+    let mut out = String::<2048>::new();
+
+    match command {
+        Command::Help => return write(class, HELP).await,
+        Command::Version => {
+            let identity = identity();
+            let [major, minor, patch, _] = identity.version;
+            let _ = writeln!(out, "{}.{}.{} serial {}", major, minor, patch, identity.serial_str());
+        },
+        Command::Uptime => {
+            let seconds = Instant::now().as_secs();
+            let _ = writeln!(out, "{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60);
+        },
+        Command::Config => {
+            let config = presets::with(|config| *config);
+            let _ = writeln!(out, "# preset {}", presets::active() + 1);
+            write(class, &out).await?;
+
+            for (channel, channel_config) in config.channels.iter().enumerate() {
+                out.clear();
+                let _ = shell::write_channel(&mut out, channel, channel_config);
+                write(class, &out).await?;
+            }
+            return Ok(());
+        },
+        Command::Set { channel, param, value } => {
+            let config = presets::with(|config| config.channels[channel]);
+            match shell::set(config, param, value) {
+                Ok(config) => presets::set_channel(channel, config),
+                Err(error) => return error_line(class, error).await,
+            }
+        },
+        Command::Calibrate(channel) => {
+            calibration::start(channel);
+            let _ = writeln!(out, "move the pedal through its full range, then type 'calibrate done'");
+        },
+        Command::CalibrateDone => match calibration::finish() {
+            None => {
+                let _ = writeln!(out, "no calibration running");
+            },
+            Some((_, None)) => {
+                let _ = writeln!(out, "the pedal was not moved far enough, range unchanged");
+            },
+            Some((channel, Some((minimum, maximum)))) => {
+                let config = presets::with(|config| config.channels[channel])
+                    .with_minimum_input(minimum)
+                    .with_maximum_input(maximum);
+                presets::set_channel(channel, config);
+                let _ = writeln!(out, "channel {} input range {} - {}", channel + 1, minimum, maximum);
+            },
+        },
+        Command::Stream(_) => {},
+    }
+
+    write(class, &out).await
+}
+
+async fn error_line<'d, D: Driver<'d>>(class: &mut CdcAcmClass<'d, D>, error: ShellError) -> Result<(), EndpointError> {
+    write(class, "error: ").await?;
+    write(class, error.message()).await?;
+    write(class, "\n").await
+}
+
+/// Writes text with CRLF line endings. Packets are kept below the maximum packet size, so the host never waits for a
+/// zero length packet.
+async fn write<'d, D: Driver<'d>>(class: &mut CdcAcmClass<'d, D>, text: &str) -> Result<(), EndpointError> {
+    let mut packet = Vec::<u8, 64>::new();
+
+    for byte in text.bytes() {
+        if packet.len() + 2 >= packet.capacity() {
+            class.write_packet(&packet).await?;
+            packet.clear();
+        }
+        if byte == b'\n' {
+            let _ = packet.push(b'\r');
+        }
+        let _ = packet.push(byte);
+    }
+
+    if !packet.is_empty() {
+        class.write_packet(&packet).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,120 @@
+use defmt::*;
+use embassy_futures::select::{Either, select};
+use embassy_stm32::mode::Async;
+use embassy_stm32::usart::{self, UartRx, UartTx};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Instant};
+use expressor_common::led::{ErrorCode, LedEvent};
+use expressor_common::midi::{MidiEvent, MidiParser, RunningStatusEncoder};
+
+use crate::output::DIN_OUTPUT;
+use crate::{feedback, presets, status};
+
+/// DIN MIDI line speed.
+pub const BAUDRATE: u32 = 31_250;
+
+/// Receivers that were plugged in while we were streaming only pick up the status again after this long a pause.
+const RUNNING_STATUS_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Size of the DMA ring buffer for incoming bytes, enough for about 20 ms of a saturated line.
+const RX_BUFFER_SIZE: usize = 64;
+
+/// Forwarded events waiting for the DIN output. Unlike pedal messages, these are never coalesced, so every note of a
+/// keyboard in the chain arrives.
+pub static DIN_THRU: Channel<ThreadModeRawMutex, MidiEvent, 32> = Channel::new();
+
+/// DIN input events waiting for the USB output.
+pub static USB_THRU: Channel<ThreadModeRawMutex, MidiEvent, 32> = Channel::new();
+
+pub fn uart_config() -> usart::Config {
+    let mut config = usart::Config::default();
+    config.baudrate = BAUDRATE;
+    config
+}
+
+/// Queues a forwarded event without blocking.
+pub fn forward(queue: &Channel<ThreadModeRawMutex, MidiEvent, 32>, event: MidiEvent) {
+    if queue.try_send(event).is_err() {
+        warn!("Thru queue full, dropping {}", event);
+        status::notify(LedEvent::Error(ErrorCode::OutputOverflow));
+    }
+}
+
+/// Writes forwarded events and the DIN output queue to the MIDI out jack. Events are merged between whole messages,
+/// using running status for consecutive messages with the same status.
+#[embassy_executor::task]
+pub async fn din_output_task(mut tx: UartTx<'static, Async>) {
+    let mut encoder = RunningStatusEncoder::new();
+    let mut last_sent = Instant::MIN;
+
+    loop {
+        // forwarded events go first, pedal messages are coalesced anyway
+        let event = match select(DIN_THRU.receive(), DIN_OUTPUT.receive()).await {
+            Either::First(event) => event,
+            Either::Second(message) => MidiEvent::Message(message),
+        };
+
+        if last_sent.elapsed() > RUNNING_STATUS_TIMEOUT {
+            encoder.reset();
+        }
+
+        status::activity();
+
+        let mut bytes = [0; 3];
+        let len = match event {
+            MidiEvent::Message(message) => encoder.encode(&message, &mut bytes),
+            MidiEvent::Realtime(byte) => {
+                bytes[0] = byte;
+                1
+            },
+        };
+
+        if let Err(e) = tx.write(&bytes[..len]).await {
+            warn!("DIN write failed: {}", e);
+            encoder.reset();
+        }
+        last_sent = Instant::now();
+    }
+}
+
+/// Parses the MIDI in jack and forwards the events according to the routing config.
+#[embassy_executor::task]
+pub async fn din_input_task(rx: UartRx<'static, Async>) {
+    let mut dma_buffer = [0u8; RX_BUFFER_SIZE];
+    let mut rx = rx.into_ring_buffered(&mut dma_buffer);
+    let mut parser = MidiParser::new();
+    let mut bytes = [0u8; RX_BUFFER_SIZE];
+
+    loop {
+        let len = match rx.read(&mut bytes).await {
+            Ok(len) => len,
+            Err(e) => {
+                warn!("DIN read failed: {}", e);
+                status::notify(LedEvent::Error(ErrorCode::DinInput));
+                parser.reset();
+                continue;
+            },
+        };
+
+        let routing = presets::with(|config| config.routing);
+
+        for event in bytes[..len].iter().filter_map(|byte| parser.push(*byte)) {
+            status::activity();
+
+            if let MidiEvent::Message(message) = event {
+                if presets::handle(&message) {
+                    continue;
+                }
+                feedback(message);
+            }
+
+            if routing.din_to_usb {
+                forward(&USB_THRU, event);
+            }
+            if routing.din_thru {
+                forward(&DIN_THRU, event);
+            }
+        }
+    }
+}
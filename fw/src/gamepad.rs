@@ -0,0 +1,46 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_usb::class::hid::HidWriter;
+use embassy_usb::driver::{Driver, EndpointError};
+use expressor_common::hid::{GAMEPAD_REPORT_SIZE, GamepadInput, GamepadReport};
+
+use crate::NUM_CHANNELS;
+
+/// Gamepad input of each channel, `None` for channels that do not feed the gamepad.
+static INPUTS: Mutex<ThreadModeRawMutex, RefCell<[Option<GamepadInput>; NUM_CHANNELS]>> = Mutex::new(RefCell::new([None; NUM_CHANNELS]));
+static CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Sets the axis or button of a channel, or returns it to rest with `None`.
+pub fn set(channel: usize, input: Option<GamepadInput>) {
+    let changed = INPUTS.lock(|inputs| {
+        let mut inputs = inputs.borrow_mut();
+        let changed = inputs[channel] != input;
+        inputs[channel] = input;
+        changed
+    });
+
+    if changed {
+        CHANGED.signal(());
+    }
+}
+
+fn report() -> GamepadReport {
+    INPUTS.lock(|inputs| GamepadReport::new(inputs.borrow().iter()))
+}
+
+/// Sends a report whenever an axis or button changes, starting with the current state.
+pub async fn gamepad_session<'d, D: Driver<'d>>(writer: &mut HidWriter<'d, D, GAMEPAD_REPORT_SIZE>) -> Result<(), EndpointError> {
+    let mut last = None;
+
+    loop {
+        let report = report();
+        if last != Some(report) {
+            writer.write(&report.to_bytes()).await?;
+            last = Some(report);
+        }
+        CHANGED.wait().await;
+    }
+}
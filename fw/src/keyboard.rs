@@ -0,0 +1,46 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_usb::class::hid::HidWriter;
+use embassy_usb::driver::{Driver, EndpointError};
+use expressor_common::hid::{KeyCombo, KeyboardReport, keyboard_report};
+
+use crate::NUM_CHANNELS;
+
+/// Key combination currently held by each channel.
+static HELD: Mutex<ThreadModeRawMutex, RefCell<[Option<KeyCombo>; NUM_CHANNELS]>> = Mutex::new(RefCell::new([None; NUM_CHANNELS]));
+static CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Holds `key` for a channel, or releases the channel's key with `None`.
+pub fn hold(channel: usize, key: Option<KeyCombo>) {
+    let changed = HELD.lock(|held| {
+        let mut held = held.borrow_mut();
+        let changed = held[channel] != key;
+        held[channel] = key;
+        changed
+    });
+
+    if changed {
+        CHANGED.signal(());
+    }
+}
+
+fn report() -> KeyboardReport {
+    HELD.lock(|held| keyboard_report(held.borrow().iter().flatten()))
+}
+
+/// Sends a report whenever the held keys change, starting with the current state.
+pub async fn keyboard_session<'d, D: Driver<'d>>(writer: &mut HidWriter<'d, D, 8>) -> Result<(), EndpointError> {
+    let mut last = None;
+
+    loop {
+        let report = report();
+        if last != Some(report) {
+            writer.write(&report).await?;
+            last = Some(report);
+        }
+        CHANGED.wait().await;
+    }
+}
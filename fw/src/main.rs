@@ -5,53 +5,97 @@ use core::sync::atomic::{AtomicBool, Ordering};
 
 use defmt::*;
 use embassy_executor::Spawner;
-use embassy_futures::join::join;
+use embassy_futures::join::{join4, join5};
+use embassy_futures::select::{Either4, select_array, select4};
 use embassy_stm32::gpio::{AnyPin, Level, Output, Speed};
+use embassy_stm32::usart::Uart;
 use embassy_stm32::usb::Driver;
-use embassy_stm32::{Config, bind_interrupts, peripherals, usb};
-use embassy_stm32::adc::{Adc, AdcChannel, AdcConfig, AnyAdcChannel, Resolution, SampleTime};
+use embassy_stm32::{Config, bind_interrupts, peripherals, uid, usart, usb};
+use embassy_stm32::adc::{Adc, AdcChannel, AdcConfig, Resolution};
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 use embassy_sync::channel::Channel;
-use embassy_usb::class::midi::MidiClass;
+use embassy_sync::signal::Signal;
+use embassy_usb::class::cdc_acm::{self, CdcAcmClass};
+use embassy_usb::class::hid::{self, HidWriter};
 use embassy_usb::driver::EndpointError;
 use embassy_usb::{Builder, Handler};
 use embassy_usb::control::OutResponse;
-use midi_types::MidiMessage;
-use midi_types::status::CONTROL_CHANGE;
+use expressor_common::config::RoutingConfig;
+use expressor_common::hid::CONSUMER_DESCRIPTOR;
+#[cfg(feature = "gamepad")]
+use expressor_common::hid::{GAMEPAD_DESCRIPTOR, GAMEPAD_REPORT_SIZE};
+use expressor_common::led::LedEvent;
+use expressor_common::midi::{MidiEvent, MidiMessage, SysexAssembler, UsbPacket, packet_cable, sysex_packets};
+use expressor_common::protocol::{FRAME_SIZE, Frame, Identity, device_release};
+use expressor_core::FIRMWARE_VERSION;
+use expressor_core::engine::Engine;
+use expressor_core::hal::{MidiIn, MidiOut};
+use expressor_core::protocol::{self, Request};
 use usbd_hid::descriptor::{KeyboardReport, SerializedDescriptor};
-use crate::channel_strip::ChannelStrip;
+use crate::din::{DIN_THRU, USB_THRU};
+use crate::output::{USB_CABLE_OUTPUTS, USB_OUTPUT};
+use crate::platform::{AdcInput, DeviceOutputs, StatusLeds};
+use crate::sampler::{FRAMES, sampler_task};
+use crate::usb_midi::JackNames;
 
 use {defmt_rtt as _, panic_probe as _};
 
-mod channel_strip;
+mod calibration;
+mod console;
+mod din;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+mod keyboard;
+mod media;
+mod output;
+mod platform;
+mod presets;
+mod sampler;
+mod status;
+mod update;
+mod usb_midi;
 
 const NUM_CHANNELS: usize = 4;
 
+/// Rate at which all inputs are sampled, in Hz.
+const SAMPLE_RATE: u64 = 1000;
+
 bind_interrupts!(struct Irqs {
     USB_LP => usb::InterruptHandler<peripherals::USB>;
+    USART1 => usart::InterruptHandler<peripherals::USART1>;
 });
 
 #[embassy_executor::main]
-async fn main(_spawner: Spawner) {
+async fn main(spawner: Spawner) {
     let config = Config::default();
     let p = embassy_stm32::init(config);
 
     info!("Initializing LED outputs...");
-    let mut led1 = Output::new(p.PB12, Level::Low, Speed::Low);
-    let mut led2 = Output::new(p.PB13, Level::Low, Speed::Low);
+    let leds = StatusLeds {
+        status: Output::new(p.PB12, Level::Low, Speed::Low),
+        activity: Output::new(p.PB13, Level::Low, Speed::Low),
+    };
+    spawner.must_spawn(status::led_task(leds));
 
     info!("Initializing ADC...");
     let config = AdcConfig {
         resolution: Some(Resolution::BITS12),
         ..AdcConfig::default()
     };
-    let mut adc = Adc::new(p.ADC1, config);
-    let mut adc_channels = [
+    let adc = Adc::new(p.ADC1, config);
+    let adc_channels = [
         p.PA0.degrade_adc(),
         p.PA1.degrade_adc(),
         p.PA2.degrade_adc(),
         p.PA3.degrade_adc(),
     ];
+    spawner.must_spawn(sampler_task(AdcInput::new(adc, p.DMA1_CH1, adc_channels), SAMPLE_RATE));
+
+    info!("Initializing DIN MIDI...");
+    let din = unwrap!(Uart::new(p.USART1, p.PA10, p.PA9, Irqs, p.DMA1_CH2, p.DMA1_CH3, din::uart_config()));
+    let (din_tx, din_rx) = din.split();
+    spawner.must_spawn(din::din_output_task(din_tx));
+    spawner.must_spawn(din::din_input_task(din_rx));
 
     info!("Initializing USB");
     let driver = Driver::new(
@@ -64,7 +108,8 @@ async fn main(_spawner: Spawner) {
     let mut config = embassy_usb::Config::new(0x1209, 0xd2b3);
     config.manufacturer = Some("schlegelflegel");
     config.product = Some("Midi Expressor");
-    config.serial_number = Some("0.3.0");
+    config.serial_number = Some(uid::uid_hex());
+    config.device_release = device_release(FIRMWARE_VERSION);
     config.max_power = 100;
     config.max_packet_size_0 = 64;
 
@@ -77,9 +122,15 @@ async fn main(_spawner: Spawner) {
 
     // Create embassy-usb DeviceBuilder using the driver and config.
     // It needs some buffers for building the descriptors.
-    let mut config_descriptor = [0; 256];
+    let mut config_descriptor = [0; 512];
     let mut bos_descriptor = [0; 256];
     let mut control_buf = [0; 64];
+    let mut keyboard_state = hid::State::new();
+    #[cfg(feature = "gamepad")]
+    let mut gamepad_state = hid::State::new();
+    let mut media_state = hid::State::new();
+    let mut console_state = cdc_acm::State::new();
+    let mut jack_names = JackNames::new();
 
     let mut builder = Builder::new(
         driver,
@@ -89,37 +140,119 @@ async fn main(_spawner: Spawner) {
         &mut [], // no msos descriptors
         &mut control_buf,
     );
-    let mut midi_class = MidiClass::new(&mut builder, 1, 1, 64);
+    let (mut midi_sender, mut midi_receiver) = usb_midi::new(&mut builder, &mut jack_names, 64);
+
+    let mut keyboard_writer = HidWriter::<_, 8>::new(&mut builder, &mut keyboard_state, hid::Config {
+        report_descriptor: KeyboardReport::desc(),
+        request_handler: None,
+        poll_ms: 10,
+        max_packet_size: 8,
+    });
+    // games pick up any joystick, so the gamepad is only part of builds that ask for it
+    #[cfg(feature = "gamepad")]
+    let mut gamepad_writer = HidWriter::<_, GAMEPAD_REPORT_SIZE>::new(&mut builder, &mut gamepad_state, hid::Config {
+        report_descriptor: GAMEPAD_DESCRIPTOR,
+        request_handler: None,
+        poll_ms: 5,
+        max_packet_size: 8,
+    });
+    let mut media_writer = HidWriter::<_, 2>::new(&mut builder, &mut media_state, hid::Config {
+        report_descriptor: CONSUMER_DESCRIPTOR,
+        request_handler: None,
+        poll_ms: 10,
+        max_packet_size: 8,
+    });
+    let mut console_class = CdcAcmClass::new(&mut builder, &mut console_state, 64);
     let mut usb = builder.build();
 
-    let mut channel_strips = [ChannelStrip::default(); NUM_CHANNELS];
+    let mut engine = Engine::<NUM_CHANNELS>::new(SAMPLE_RATE);
 
-    let mut usb_fut = usb.run();
+    let usb_fut = usb.run();
 
-    let mut midi_fut = async {
+    let midi_out_fut = async {
         loop {
-            midi_class.wait_connection().await;
+            midi_sender.wait_connection().await;
             info!("USB Connected");
-            let _ = midi_session(&mut midi_class).await;
+            status::notify(LedEvent::UsbConnected);
+            RESEND.signal(());
+            let _ = midi_session(&mut midi_sender).await;
             info!("USB Disconnected");
+            status::notify(LedEvent::UsbDisconnected);
         }
     };
 
-    // let in_fut = async {
-    //     loop {
-    //         for (i, channel_strip) in channel_strips.iter_mut().enumerate() {
-    //             let raw_value = adc.blocking_read(&mut adc_channels[i], SampleTime::CYCLES24_5);
+    let keyboard_fut = async {
+        loop {
+            keyboard_writer.ready().await;
+            let _ = keyboard::keyboard_session(&mut keyboard_writer).await;
+        }
+    };
 
-    //             channel_strip.process(raw_value);
+    #[cfg(feature = "gamepad")]
+    let gamepad_fut = async {
+        loop {
+            gamepad_writer.ready().await;
+            let _ = gamepad::gamepad_session(&mut gamepad_writer).await;
+        }
+    };
+    #[cfg(not(feature = "gamepad"))]
+    let gamepad_fut = core::future::pending::<()>();
 
-    //             if channel_strip.changed() {
-    //                 info!("Channel {}: Value = {}", i, channel_strip.value())
-    //             }
-    //         }
-    //     }
-    // };
+    let media_fut = async {
+        loop {
+            media_writer.ready().await;
+            let _ = media::media_session(&mut media_writer).await;
+        }
+    };
 
-    join(usb_fut, midi_fut).await;
+    let console_fut = async {
+        loop {
+            console_class.wait_connection().await;
+            let _ = console::console_session(&mut console_class).await;
+        }
+    };
+
+    let midi_in_fut = async {
+        loop {
+            midi_receiver.wait_connection().await;
+            let _ = midi_receive(&mut midi_receiver).await;
+        }
+    };
+
+    let process_fut = async {
+        let mut preset = presets::active();
+        loop {
+            let frame = FRAMES.receive().await;
+
+            console::sample(&frame.values);
+            calibration::sample(&frame.values);
+
+            let mut outputs = DeviceOutputs::new();
+            let resend = RESEND.try_take().is_some();
+            let report = REPORT.try_take().is_some();
+            presets::lock(|presets| {
+                let config = presets.config();
+                if presets.active() != preset {
+                    preset = presets.active();
+                    engine.preset_changed(config);
+                }
+                while let Ok(message) = FEEDBACK.try_receive() {
+                    engine.feedback(&message, config);
+                }
+
+                engine.process(frame.timestamp, &frame.values, config, &mut outputs);
+                if resend {
+                    engine.resend(frame.timestamp, config, &mut outputs);
+                }
+                if report {
+                    engine.report(config, &mut outputs);
+                }
+            });
+            outputs.flush();
+        }
+    };
+
+    join5(usb_fut, midi_out_fut, midi_in_fut, join4(keyboard_fut, gamepad_fut, media_fut, console_fut), process_fut).await;
 }
 
 pub struct Disconnected;
@@ -133,19 +266,102 @@ impl From<EndpointError> for Disconnected {
     }
 }
 
-static MIDI_QUEUE: Channel<ThreadModeRawMutex, MidiMessage, 10> = Channel::new();
+/// Protocol frames (e.g. pedal detection reports) going to the desktop app.
+static SYSEX_QUEUE: Channel<ThreadModeRawMutex, Frame, 8> = Channel::new();
+
+/// Asks the processing loop to send the current channel values again, after a connect or a state dump request.
+static RESEND: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Asks the processing loop to tell the desktop app the current programs, after it read the config.
+static REPORT: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Controller values and programs from the host and the MIDI input, waiting for the soft takeover and the program
+/// navigation in the processing loop.
+static FEEDBACK: Channel<ThreadModeRawMutex, MidiMessage, 16> = Channel::new();
+
+/// Passes a received message on to the soft takeover and the program navigation. Only control and program changes
+/// matter there.
+pub fn feedback(message: MidiMessage) {
+    if let MidiMessage::ControlChange(..) | MidiMessage::ProgramChange(..) = message {
+        let _ = FEEDBACK.try_send(message);
+    }
+}
+
+pub async fn midi_session(midi: &mut impl MidiOut<Error = EndpointError>) -> Result<(), Disconnected> {
+    loop {
+        let cables = select_array(USB_CABLE_OUTPUTS.each_ref().map(|output| output.receive()));
+        let next = select4(USB_THRU.receive(), USB_OUTPUT.receive(), SYSEX_QUEUE.receive(), cables).await;
+        status::activity();
+
+        match next {
+            Either4::First(event) => {
+                midi.send(&event.to_usb_packet(RoutingConfig::MERGED_CABLE)).await?;
+            }
+            Either4::Second(msg) => {
+                midi.send(&msg.to_usb_packet(RoutingConfig::MERGED_CABLE)).await?;
+            }
+            Either4::Third(frame) => {
+                for packet in sysex_packets(RoutingConfig::MERGED_CABLE, &frame) {
+                    midi.send(&packet).await?;
+                }
+            }
+            Either4::Fourth((msg, channel)) => {
+                midi.send(&msg.to_usb_packet(RoutingConfig::channel_cable(channel))).await?;
+            }
+        }
+    }
+}
+
+pub async fn midi_receive(midi: &mut impl MidiIn<Error = EndpointError>) -> Result<(), Disconnected> {
+    let mut packets = [UsbPacket::default(); 16];
+    let mut sysex = SysexAssembler::<FRAME_SIZE>::new();
 
-pub async fn midi_session<'d, T: usb::Instance + 'd>(midi: &mut MidiClass<'d, Driver<'d, T>>) -> Result<(), Disconnected> {
     loop {
-        let msg = MIDI_QUEUE.receive().await;
-        match msg {
-            MidiMessage::ControlChange(channel, control, value) => {
-                let cin = CONTROL_CHANGE >> 4;
-                let status = CONTROL_CHANGE << 4 | u8::from(channel);
-                let packet = [CONTROL_CHANGE >> 4, status, u8::from(control), u8::from(value)];
-                midi.write_packet(&packet).await?;
+        let len = midi.receive(&mut packets).await?;
+
+        for packet in &packets[..len] {
+            status::activity();
+
+            // the protocol runs on the merged cable only, so sysex on the other cables can't corrupt a frame
+            if packet_cable(packet) == RoutingConfig::MERGED_CABLE
+                && let Some(frame) = sysex.push(packet)
+            {
+                handle_sysex(frame).await;
+                continue;
+            }
+
+            let Some(event) = MidiEvent::from_usb_packet(packet) else {
+                continue;
+            };
+
+            if let MidiEvent::Message(message) = event {
+                if presets::handle(&message) {
+                    continue;
+                }
+                feedback(message);
+            }
+
+            if presets::with(|config| config.routing.usb_to_din) {
+                din::forward(&DIN_THRU, event);
             }
-            _ => {}
         }
     }
 }
+
+fn identity() -> Identity {
+    Identity { version: FIRMWARE_VERSION, serial: *uid::uid_hex_bytes() }
+}
+
+/// Answers identity requests and applies config commands from the desktop app.
+async fn handle_sysex(frame: &[u8]) {
+    let mut outputs = DeviceOutputs::new();
+    let request = presets::lock(|presets| protocol::handle_sysex(frame, &identity(), presets, &mut outputs));
+    outputs.send().await;
+
+    match request {
+        Some(Request::EnterBootloader) => update::enter_bootloader().await,
+        Some(Request::ResendState) => RESEND.signal(()),
+        Some(Request::ReportState) => REPORT.signal(()),
+        None => {},
+    }
+}
@@ -0,0 +1,103 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_usb::class::hid::HidWriter;
+use embassy_usb::driver::{Driver, EndpointError};
+use expressor_common::hid::{ConsumerReport, MediaKey, VolumeStepper};
+
+use crate::NUM_CHANNELS;
+
+struct State {
+    /// Media key currently held by each channel.
+    held: [Option<MediaKey>; NUM_CHANNELS],
+    steppers: [VolumeStepper; NUM_CHANNELS],
+    /// Volume steps not sent yet, positive for volume up.
+    pending: i32,
+}
+
+static STATE: Mutex<ThreadModeRawMutex, RefCell<State>> = Mutex::new(RefCell::new(State {
+    held: [None; NUM_CHANNELS],
+    steppers: [VolumeStepper::new(); NUM_CHANNELS],
+    pending: 0,
+}));
+static CHANGED: Signal<ThreadModeRawMutex, ()> = Signal::new();
+
+/// Holds a media key for a channel, or releases the channel's key with `None`.
+pub fn hold(channel: usize, key: Option<MediaKey>) {
+    let changed = STATE.lock(|state| {
+        let mut state = state.borrow_mut();
+        let changed = state.held[channel] != key;
+        state.held[channel] = key;
+        changed
+    });
+
+    if changed {
+        CHANGED.signal(());
+    }
+}
+
+/// Follows the pedal `value` of a channel with volume steps, or stops following with `None`.
+pub fn volume(channel: usize, value: Option<(u8, u8)>) {
+    let changed = STATE.lock(|state| {
+        let mut state = state.borrow_mut();
+        let Some((value, steps)) = value else {
+            state.steppers[channel].reset();
+            return false;
+        };
+
+        let delta = state.steppers[channel].update(value, steps);
+        state.pending += delta as i32;
+        delta != 0
+    });
+
+    if changed {
+        CHANGED.signal(());
+    }
+}
+
+/// Takes the next volume step to send, if any.
+fn next_step() -> Option<MediaKey> {
+    STATE.lock(|state| {
+        let mut state = state.borrow_mut();
+        let step = match state.pending {
+            0 => return None,
+            1.. => MediaKey::VolumeUp,
+            _ => MediaKey::VolumeDown,
+        };
+        state.pending -= state.pending.signum();
+        Some(step)
+    })
+}
+
+fn report() -> ConsumerReport {
+    STATE.lock(|state| MediaKey::report(state.borrow().held.iter().flatten().next().copied()))
+}
+
+/// Sends volume steps as a press and release each, and otherwise reports the held media key. The host volume is
+/// unknown after connecting, so the volume pedals start over.
+pub async fn media_session<'d, D: Driver<'d>>(writer: &mut HidWriter<'d, D, 2>) -> Result<(), EndpointError> {
+    STATE.lock(|state| {
+        let mut state = state.borrow_mut();
+        state.steppers.iter_mut().for_each(VolumeStepper::reset);
+        state.pending = 0;
+    });
+    let mut last = None;
+
+    loop {
+        if let Some(step) = next_step() {
+            writer.write(&MediaKey::report(Some(step))).await?;
+            writer.write(&MediaKey::report(None)).await?;
+            last = Some(MediaKey::report(None));
+            continue;
+        }
+
+        let report = report();
+        if last != Some(report) {
+            writer.write(&report).await?;
+            last = Some(report);
+        }
+        CHANGED.wait().await;
+    }
+}
@@ -0,0 +1,107 @@
+use core::cell::RefCell;
+
+use defmt::*;
+use embassy_futures::select::select;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{Instant, Timer};
+use expressor_common::config::{MAX_MAPPINGS, OutputPath};
+use expressor_common::led::{ErrorCode, LedEvent};
+use expressor_common::midi::{CoalescingBuffer, MidiMessage};
+
+use crate::{NUM_CHANNELS, status};
+
+/// Number of distinct destinations that can be pending at the same time on one port.
+const OUTPUT_SLOTS: usize = 32;
+/// The cable of a channel only carries the messages of its mappings.
+const CABLE_SLOTS: usize = 2 * MAX_MAPPINGS;
+
+/// Latest pending message per destination for a single port, drained by that port as fast as it allows.
+pub struct Output<const N: usize = OUTPUT_SLOTS> {
+    buffer: Mutex<ThreadModeRawMutex, RefCell<CoalescingBuffer<N>>>,
+    signal: Signal<ThreadModeRawMutex, ()>,
+}
+
+/// Merged USB cable.
+pub static USB_OUTPUT: Output = Output::new();
+pub static DIN_OUTPUT: Output = Output::new();
+/// USB cables of the channels, used when channels have cables of their own.
+pub static USB_CABLE_OUTPUTS: [Output<CABLE_SLOTS>; NUM_CHANNELS] = [const { Output::new() }; NUM_CHANNELS];
+
+impl<const N: usize> Output<N> {
+    const fn new() -> Self {
+        Self {
+            buffer: Mutex::new(RefCell::new(CoalescingBuffer::new())),
+            signal: Signal::new(),
+        }
+    }
+
+    /// Queues a message without blocking. A pending message to the same destination is replaced.
+    ///
+    /// `min_interval` is the minimum time between two messages to the destination in microseconds.
+    pub fn send(&self, message: MidiMessage, min_interval: u64) {
+        self.queue(message, |buffer| buffer.push(message, min_interval));
+    }
+
+    /// Queues a message without blocking, behind everything queued before. It is neither replaced nor rate limited.
+    pub fn send_ordered(&self, message: MidiMessage) {
+        self.queue(message, |buffer| buffer.push_ordered(message));
+    }
+
+    fn queue(&self, message: MidiMessage, push: impl FnOnce(&mut CoalescingBuffer<N>) -> bool) {
+        let queued = self.buffer.lock(|buffer| push(&mut *buffer.borrow_mut()));
+        if !queued {
+            warn!("Output buffer full, dropping {}", message);
+            status::notify(LedEvent::Error(ErrorCode::OutputOverflow));
+        }
+
+        self.signal.signal(());
+    }
+
+    /// Waits for the next message that may be sent.
+    pub async fn receive(&self) -> MidiMessage {
+        loop {
+            let now = Instant::now().as_micros();
+            let (message, next_ready) = self.buffer.lock(|buffer| {
+                let mut buffer = buffer.borrow_mut();
+                (buffer.pop(now), buffer.next_ready())
+            });
+
+            if let Some(message) = message {
+                return message;
+            }
+
+            match next_ready {
+                Some(ready) => { select(self.signal.wait(), Timer::at(Instant::from_micros(ready))).await; },
+                None => self.signal.wait().await,
+            }
+        }
+    }
+}
+
+/// Queues a message of `channel` on every port selected by `path`, and on the USB cable of the channel if enabled.
+pub fn send(channel: usize, message: MidiMessage, min_interval: u64, path: OutputPath, cables: bool) {
+    if path.usb() {
+        USB_OUTPUT.send(message, min_interval);
+        if cables {
+            USB_CABLE_OUTPUTS[channel].send(message, min_interval);
+        }
+    }
+    if path.din() {
+        DIN_OUTPUT.send(message, min_interval);
+    }
+}
+
+/// Like [`send`], but in order with everything queued before, see [`Output::send_ordered`].
+pub fn send_ordered(channel: usize, message: MidiMessage, path: OutputPath, cables: bool) {
+    if path.usb() {
+        USB_OUTPUT.send_ordered(message);
+        if cables {
+            USB_CABLE_OUTPUTS[channel].send_ordered(message);
+        }
+    }
+    if path.din() {
+        DIN_OUTPUT.send_ordered(message);
+    }
+}
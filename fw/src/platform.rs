@@ -0,0 +1,161 @@
+//! Binds the traits of the core to the peripherals and USB classes of the board.
+
+use defmt::*;
+use embassy_stm32::Peri;
+use embassy_stm32::adc::{Adc, AnyAdcChannel, SampleTime};
+use embassy_stm32::gpio::Output;
+use embassy_stm32::peripherals::{ADC1, DMA1_CH1};
+use embassy_time::Instant;
+use embassy_usb::driver::{Driver, EndpointError};
+use expressor_common::config::{ChannelConfig, RoutingConfig};
+use expressor_common::led::LedEvent;
+use expressor_common::midi::{MidiMessage, UsbPacket};
+use expressor_common::protocol::Frame;
+use expressor_core::engine::{HidState, Outputs};
+use expressor_core::hal::{AnalogInput, Clock, Leds, MidiIn, MidiOut};
+use heapless::Vec;
+
+use crate::usb_midi::{Receiver, Sender};
+use crate::{NUM_CHANNELS, SYSEX_QUEUE, keyboard, media, output, presets, status};
+#[cfg(feature = "gamepad")]
+use crate::gamepad;
+
+const SAMPLE_TIME: SampleTime = SampleTime::CYCLES24_5;
+
+/// The longest answer is the complete configuration, one frame per channel plus the routing and the preset.
+const REPLY_SLOTS: usize = NUM_CHANNELS + 2;
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_micros(&self) -> u64 {
+        Instant::now().as_micros()
+    }
+}
+
+/// The channel jacks, converted in one go using DMA.
+pub struct AdcInput {
+    adc: Adc<'static, ADC1>,
+    dma: Peri<'static, DMA1_CH1>,
+    channels: [AnyAdcChannel<'static, ADC1>; NUM_CHANNELS],
+}
+
+impl AdcInput {
+    pub fn new(adc: Adc<'static, ADC1>, dma: Peri<'static, DMA1_CH1>, channels: [AnyAdcChannel<'static, ADC1>; NUM_CHANNELS]) -> Self {
+        Self { adc, dma, channels }
+    }
+}
+
+impl AnalogInput<NUM_CHANNELS> for AdcInput {
+    async fn sample(&mut self) -> [u16; NUM_CHANNELS] {
+        let mut values = [0u16; NUM_CHANNELS];
+        self.adc.read(
+            self.dma.reborrow(),
+            self.channels.iter_mut().map(|channel| (channel, SAMPLE_TIME)),
+            &mut values,
+        ).await;
+        values
+    }
+}
+
+pub struct StatusLeds {
+    pub status: Output<'static>,
+    pub activity: Output<'static>,
+}
+
+impl Leds<2> for StatusLeds {
+    fn set(&mut self, [status, activity]: [bool; 2]) {
+        self.status.set_level(status.into());
+        self.activity.set_level(activity.into());
+    }
+}
+
+impl<'d, D: Driver<'d>> MidiOut for Sender<'d, D> {
+    type Error = EndpointError;
+
+    async fn send(&mut self, packet: &UsbPacket) -> Result<(), EndpointError> {
+        self.write_packet(packet).await
+    }
+}
+
+impl<'d, D: Driver<'d>> MidiIn for Receiver<'d, D> {
+    type Error = EndpointError;
+
+    async fn receive(&mut self, packets: &mut [UsbPacket]) -> Result<usize, EndpointError> {
+        let mut buf = [0u8; 64];
+        let len = self.read_packet(&mut buf).await?;
+
+        let (received, _) = buf[..len].as_chunks::<4>();
+        let count = received.len().min(packets.len());
+        packets[..count].copy_from_slice(&received[..count]);
+        Ok(count)
+    }
+}
+
+/// Passes the output of the core on to the MIDI ports, the HID interfaces and the status LEDs. Replies to the desktop
+/// app are collected until the caller decides whether to wait for room in the queue.
+pub struct DeviceOutputs {
+    replies: Vec<Frame, REPLY_SLOTS>,
+    /// Preset requested by a switch gesture, selected on flush when the presets are no longer locked.
+    preset: Option<u8>,
+}
+
+impl DeviceOutputs {
+    pub fn new() -> Self {
+        Self { replies: Vec::new(), preset: None }
+    }
+
+    /// Queues the replies without blocking, dropping those that do not fit, and selects the requested preset.
+    pub fn flush(self) {
+        for frame in self.replies {
+            if SYSEX_QUEUE.try_send(frame).is_err() {
+                debug!("SysEx queue full, dropping reply");
+            }
+        }
+
+        if let Some(index) = self.preset
+            && !presets::select(index)
+        {
+            warn!("Gesture selects missing preset {}", index);
+        }
+    }
+
+    /// Queues the replies, waiting for room.
+    pub async fn send(self) {
+        for frame in self.replies {
+            SYSEX_QUEUE.send(frame).await;
+        }
+    }
+}
+
+impl Outputs for DeviceOutputs {
+    fn midi(&mut self, channel: usize, message: MidiMessage, config: &ChannelConfig, routing: &RoutingConfig) {
+        output::send(channel, message, config.min_interval(), config.output_path, routing.usb_cables);
+    }
+
+    fn midi_ordered(&mut self, channel: usize, message: MidiMessage, config: &ChannelConfig, routing: &RoutingConfig) {
+        output::send_ordered(channel, message, config.output_path, routing.usb_cables);
+    }
+
+    fn hid(&mut self, channel: usize, state: HidState) {
+        keyboard::hold(channel, state.key);
+        #[cfg(feature = "gamepad")]
+        gamepad::set(channel, state.gamepad);
+        media::hold(channel, state.media);
+        media::volume(channel, state.volume);
+    }
+
+    fn led(&mut self, event: LedEvent) {
+        status::notify(event);
+    }
+
+    fn reply(&mut self, frame: Frame) {
+        if self.replies.push(frame).is_err() {
+            warn!("Reply buffer full, dropping reply");
+        }
+    }
+
+    fn preset(&mut self, index: u8) {
+        self.preset = Some(index);
+    }
+}
@@ -0,0 +1,70 @@
+use core::cell::RefCell;
+
+use defmt::*;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use expressor_common::config::{ChannelConfig, DeviceConfig};
+use expressor_common::midi::MidiMessage;
+use expressor_common::protocol::DeviceMessage;
+use expressor_core::hal::NoStorage;
+use expressor_core::presets::Presets;
+use expressor_core::protocol;
+
+use crate::platform::DeviceOutputs;
+use crate::{NUM_CHANNELS, SYSEX_QUEUE};
+
+pub type Config = DeviceConfig<NUM_CHANNELS>;
+
+/// Presets only live in RAM for now and start out with the default configuration.
+static PRESETS: Mutex<ThreadModeRawMutex, RefCell<Option<Presets<NUM_CHANNELS>>>> = Mutex::new(RefCell::new(None));
+
+/// Runs `f` with all presets.
+pub fn lock<R>(f: impl FnOnce(&mut Presets<NUM_CHANNELS>) -> R) -> R {
+    PRESETS.lock(|presets| {
+        let mut presets = presets.borrow_mut();
+        f(presets.get_or_insert_with(|| Presets::load(&mut NoStorage)))
+    })
+}
+
+/// Runs `f` with the active configuration.
+pub fn with<R>(f: impl FnOnce(&Config) -> R) -> R {
+    lock(|presets| f(presets.config()))
+}
+
+/// Changes the active configuration.
+pub fn update(f: impl FnOnce(&mut Config)) {
+    lock(|presets| f(presets.config_mut()))
+}
+
+/// Replaces a channel of the active configuration from the device side and tells the desktop app about it.
+pub fn set_channel(channel: usize, config: ChannelConfig) {
+    update(|device_config| device_config.channels[channel] = config);
+    let _ = SYSEX_QUEUE.try_send(DeviceMessage::ChannelConfig { channel: channel as u8, config }.encode());
+}
+
+pub fn active() -> u8 {
+    lock(|presets| presets.active())
+}
+
+/// Activates a preset and tells the desktop app about it. Returns `false` if there is no such preset.
+pub fn select(index: u8) -> bool {
+    let mut outputs = DeviceOutputs::new();
+    let selected = lock(|presets| protocol::select_preset(presets, index, &mut outputs));
+    outputs.flush();
+
+    if selected {
+        info!("Selected preset {}", index);
+    }
+    selected
+}
+
+/// Handles program changes on the preset channel. Returns `true` if the message was consumed.
+pub fn handle(message: &MidiMessage) -> bool {
+    match lock(|presets| presets.preset_change(message)) {
+        Some(program) => {
+            select(program);
+            true
+        },
+        None => false,
+    }
+}
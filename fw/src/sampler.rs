@@ -0,0 +1,38 @@
+use defmt::*;
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Ticker};
+use expressor_common::led::{ErrorCode, LedEvent};
+use expressor_core::hal::{AnalogInput, Clock};
+
+use crate::platform::{AdcInput, SystemClock};
+use crate::{NUM_CHANNELS, status};
+
+/// One conversion of all inputs, taken at `timestamp` microseconds.
+#[derive(Clone, Copy)]
+pub struct RawFrame {
+    pub timestamp: u64,
+    pub values: [u16; NUM_CHANNELS],
+}
+
+/// Frames waiting for the channel processing. The sampler never waits for room, so a stalled consumer only loses
+/// frames instead of delaying the sampling.
+pub static FRAMES: Channel<ThreadModeRawMutex, RawFrame, 8> = Channel::new();
+
+/// Samples all inputs at a fixed rate.
+#[embassy_executor::task]
+pub async fn sampler_task(mut input: AdcInput, sample_rate: u64) {
+    let mut ticker = Ticker::every(Duration::from_hz(sample_rate));
+
+    loop {
+        ticker.next().await;
+
+        let timestamp = SystemClock.now_micros();
+        let values = input.sample().await;
+
+        if FRAMES.try_send(RawFrame { timestamp, values }).is_err() {
+            warn!("Channel processing overrun, dropping frame");
+            status::notify(LedEvent::Error(ErrorCode::SampleOverrun));
+        }
+    }
+}
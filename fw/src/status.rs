@@ -0,0 +1,51 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::*;
+use embassy_futures::select::{Either, select};
+use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_time::{Duration, Instant, Ticker};
+use expressor_common::led::{LedEngine, LedEvent};
+use expressor_core::hal::Leds;
+
+use crate::platform::StatusLeds;
+
+/// Refresh interval of the LED patterns.
+const FRAME: Duration = Duration::from_millis(10);
+
+static EVENTS: Channel<ThreadModeRawMutex, LedEvent, 8> = Channel::new();
+
+/// Set for every MIDI message. Kept apart from the event queue, so a busy stream can not crowd out other events.
+static ACTIVITY: AtomicBool = AtomicBool::new(false);
+
+/// Shows `event` on the status LEDs without blocking.
+pub fn notify(event: LedEvent) {
+    if EVENTS.try_send(event).is_err() {
+        debug!("LED event queue full, dropping {}", event);
+    }
+}
+
+/// Flashes the activity LED.
+pub fn activity() {
+    ACTIVITY.store(true, Ordering::Relaxed);
+}
+
+#[embassy_executor::task]
+pub async fn led_task(mut leds: StatusLeds) {
+    let mut engine = LedEngine::new();
+    let mut ticker = Ticker::every(FRAME);
+
+    loop {
+        let event = select(EVENTS.receive(), ticker.next()).await;
+        let now = Instant::now().as_millis();
+
+        if let Either::First(event) = event {
+            engine.handle(event, now);
+        }
+        if ACTIVITY.swap(false, Ordering::Relaxed) {
+            engine.handle(LedEvent::MidiActivity, now);
+        }
+
+        leds.set(engine.levels(now));
+    }
+}
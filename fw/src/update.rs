@@ -0,0 +1,14 @@
+use defmt::*;
+use embassy_time::Timer;
+use expressor_common::update::layout::{UPDATE_REQUEST, UPDATE_REQUEST_ADDRESS};
+
+/// Restarts into the bootloader, which takes over the update from the desktop app.
+pub async fn enter_bootloader() -> ! {
+    info!("Restarting into the bootloader");
+    // lets pending USB transfers complete before the device disconnects
+    Timer::after_millis(50).await;
+
+    // SAFETY: the word is excluded from the RAM in memory.x, so nothing else uses it
+    unsafe { core::ptr::write_volatile(UPDATE_REQUEST_ADDRESS as *mut u32, UPDATE_REQUEST) };
+    cortex_m::peripheral::SCB::sys_reset()
+}
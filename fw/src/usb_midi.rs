@@ -0,0 +1,154 @@
+//! USB MIDI class with one named jack pair per cable.
+//!
+//! Same descriptors as `embassy_usb::class::midi`, except that every jack refers to a string, so hosts show the
+//! cables under the channel labels. Names are read when the host asks for them, which is usually when the device is
+//! plugged in.
+
+use core::fmt::Write;
+
+use embassy_usb::driver::{Driver, Endpoint, EndpointError, EndpointIn, EndpointOut};
+use embassy_usb::types::StringIndex;
+use embassy_usb::{Builder, Handler};
+use expressor_common::config::RoutingConfig;
+use heapless::String;
+
+use crate::{NUM_CHANNELS, presets};
+
+/// The merged cable and one cable per channel.
+pub const NUM_CABLES: usize = NUM_CHANNELS + 1;
+
+const USB_AUDIO_CLASS: u8 = 0x01;
+const USB_AUDIOCONTROL_SUBCLASS: u8 = 0x01;
+const USB_MIDISTREAMING_SUBCLASS: u8 = 0x03;
+const MIDI_IN_JACK_SUBTYPE: u8 = 0x02;
+const MIDI_OUT_JACK_SUBTYPE: u8 = 0x03;
+const EMBEDDED: u8 = 0x01;
+const EXTERNAL: u8 = 0x02;
+const CS_INTERFACE: u8 = 0x24;
+const CS_ENDPOINT: u8 = 0x25;
+const HEADER_SUBTYPE: u8 = 0x01;
+const MS_HEADER_SUBTYPE: u8 = 0x01;
+const MS_GENERAL: u8 = 0x01;
+const PROTOCOL_NONE: u8 = 0x00;
+const MIDI_IN_SIZE: usize = 0x06;
+const MIDI_OUT_SIZE: usize = 0x09;
+
+/// Serves the jack names from the active configuration.
+pub struct JackNames {
+    first: u8,
+    name: String<32>,
+}
+
+impl JackNames {
+    pub fn new() -> Self {
+        Self { first: 0, name: String::new() }
+    }
+}
+
+impl Handler for JackNames {
+    fn get_string(&mut self, index: StringIndex, _lang_id: u16) -> Option<&str> {
+        let cable = u8::from(index).checked_sub(self.first).filter(|cable| (*cable as usize) < NUM_CABLES)?;
+
+        self.name.clear();
+        match (0..NUM_CHANNELS).find(|channel| RoutingConfig::channel_cable(*channel) == cable) {
+            None => self.name.push_str("Midi Expressor").ok()?,
+            Some(channel) => {
+                let config = presets::with(|config| config.channels[channel]);
+                match config.label_str() {
+                    "" => write!(self.name, "Pedal {}", channel + 1).ok()?,
+                    label => self.name.push_str(label).ok()?,
+                }
+            },
+        }
+        Some(&self.name)
+    }
+}
+
+/// Creates the MIDI class and registers `names` for its jacks. For full-speed devices, `max_packet_size` has to be
+/// one of 8, 16, 32 or 64.
+pub fn new<'d, D: Driver<'d>>(
+    builder: &mut Builder<'d, D>,
+    names: &'d mut JackNames,
+    max_packet_size: u16,
+) -> (Sender<'d, D>, Receiver<'d, D>) {
+    let strings: [StringIndex; NUM_CABLES] = core::array::from_fn(|_| builder.string());
+    names.first = strings[0].into();
+    builder.handler(names);
+
+    let mut func = builder.function(USB_AUDIO_CLASS, USB_AUDIOCONTROL_SUBCLASS, PROTOCOL_NONE);
+
+    // Audio control interface
+    let mut iface = func.interface();
+    let audio_if = iface.interface_number();
+    let midi_if = u8::from(audio_if) + 1;
+    let mut alt = iface.alt_setting(USB_AUDIO_CLASS, USB_AUDIOCONTROL_SUBCLASS, PROTOCOL_NONE, None);
+    alt.descriptor(CS_INTERFACE, &[HEADER_SUBTYPE, 0x00, 0x01, 0x09, 0x00, 0x01, midi_if]);
+
+    // MIDIStreaming interface
+    let mut iface = func.interface();
+    let mut alt = iface.alt_setting(USB_AUDIO_CLASS, USB_MIDISTREAMING_SUBCLASS, PROTOCOL_NONE, None);
+
+    let total_length = 7 + 2 * NUM_CABLES * (MIDI_IN_SIZE + MIDI_OUT_SIZE) + 2 * (7 + 4 + NUM_CABLES);
+    alt.descriptor(CS_INTERFACE, &[MS_HEADER_SUBTYPE, 0x00, 0x01, total_length as u8, (total_length >> 8) as u8]);
+
+    // every cable has an external and an embedded jack in each direction
+    let in_jack_ext = |cable: usize| (4 * cable + 1) as u8;
+    let out_jack_emb = |cable: usize| (4 * cable + 2) as u8;
+    let out_jack_ext = |cable: usize| (4 * cable + 3) as u8;
+    let in_jack_emb = |cable: usize| (4 * cable + 4) as u8;
+
+    for (cable, string) in strings.iter().enumerate() {
+        let string = u8::from(*string);
+        alt.descriptor(CS_INTERFACE, &[MIDI_IN_JACK_SUBTYPE, EXTERNAL, in_jack_ext(cable), string]);
+        alt.descriptor(CS_INTERFACE, &[MIDI_IN_JACK_SUBTYPE, EMBEDDED, in_jack_emb(cable), string]);
+        alt.descriptor(CS_INTERFACE, &[MIDI_OUT_JACK_SUBTYPE, EXTERNAL, out_jack_ext(cable), 0x01, in_jack_emb(cable), 0x01, string]);
+        alt.descriptor(CS_INTERFACE, &[MIDI_OUT_JACK_SUBTYPE, EMBEDDED, out_jack_emb(cable), 0x01, in_jack_ext(cable), 0x01, string]);
+    }
+
+    let mut endpoint_data = [0; 2 + NUM_CABLES];
+    endpoint_data[0] = MS_GENERAL;
+    endpoint_data[1] = NUM_CABLES as u8;
+
+    let read_ep = alt.endpoint_bulk_out(None, max_packet_size);
+    for cable in 0..NUM_CABLES {
+        endpoint_data[2 + cable] = in_jack_emb(cable);
+    }
+    alt.descriptor(CS_ENDPOINT, &endpoint_data);
+
+    let write_ep = alt.endpoint_bulk_in(None, max_packet_size);
+    for cable in 0..NUM_CABLES {
+        endpoint_data[2 + cable] = out_jack_emb(cable);
+    }
+    alt.descriptor(CS_ENDPOINT, &endpoint_data);
+
+    (Sender { write_ep }, Receiver { read_ep })
+}
+
+pub struct Sender<'d, D: Driver<'d>> {
+    write_ep: D::EndpointIn,
+}
+
+impl<'d, D: Driver<'d>> Sender<'d, D> {
+    pub async fn write_packet(&mut self, data: &[u8]) -> Result<(), EndpointError> {
+        self.write_ep.write(data).await
+    }
+
+    pub async fn wait_connection(&mut self) {
+        self.write_ep.wait_enabled().await;
+    }
+}
+
+pub struct Receiver<'d, D: Driver<'d>> {
+    read_ep: D::EndpointOut,
+}
+
+impl<'d, D: Driver<'d>> Receiver<'d, D> {
+    /// Reads a packet, `data` must hold at least the maximum packet size.
+    pub async fn read_packet(&mut self, data: &mut [u8]) -> Result<usize, EndpointError> {
+        self.read_ep.read(data).await
+    }
+
+    pub async fn wait_connection(&mut self) {
+        self.read_ep.wait_enabled().await;
+    }
+}
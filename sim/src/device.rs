@@ -0,0 +1,373 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::pin::pin;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_io::Timer;
+use expressor_common::config::{ChannelConfig, RoutingConfig};
+use expressor_common::detect::RAW_MAX;
+use expressor_common::led::LedEvent;
+use expressor_common::midi::{CoalescingBuffer, MidiEvent, MidiMessage, SysexAssembler, UsbPacket, packet_cable, sysex_packets};
+use expressor_common::protocol::{FRAME_SIZE, Frame, Identity, SERIAL_LEN};
+use expressor_core::FIRMWARE_VERSION;
+use expressor_core::engine::{Engine, HidState, Outputs};
+use expressor_core::hal::{AnalogInput, Clock, MidiIn, MidiOut, NoStorage};
+use expressor_core::presets::Presets;
+use expressor_core::protocol::{self, Request};
+use futures::{Stream, StreamExt};
+use futures::future::{Either, select};
+
+pub const NUM_CHANNELS: usize = 4;
+
+/// Rate at which the pedals are sampled, in Hz, the same as on the board.
+const SAMPLE_RATE: u64 = 1000;
+
+/// Number of distinct destinations that can be pending at the same time.
+const OUTPUT_SLOTS: usize = 32;
+
+/// Tells the simulator apart from boards in the desktop app.
+const SERIAL: &[u8; SERIAL_LEN] = b"53494D554C41544F52000000";
+
+/// What the device did, for the front end to show.
+#[derive(Debug, Clone, Copy)]
+pub enum Event {
+    Sent(MidiMessage),
+    Received(MidiMessage),
+    Led(LedEvent),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sent(message) => write!(f, "sent {message:?}"),
+            Self::Received(message) => write!(f, "received {message:?}"),
+            Self::Led(event) => write!(f, "led {event:?}"),
+        }
+    }
+}
+
+/// Pedal positions, set by the front end and sampled by the device.
+#[derive(Debug, Clone, Default)]
+pub struct Pedals(Arc<Mutex<[u16; NUM_CHANNELS]>>);
+
+impl Pedals {
+    /// Moves the pedal of a channel to a 7-bit position.
+    pub fn set(&self, channel: usize, value: u8) {
+        let raw = value.min(127) as u32 * RAW_MAX as u32 / 127;
+        self.0.lock().unwrap()[channel] = raw as u16;
+    }
+}
+
+impl AnalogInput<NUM_CHANNELS> for Pedals {
+    async fn sample(&mut self) -> [u16; NUM_CHANNELS] {
+        *self.0.lock().unwrap()
+    }
+}
+
+struct SystemClock(Instant);
+
+impl Clock for SystemClock {
+    fn now_micros(&self) -> u64 {
+        self.0.elapsed().as_micros() as u64
+    }
+}
+
+/// Queues MIDI messages like the USB output of the board and collects replies until the next flush. There are no
+/// HID interfaces and no DIN port, what the core sends there is dropped.
+struct SimOutputs {
+    buffer: CoalescingBuffer<OUTPUT_SLOTS>,
+    replies: Vec<Frame>,
+    events: mpsc::Sender<Event>,
+    /// Preset requested by a switch gesture.
+    preset: Option<u8>,
+}
+
+impl SimOutputs {
+    /// Takes the packets that may be sent at `now`.
+    fn take_packets(&mut self, now: u64) -> Vec<UsbPacket> {
+        let messages = std::iter::from_fn(|| self.buffer.pop(now)).inspect(|message| {
+            let _ = self.events.send(Event::Sent(*message));
+        });
+        let mut packets: Vec<UsbPacket> = messages.map(|message| message.to_usb_packet(RoutingConfig::MERGED_CABLE)).collect();
+        for frame in self.replies.drain(..) {
+            packets.extend(sysex_packets(RoutingConfig::MERGED_CABLE, &frame));
+        }
+        packets
+    }
+}
+
+impl Outputs for SimOutputs {
+    fn midi(&mut self, _channel: usize, message: MidiMessage, config: &ChannelConfig, _routing: &RoutingConfig) {
+        if config.output_path.usb() && !self.buffer.push(message, config.min_interval()) {
+            eprintln!("Output buffer full, dropping {message:?}");
+        }
+    }
+
+    fn midi_ordered(
+        &mut self,
+        _channel: usize,
+        message: MidiMessage,
+        config: &ChannelConfig,
+        _routing: &RoutingConfig,
+    ) {
+        if config.output_path.usb() && !self.buffer.push_ordered(message) {
+            eprintln!("Output buffer full, dropping {message:?}");
+        }
+    }
+
+    fn hid(&mut self, _channel: usize, _state: HidState) {}
+
+    fn led(&mut self, event: LedEvent) {
+        let _ = self.events.send(Event::Led(event));
+    }
+
+    fn reply(&mut self, frame: Frame) {
+        self.replies.push(frame);
+    }
+
+    fn preset(&mut self, index: u8) {
+        self.preset = Some(index);
+    }
+}
+
+struct State {
+    engine: Engine<NUM_CHANNELS>,
+    presets: Presets<NUM_CHANNELS>,
+    outputs: SimOutputs,
+    /// The host asked for the current values, sent with the next sample.
+    resend: bool,
+    /// The host read the config and wants the current programs, sent with the next sample.
+    report: bool,
+}
+
+pub struct Disconnected;
+
+/// Runs the device until the port goes away.
+pub async fn run(pedals: Pedals, input: impl MidiIn, output: impl MidiOut, events: mpsc::Sender<Event>) -> Disconnected {
+    let ticks = Timer::interval(Duration::from_micros(1_000_000 / SAMPLE_RATE));
+    run_with(SystemClock(Instant::now()), ticks, pedals, input, output, events).await
+}
+
+/// Runs the device with a sample on every tick, at the time of `clock`.
+async fn run_with(
+    clock: impl Clock,
+    ticks: impl Stream,
+    mut pedals: Pedals,
+    mut input: impl MidiIn,
+    mut output: impl MidiOut,
+    events: mpsc::Sender<Event>,
+) -> Disconnected {
+    let identity = Identity { version: FIRMWARE_VERSION, serial: *SERIAL };
+    let state = RefCell::new(State {
+        engine: Engine::new(SAMPLE_RATE),
+        presets: Presets::load(&mut NoStorage),
+        outputs: SimOutputs {
+            buffer: CoalescingBuffer::new(),
+            replies: Vec::new(),
+            events: events.clone(),
+            preset: None,
+        },
+        resend: false,
+        report: false,
+    });
+
+    let process = async {
+        let mut ticks = pin!(ticks);
+        while ticks.next().await.is_some() {
+            let values = pedals.sample().await;
+            let now = clock.now_micros();
+
+            let packets = {
+                let State { engine, presets, outputs, resend, report } = &mut *state.borrow_mut();
+                engine.process(now, &values, presets.config(), outputs);
+                if std::mem::take(resend) {
+                    engine.resend(now, presets.config(), outputs);
+                }
+                if std::mem::take(report) {
+                    engine.report(presets.config(), outputs);
+                }
+                if let Some(index) = outputs.preset.take()
+                    && protocol::select_preset(presets, index, outputs)
+                {
+                    engine.preset_changed(presets.config());
+                }
+                outputs.take_packets(now)
+            };
+            for packet in &packets {
+                if output.send(packet).await.is_err() {
+                    return Disconnected;
+                }
+            }
+        }
+        Disconnected
+    };
+
+    let receive = async {
+        let mut packets = [UsbPacket::default(); 16];
+        let mut sysex = SysexAssembler::<FRAME_SIZE>::new();
+
+        loop {
+            let Ok(len) = input.receive(&mut packets).await else {
+                return Disconnected;
+            };
+
+            let State { engine, presets, outputs, resend, report } = &mut *state.borrow_mut();
+            for packet in &packets[..len] {
+                if packet_cable(packet) == RoutingConfig::MERGED_CABLE
+                    && let Some(frame) = sysex.push(packet)
+                {
+                    match protocol::handle_sysex(frame, &identity, presets, outputs) {
+                        Some(Request::EnterBootloader) => {
+                            eprintln!("Firmware updates need the board, ignoring the update request")
+                        },
+                        Some(Request::ResendState) => *resend = true,
+                        Some(Request::ReportState) => *report = true,
+                        None => {},
+                    }
+                    continue;
+                }
+
+                if let Some(MidiEvent::Message(message)) = MidiEvent::from_usb_packet(packet) {
+                    let _ = events.send(Event::Received(message));
+                    match presets.preset_change(&message) {
+                        Some(program) => {
+                            if protocol::select_preset(presets, program, outputs) {
+                                engine.preset_changed(presets.config());
+                            }
+                        },
+                        None => engine.feedback(&message, presets.config()),
+                    }
+                }
+            }
+        }
+    };
+
+    match select(Box::pin(process), Box::pin(receive)).await {
+        Either::Left((disconnected, _)) | Either::Right((disconnected, _)) => disconnected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::convert::Infallible;
+    use std::pin::Pin;
+    use std::rc::Rc;
+
+    use async_channel::{Receiver, RecvError, Sender};
+    use expressor_common::config::DeviceConfig;
+    use expressor_common::protocol::{DeviceMessage, HostMessage, identity_request};
+    use futures::FutureExt;
+
+    use super::*;
+
+    struct TestIn(Receiver<UsbPacket>);
+
+    impl MidiIn for TestIn {
+        type Error = RecvError;
+
+        async fn receive(&mut self, packets: &mut [UsbPacket]) -> Result<usize, RecvError> {
+            packets[0] = self.0.recv().await?;
+            Ok(1)
+        }
+    }
+
+    struct TestOut(Sender<UsbPacket>);
+
+    impl MidiOut for TestOut {
+        type Error = Infallible;
+
+        async fn send(&mut self, packet: &UsbPacket) -> Result<(), Infallible> {
+            let _ = self.0.try_send(*packet);
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct TestClock(Rc<Cell<u64>>);
+
+    impl Clock for TestClock {
+        fn now_micros(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    /// Polls the device until it waits for the next tick or packet.
+    fn settle(device: Pin<&mut impl Future<Output = Disconnected>>) {
+        assert!(device.now_or_never().is_none(), "device stopped");
+    }
+
+    /// Samples the pedals once per millisecond.
+    fn run_for(millis: u64, clock: &TestClock, tick: &Sender<()>, mut device: Pin<&mut impl Future<Output = Disconnected>>) {
+        for _ in 0..millis {
+            clock.0.set(clock.0.get() + 1_000_000 / SAMPLE_RATE);
+            tick.try_send(()).unwrap();
+            settle(device.as_mut());
+        }
+    }
+
+    /// Decodes the replies and messages the host received so far.
+    fn received(host_in: &Receiver<UsbPacket>) -> (Vec<Frame>, Vec<MidiMessage>) {
+        let mut sysex = SysexAssembler::<FRAME_SIZE>::new();
+        let mut frames = Vec::new();
+        let mut messages = Vec::new();
+        while let Ok(packet) = host_in.try_recv() {
+            if let Some(frame) = sysex.push(&packet) {
+                frames.push(Frame::from_slice(frame).unwrap());
+            } else if let Some(MidiEvent::Message(message)) = MidiEvent::from_usb_packet(&packet) {
+                messages.push(message);
+            }
+        }
+        (frames, messages)
+    }
+
+    #[test]
+    fn test_session() {
+        let pedals = Pedals::default();
+        let clock = TestClock::default();
+        let (tick, ticks) = async_channel::unbounded();
+        let (host_out, device_in) = async_channel::unbounded();
+        let (device_out, host_in) = async_channel::unbounded();
+        let (events, _) = mpsc::channel();
+
+        let device = run_with(clock.clone(), ticks, pedals.clone(), TestIn(device_in), TestOut(device_out), events);
+        let mut device = pin!(device);
+        let send = |message: &[u8]| {
+            for packet in sysex_packets(RoutingConfig::MERGED_CABLE, message) {
+                host_out.try_send(packet).unwrap();
+            }
+        };
+
+        send(&identity_request());
+        send(&HostMessage::GetConfig.encode());
+        settle(device.as_mut());
+        pedals.set(0, 127);
+        run_for(20, &clock, &tick, device.as_mut());
+
+        let (frames, messages) = received(&host_in);
+        assert_eq!(Identity::decode(&frames[0]).map(|identity| identity.version), Some(FIRMWARE_VERSION));
+        let replies: Vec<_> = frames[1..].iter().map(|frame| DeviceMessage::decode(frame)).collect();
+        let config = DeviceConfig::<NUM_CHANNELS>::default();
+        let expected = (0..NUM_CHANNELS)
+            .map(|channel| DeviceMessage::ChannelConfig { channel: channel as u8, config: config.channels[channel] })
+            .chain([DeviceMessage::Routing(config.routing), DeviceMessage::Preset(0)]);
+        assert!(replies.into_iter().eq(expected.map(Some)));
+        assert_eq!(messages, [MidiMessage::ControlChange(0, 0, 127)]);
+
+        // the state dump covers every channel
+        send(&HostMessage::DumpState.encode());
+        settle(device.as_mut());
+        run_for(20, &clock, &tick, device.as_mut());
+
+        let (frames, messages) = received(&host_in);
+        assert!(frames.is_empty());
+        assert_eq!(messages, [
+            MidiMessage::ControlChange(0, 0, 127),
+            MidiMessage::ControlChange(0, 1, 0),
+            MidiMessage::ControlChange(0, 2, 0),
+            MidiMessage::ControlChange(0, 3, 0),
+        ]);
+    }
+}
@@ -0,0 +1,109 @@
+//! Virtual Midi Expressor for developing the desktop app and DAW mappings without a board.
+//!
+//! Runs the device logic of the firmware behind a virtual ALSA MIDI port with the name of the board, so the desktop
+//! app and DAWs find it like the real device. The pedals are moved with commands from stdin or a script, or with the
+//! keyboard in a terminal view:
+//!
+//! ```text
+//! midibox-sim                  commands from stdin, see `midibox-sim --help`
+//! midibox-sim --script FILE    runs the commands of a file, then exits
+//! midibox-sim --tui            terminal view
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::device::{Event, Pedals};
+
+mod device;
+mod port;
+mod script;
+mod tui;
+
+const USAGE: &str = "usage: midibox-sim [--script FILE | --tui]";
+
+/// Time for the last messages of a script to reach the port before the simulator exits.
+const DRAIN_TIME: Duration = Duration::from_millis(100);
+
+enum Frontend {
+    Stdin,
+    Script(String),
+    Tui,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Frontend, String> {
+    let frontend = match args.next().as_deref() {
+        None => Frontend::Stdin,
+        Some("--script") => Frontend::Script(args.next().ok_or("--script needs a file")?),
+        Some("--tui") => Frontend::Tui,
+        Some("--help") => return Err(format!("{USAGE}\n\n{}", script::HELP)),
+        Some(arg) => return Err(format!("unknown argument {arg}\n{USAGE}")),
+    };
+
+    match args.next() {
+        Some(arg) => Err(format!("unexpected argument {arg}\n{USAGE}")),
+        None => Ok(frontend),
+    }
+}
+
+/// Prints the device events, one per line.
+fn print_events(events: mpsc::Receiver<Event>) {
+    thread::spawn(move || {
+        for event in events {
+            println!("{event}");
+        }
+    });
+}
+
+fn main() -> ExitCode {
+    let frontend = match parse_args(std::env::args().skip(1)) {
+        Ok(frontend) => frontend,
+        Err(message) => {
+            eprintln!("{message}");
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let (input, output) = match port::open() {
+        Ok(port) => port,
+        Err(error) => {
+            eprintln!("Could not create the MIDI port: {error}");
+            return ExitCode::FAILURE;
+        },
+    };
+
+    let pedals = Pedals::default();
+    let (events, received) = mpsc::channel();
+    let device_pedals = pedals.clone();
+    thread::spawn(move || futures::executor::block_on(device::run(device_pedals, input, output, events)));
+
+    match frontend {
+        Frontend::Stdin => {
+            print_events(received);
+            script::run(io::stdin().lock(), &pedals);
+        },
+        Frontend::Script(path) => {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(error) => {
+                    eprintln!("Could not open {path}: {error}");
+                    return ExitCode::FAILURE;
+                },
+            };
+            print_events(received);
+            script::run(BufReader::new(file), &pedals);
+            thread::sleep(DRAIN_TIME);
+        },
+        Frontend::Tui => {
+            if let Err(error) = tui::run(&pedals, received) {
+                eprintln!("Terminal error: {error}");
+                return ExitCode::FAILURE;
+            }
+        },
+    }
+    ExitCode::SUCCESS
+}
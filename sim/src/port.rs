@@ -0,0 +1,116 @@
+//! Virtual ALSA port that stands in for the USB MIDI interface.
+//!
+//! ALSA passes whole MIDI messages, so they are split into USB-MIDI packets on the way in and joined again on the way
+//! out. The device logic sees the same packet stream as on the board.
+
+use async_channel::{Receiver, RecvError, Sender};
+use expressor_common::midi::{MidiEvent, MidiMessage, UsbPacket, packet_bytes, sysex_packets};
+use expressor_common::protocol::SYSEX_START;
+use expressor_core::hal::{MidiIn, MidiOut};
+use midir::os::unix::{VirtualInput, VirtualOutput};
+use midir::{Ignore, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection, SendError};
+
+/// Name of the ALSA client and its port, the same as the USB product name of the board.
+pub const PORT_NAME: &str = "Midi Expressor";
+
+/// Packets from the host that the device has not taken yet.
+const QUEUE_SIZE: usize = 1024;
+
+/// Creates the virtual port, returning its two directions.
+pub fn open() -> Result<(PortIn, PortOut), String> {
+    let mut input = MidiInput::new(PORT_NAME).map_err(|error| error.to_string())?;
+    input.ignore(Ignore::None);
+    let (sender, receiver) = async_channel::bounded(QUEUE_SIZE);
+    let connection = input
+        .create_virtual(PORT_NAME, |_timestamp, bytes, sender: &mut Sender<UsbPacket>| {
+            for packet in usb_packets(bytes) {
+                let _ = sender.try_send(packet);
+            }
+        }, sender)
+        .map_err(|error| error.to_string())?;
+
+    let output = MidiOutput::new(PORT_NAME).map_err(|error| error.to_string())?;
+    let output = output.create_virtual(PORT_NAME).map_err(|error| error.to_string())?;
+
+    Ok((PortIn { _connection: connection, receiver }, PortOut { connection: output, sysex: Vec::new() }))
+}
+
+/// Splits a message from ALSA into the packets a USB host would send for it.
+fn usb_packets(bytes: &[u8]) -> Vec<UsbPacket> {
+    match bytes {
+        [SYSEX_START, ..] => sysex_packets(0, bytes).collect(),
+        [byte] if *byte >= 0xF8 => vec![MidiEvent::Realtime(*byte).to_usb_packet(0)],
+        [status, data @ ..] => {
+            let data = |i: usize| data.get(i).copied().unwrap_or(0);
+            MidiMessage::from_bytes(*status, data(0), data(1)).map(|message| message.to_usb_packet(0)).into_iter().collect()
+        },
+        [] => Vec::new(),
+    }
+}
+
+pub struct PortIn {
+    _connection: MidiInputConnection<Sender<UsbPacket>>,
+    receiver: Receiver<UsbPacket>,
+}
+
+impl MidiIn for PortIn {
+    type Error = RecvError;
+
+    async fn receive(&mut self, packets: &mut [UsbPacket]) -> Result<usize, RecvError> {
+        if packets.is_empty() {
+            return Ok(0);
+        }
+        packets[0] = self.receiver.recv().await?;
+
+        let mut count = 1;
+        while count < packets.len()
+            && let Ok(packet) = self.receiver.try_recv()
+        {
+            packets[count] = packet;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+pub struct PortOut {
+    connection: MidiOutputConnection,
+    /// System exclusive message being joined from its packets.
+    sysex: Vec<u8>,
+}
+
+impl MidiOut for PortOut {
+    type Error = SendError;
+
+    async fn send(&mut self, packet: &UsbPacket) -> Result<(), SendError> {
+        let bytes = packet_bytes(packet);
+        match packet[0] & 0x0F {
+            // system exclusive starts, continues or ends
+            0x04..=0x07 if bytes[0] == SYSEX_START || !self.sysex.is_empty() => {
+                self.sysex.extend_from_slice(bytes);
+                if packet[0] & 0x0F == 0x04 {
+                    return Ok(());
+                }
+                let result = self.connection.send(&self.sysex);
+                self.sysex.clear();
+                result
+            },
+            _ => self.connection.send(bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usb_packets() {
+        assert_eq!(usb_packets(&[0xB0, 7, 100]), [[0x0B, 0xB0, 7, 100]]);
+        assert_eq!(usb_packets(&[0xC1, 3]), [[0x0C, 0xC1, 3, 0]]);
+        assert_eq!(usb_packets(&[0xF8]), [[0x0F, 0xF8, 0, 0]]);
+        assert_eq!(usb_packets(&[0xF0, 0x7D, 0x04, 0xF7]), [[0x04, 0xF0, 0x7D, 0x04], [0x05, 0xF7, 0, 0]]);
+        // system common messages do not reach the device on the board either
+        assert!(usb_packets(&[0xF2, 0, 0]).is_empty());
+    }
+}
@@ -0,0 +1,107 @@
+//! Line based pedal commands, read from stdin or a script file.
+
+use std::io::BufRead;
+use std::thread;
+use std::time::Duration;
+
+use crate::device::{NUM_CHANNELS, Pedals};
+
+pub const HELP: &str = "\
+commands:
+  set <channel> <value>   move the pedal of a channel (1-4) to a position (0-127)
+  press <channel>         close the switch of a channel
+  release <channel>       open the switch of a channel
+  wait <milliseconds>     pause before the next command
+  quit                    stop the simulator
+lines starting with # are ignored
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Set { channel: usize, value: u8 },
+    Wait(Duration),
+    Quit,
+}
+
+impl Command {
+    /// Parses a line, `None` for empty lines and comments.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next().filter(|word| !word.starts_with('#')) else {
+            return Ok(None);
+        };
+        let mut arg = |name: &str| words.next().ok_or_else(|| format!("missing {name}"));
+
+        let command = match command {
+            "set" => {
+                let channel = channel(arg("channel")?)?;
+                let value = arg("value")?;
+                let value = value.parse().ok().filter(|value| *value <= 127).ok_or_else(|| format!("invalid value {value}"))?;
+                Self::Set { channel, value }
+            },
+            "press" => Self::Set { channel: channel(arg("channel")?)?, value: 127 },
+            "release" => Self::Set { channel: channel(arg("channel")?)?, value: 0 },
+            "wait" => {
+                let millis = arg("milliseconds")?;
+                Self::Wait(Duration::from_millis(millis.parse().map_err(|_| format!("invalid time {millis}"))?))
+            },
+            "quit" => Self::Quit,
+            _ => return Err(format!("unknown command {command}")),
+        };
+
+        match words.next() {
+            Some(extra) => Err(format!("unexpected {extra}")),
+            None => Ok(Some(command)),
+        }
+    }
+}
+
+fn channel(word: &str) -> Result<usize, String> {
+    match word.parse::<usize>() {
+        Ok(channel @ 1..=NUM_CHANNELS) => Ok(channel - 1),
+        _ => Err(format!("invalid channel {word}")),
+    }
+}
+
+/// Runs the commands of `reader` until it ends or asks to quit. Invalid lines are reported and skipped.
+pub fn run(reader: impl BufRead, pedals: &Pedals) {
+    for (number, line) in reader.lines().enumerate() {
+        let Ok(line) = line else {
+            return;
+        };
+
+        match Command::parse(&line) {
+            Ok(Some(Command::Set { channel, value })) => pedals.set(channel, value),
+            Ok(Some(Command::Wait(duration))) => thread::sleep(duration),
+            Ok(Some(Command::Quit)) => return,
+            Ok(None) => {},
+            Err(error) => eprintln!("line {}: {error}", number + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::parse("set 2 64"), Ok(Some(Command::Set { channel: 1, value: 64 })));
+        assert_eq!(Command::parse("  press 4 "), Ok(Some(Command::Set { channel: 3, value: 127 })));
+        assert_eq!(Command::parse("release 1"), Ok(Some(Command::Set { channel: 0, value: 0 })));
+        assert_eq!(Command::parse("wait 250"), Ok(Some(Command::Wait(Duration::from_millis(250)))));
+        assert_eq!(Command::parse("quit"), Ok(Some(Command::Quit)));
+        assert_eq!(Command::parse(""), Ok(None));
+        assert_eq!(Command::parse("# sweep"), Ok(None));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Command::parse("set 0 64"), Err("invalid channel 0".into()));
+        assert_eq!(Command::parse("set 5 64"), Err("invalid channel 5".into()));
+        assert_eq!(Command::parse("set 1 128"), Err("invalid value 128".into()));
+        assert_eq!(Command::parse("set 1"), Err("missing value".into()));
+        assert_eq!(Command::parse("press 1 2"), Err("unexpected 2".into()));
+        assert_eq!(Command::parse("stomp"), Err("unknown command stomp".into()));
+    }
+}
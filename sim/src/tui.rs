@@ -0,0 +1,154 @@
+//! Full screen terminal view with a bar per pedal, moved with the keyboard.
+
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::mpsc;
+
+use expressor_common::led::LedEvent;
+
+use crate::device::{Event, NUM_CHANNELS, Pedals};
+
+/// Number of device events shown below the pedals.
+const HISTORY: usize = 10;
+const BAR_WIDTH: usize = 32;
+
+/// Puts the terminal into non-canonical mode without echo, restoring it when dropped.
+struct RawMode(libc::termios);
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        // SAFETY: termios is plain data and only read after tcgetattr filled it in
+        unsafe {
+            let mut original = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            let mut raw = original;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            // reads return after 100 ms without input, so device events show up while no key is pressed
+            raw.c_cc[libc::VMIN] = 0;
+            raw.c_cc[libc::VTIME] = 1;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self(original))
+        }
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: restores the settings read in enable
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.0) };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Channel(usize),
+    Step(i16),
+    Toggle,
+    Quit,
+}
+
+fn key(bytes: &[u8]) -> Option<Key> {
+    match bytes {
+        [digit @ b'1'..=b'9'] if ((digit - b'1') as usize) < NUM_CHANNELS => Some(Key::Channel((digit - b'1') as usize)),
+        [0x1B, b'[', b'A'] | [b'+'] => Some(Key::Step(1)),
+        [0x1B, b'[', b'B'] | [b'-'] => Some(Key::Step(-1)),
+        [0x1B, b'[', b'C'] => Some(Key::Step(16)),
+        [0x1B, b'[', b'D'] => Some(Key::Step(-16)),
+        [b' '] => Some(Key::Toggle),
+        [b'q'] => Some(Key::Quit),
+        _ => None,
+    }
+}
+
+struct View {
+    values: [u8; NUM_CHANNELS],
+    selected: usize,
+    preset: u8,
+    history: VecDeque<String>,
+}
+
+impl View {
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        // clear the screen and move to the top left corner
+        write!(out, "\x1B[2J\x1B[H")?;
+        writeln!(out, "Midi Expressor simulator - 1-{NUM_CHANNELS} select, up/down +-1, left/right +-16, space toggle, q quit\r\n\r")?;
+
+        for (channel, value) in self.values.iter().enumerate() {
+            let marker = if channel == self.selected { '>' } else { ' ' };
+            let filled = *value as usize * BAR_WIDTH / 127;
+            writeln!(out, "{marker} {} [{}{}] {value:3}\r", channel + 1, "#".repeat(filled), " ".repeat(BAR_WIDTH - filled))?;
+        }
+
+        writeln!(out, "\r\npreset {}\r\n\r", self.preset + 1)?;
+        for line in &self.history {
+            writeln!(out, "{line}\r")?;
+        }
+        out.flush()
+    }
+
+    fn push(&mut self, event: Event) {
+        if let Event::Led(LedEvent::Preset(preset)) = event {
+            self.preset = preset;
+        }
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(event.to_string());
+    }
+}
+
+/// Runs the view until `q` is pressed.
+pub fn run(pedals: &Pedals, events: mpsc::Receiver<Event>) -> io::Result<()> {
+    let _raw = RawMode::enable()?;
+    let mut view = View { values: [0; NUM_CHANNELS], selected: 0, preset: 0, history: VecDeque::new() };
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout().lock();
+    let mut buf = [0u8; 8];
+
+    loop {
+        view.draw(&mut stdout)?;
+
+        let len = stdin.read(&mut buf)?;
+        match key(&buf[..len]) {
+            Some(Key::Channel(channel)) => view.selected = channel,
+            Some(Key::Step(step)) => {
+                let value = &mut view.values[view.selected];
+                *value = (*value as i16 + step).clamp(0, 127) as u8;
+            },
+            Some(Key::Toggle) => {
+                let value = &mut view.values[view.selected];
+                *value = if *value < 64 { 127 } else { 0 };
+            },
+            Some(Key::Quit) => break,
+            None => {},
+        }
+        pedals.set(view.selected, view.values[view.selected]);
+
+        for event in events.try_iter() {
+            view.push(event);
+        }
+    }
+
+    // leave the last screen in place, with the prompt below it
+    writeln!(stdout, "\r")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(key(b"2"), Some(Key::Channel(1)));
+        assert_eq!(key(b"5"), None);
+        assert_eq!(key(b"\x1B[A"), Some(Key::Step(1)));
+        assert_eq!(key(b"\x1B[D"), Some(Key::Step(-16)));
+        assert_eq!(key(b" "), Some(Key::Toggle));
+        assert_eq!(key(b""), None);
+    }
+}
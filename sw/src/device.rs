@@ -0,0 +1,117 @@
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use expressor_common::protocol::{DeviceMessage, HostMessage, Identity, identity_request};
+use iced::Subscription;
+use iced::futures::Stream;
+use iced::futures::channel::mpsc as futures_mpsc;
+use midir::{Ignore, MidiInput, MidiInputPort, MidiOutput, MidiOutputConnection};
+
+const CLIENT_NAME: &str = "Expresso";
+const DEVICE_NAME: &str = "Midi Expressor";
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub enum Event {
+    Connected(Link),
+    Disconnected,
+    Identified(Identity),
+    Message(Box<DeviceMessage>),
+}
+
+/// Sends protocol messages to the connected device. Messages sent while the device is gone are dropped.
+#[derive(Debug, Clone)]
+pub struct Link(mpsc::Sender<HostMessage>);
+
+impl Link {
+    pub fn send(&self, message: HostMessage) {
+        let _ = self.0.send(message);
+    }
+}
+
+/// Watches for the device and forwards its protocol messages.
+pub fn subscription() -> Subscription<Event> {
+    Subscription::run(listen)
+}
+
+fn listen() -> impl Stream<Item = Event> {
+    let (sender, receiver) = futures_mpsc::channel(64);
+    thread::spawn(move || watch(sender));
+    receiver
+}
+
+fn find_port(input: &MidiInput) -> Option<MidiInputPort> {
+    input.ports()
+        .into_iter()
+        .find(|port| input
+            .port_name(port)
+            .is_ok_and(|name| name.contains(DEVICE_NAME)))
+}
+
+fn connect_output() -> Option<MidiOutputConnection> {
+    let output = MidiOutput::new(CLIENT_NAME).ok()?;
+    let port = output.ports()
+        .into_iter()
+        .find(|port| output
+            .port_name(port)
+            .is_ok_and(|name| name.contains(DEVICE_NAME)))?;
+    output.connect(&port, "expresso-out").ok()
+}
+
+fn watch(mut sender: futures_mpsc::Sender<Event>) {
+    while !sender.is_closed() {
+        let Ok(mut input) = MidiInput::new(CLIENT_NAME) else {
+            return;
+        };
+        input.ignore(Ignore::None);
+
+        let connection = find_port(&input).and_then(|port| input
+            .connect(
+                &port,
+                "expresso-in",
+                |_timestamp, bytes, sender: &mut futures_mpsc::Sender<Event>| {
+                    if let Some(message) = DeviceMessage::decode(bytes) {
+                        let _ = sender.try_send(Event::Message(Box::new(message)));
+                    } else if let Some(identity) = Identity::decode(bytes) {
+                        let _ = sender.try_send(Event::Identified(identity));
+                    }
+                },
+                sender.clone(),
+            )
+            .ok());
+
+        let (Some(connection), Some(mut output)) = (connection, connect_output()) else {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        };
+
+        let (link, messages) = mpsc::channel();
+        let _ = sender.try_send(Event::Connected(Link(link)));
+        // tells several devices apart by their serial number
+        let _ = output.send(&identity_request());
+
+        // keep the connection open for as long as the port exists
+        let mut last_poll = Instant::now();
+        while !sender.is_closed() {
+            match messages.recv_timeout(POLL_INTERVAL) {
+                Ok(message) => {
+                    let _ = output.send(&message.encode());
+                },
+                Err(RecvTimeoutError::Timeout) => {},
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if last_poll.elapsed() >= POLL_INTERVAL {
+                last_poll = Instant::now();
+                if !MidiInput::new(CLIENT_NAME).is_ok_and(|input| find_port(&input).is_some()) {
+                    break;
+                }
+            }
+        }
+
+        connection.close();
+        output.close();
+        let _ = sender.try_send(Event::Disconnected);
+    }
+}
@@ -0,0 +1,157 @@
+use expressor_common::hid::{KeyCombo, modifier};
+use iced::keyboard::key::{Code, Physical};
+use iced::keyboard::Modifiers;
+
+/// Physical keys that can be captured, with their HID keyboard usage ID and display name.
+const KEYS: &[(Code, u8, &str)] = &[
+    (Code::KeyA, 0x04, "A"),
+    (Code::KeyB, 0x05, "B"),
+    (Code::KeyC, 0x06, "C"),
+    (Code::KeyD, 0x07, "D"),
+    (Code::KeyE, 0x08, "E"),
+    (Code::KeyF, 0x09, "F"),
+    (Code::KeyG, 0x0A, "G"),
+    (Code::KeyH, 0x0B, "H"),
+    (Code::KeyI, 0x0C, "I"),
+    (Code::KeyJ, 0x0D, "J"),
+    (Code::KeyK, 0x0E, "K"),
+    (Code::KeyL, 0x0F, "L"),
+    (Code::KeyM, 0x10, "M"),
+    (Code::KeyN, 0x11, "N"),
+    (Code::KeyO, 0x12, "O"),
+    (Code::KeyP, 0x13, "P"),
+    (Code::KeyQ, 0x14, "Q"),
+    (Code::KeyR, 0x15, "R"),
+    (Code::KeyS, 0x16, "S"),
+    (Code::KeyT, 0x17, "T"),
+    (Code::KeyU, 0x18, "U"),
+    (Code::KeyV, 0x19, "V"),
+    (Code::KeyW, 0x1A, "W"),
+    (Code::KeyX, 0x1B, "X"),
+    (Code::KeyY, 0x1C, "Y"),
+    (Code::KeyZ, 0x1D, "Z"),
+    (Code::Digit1, 0x1E, "1"),
+    (Code::Digit2, 0x1F, "2"),
+    (Code::Digit3, 0x20, "3"),
+    (Code::Digit4, 0x21, "4"),
+    (Code::Digit5, 0x22, "5"),
+    (Code::Digit6, 0x23, "6"),
+    (Code::Digit7, 0x24, "7"),
+    (Code::Digit8, 0x25, "8"),
+    (Code::Digit9, 0x26, "9"),
+    (Code::Digit0, 0x27, "0"),
+    (Code::Enter, 0x28, "Enter"),
+    (Code::Escape, 0x29, "Escape"),
+    (Code::Backspace, 0x2A, "Backspace"),
+    (Code::Tab, 0x2B, "Tab"),
+    (Code::Space, 0x2C, "Space"),
+    (Code::Minus, 0x2D, "-"),
+    (Code::Equal, 0x2E, "="),
+    (Code::BracketLeft, 0x2F, "["),
+    (Code::BracketRight, 0x30, "]"),
+    (Code::Backslash, 0x31, "\\"),
+    (Code::Semicolon, 0x33, ";"),
+    (Code::Quote, 0x34, "'"),
+    (Code::Backquote, 0x35, "`"),
+    (Code::Comma, 0x36, ","),
+    (Code::Period, 0x37, "."),
+    (Code::Slash, 0x38, "/"),
+    (Code::CapsLock, 0x39, "Caps Lock"),
+    (Code::F1, 0x3A, "F1"),
+    (Code::F2, 0x3B, "F2"),
+    (Code::F3, 0x3C, "F3"),
+    (Code::F4, 0x3D, "F4"),
+    (Code::F5, 0x3E, "F5"),
+    (Code::F6, 0x3F, "F6"),
+    (Code::F7, 0x40, "F7"),
+    (Code::F8, 0x41, "F8"),
+    (Code::F9, 0x42, "F9"),
+    (Code::F10, 0x43, "F10"),
+    (Code::F11, 0x44, "F11"),
+    (Code::F12, 0x45, "F12"),
+    (Code::PrintScreen, 0x46, "Print Screen"),
+    (Code::ScrollLock, 0x47, "Scroll Lock"),
+    (Code::Pause, 0x48, "Pause"),
+    (Code::Insert, 0x49, "Insert"),
+    (Code::Home, 0x4A, "Home"),
+    (Code::PageUp, 0x4B, "Page Up"),
+    (Code::Delete, 0x4C, "Delete"),
+    (Code::End, 0x4D, "End"),
+    (Code::PageDown, 0x4E, "Page Down"),
+    (Code::ArrowRight, 0x4F, "Right"),
+    (Code::ArrowLeft, 0x50, "Left"),
+    (Code::ArrowDown, 0x51, "Down"),
+    (Code::ArrowUp, 0x52, "Up"),
+    (Code::F13, 0x68, "F13"),
+    (Code::F14, 0x69, "F14"),
+    (Code::F15, 0x6A, "F15"),
+    (Code::F16, 0x6B, "F16"),
+    (Code::F17, 0x6C, "F17"),
+    (Code::F18, 0x6D, "F18"),
+    (Code::F19, 0x6E, "F19"),
+    (Code::F20, 0x6F, "F20"),
+    (Code::F21, 0x70, "F21"),
+    (Code::F22, 0x71, "F22"),
+    (Code::F23, 0x72, "F23"),
+    (Code::F24, 0x73, "F24"),
+];
+
+const MODIFIER_KEYS: &[Code] = &[
+    Code::ControlLeft,
+    Code::ControlRight,
+    Code::ShiftLeft,
+    Code::ShiftRight,
+    Code::AltLeft,
+    Code::AltRight,
+    Code::SuperLeft,
+    Code::SuperRight,
+];
+
+const MODIFIER_NAMES: &[(u8, &str)] = &[
+    (modifier::LEFT_CTRL | modifier::RIGHT_CTRL, "Ctrl"),
+    (modifier::LEFT_SHIFT | modifier::RIGHT_SHIFT, "Shift"),
+    (modifier::LEFT_ALT | modifier::RIGHT_ALT, "Alt"),
+    (modifier::LEFT_GUI | modifier::RIGHT_GUI, "Super"),
+];
+
+fn modifier_bits(modifiers: Modifiers) -> u8 {
+    [
+        (modifiers.control(), modifier::LEFT_CTRL),
+        (modifiers.shift(), modifier::LEFT_SHIFT),
+        (modifiers.alt(), modifier::LEFT_ALT),
+        (modifiers.logo(), modifier::LEFT_GUI),
+    ]
+        .into_iter()
+        .filter(|(held, _)| *held)
+        .fold(0, |bits, (_, bit)| bits | bit)
+}
+
+/// Key combination for a key press. Returns `None` for keys without a HID usage and for lone modifier keys, so the
+/// capture waits for the actual key.
+pub fn key_combo(physical_key: &Physical, modifiers: Modifiers) -> Option<KeyCombo> {
+    let Physical::Code(code) = physical_key else {
+        return None;
+    };
+    if MODIFIER_KEYS.contains(code) {
+        return None;
+    }
+
+    let (_, usage, _) = KEYS.iter().find(|(key, _, _)| key == code)?;
+    Some(KeyCombo::new(modifier_bits(modifiers), *usage))
+}
+
+/// Readable name like "Ctrl+Shift+Page Down".
+pub fn key_combo_name(combo: &KeyCombo) -> String {
+    let modifiers = MODIFIER_NAMES
+        .iter()
+        .filter(|(bits, _)| combo.modifiers & bits != 0)
+        .map(|(_, name)| name.to_string());
+    let key = KEYS
+        .iter()
+        .find(|(_, usage, _)| *usage == combo.key)
+        .map(|(_, _, name)| name.to_string())
+        .or_else(|| (combo.key != 0).then(|| format!("0x{:02X}", combo.key)));
+
+    let name = modifiers.chain(key).collect::<Vec<_>>().join("+");
+    if name.is_empty() { "None".to_string() } else { name }
+}
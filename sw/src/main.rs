@@ -1,55 +1,246 @@
-use iced::{Center, Element, Fill};
-use iced::widget::{column, row};
+use expressor_common::config::{ChannelConfig, DeviceConfig, RoutingConfig};
+use expressor_common::detect::Detection;
+use expressor_common::protocol::{DeviceMessage, HostMessage, Identity};
+use expressor_common::update::{Step, Upload};
+use iced::{Center, Element, Fill, Subscription, keyboard};
+use iced::widget::{column, row, scrollable};
 
-use crate::device_config::{ChannelConfig, DeviceConfig};
 use crate::theme::config::{PADDING, SPACING};
-use crate::ui::channel_strip;
+use crate::ui::{channel_strip, firmware_update, routing_config};
 use crate::theme::theme;
 
 mod theme;
 mod ui;
-mod device_config;
+mod device;
+mod keymap;
 
 #[derive(Debug, Clone)]
 enum Message {
-    ChannelConfigChanged(usize, ChannelConfig),
+    ChannelConfigChanged(usize, Box<ChannelConfig>),
+    RoutingConfigChanged(RoutingConfig),
+    PresetSelected(u8),
+    /// Starts or cancels capturing the HID key of a channel.
+    CaptureKey(usize),
+    Keyboard(keyboard::Event),
+    FirmwarePathChanged(String),
+    UpdateFirmware,
+    Device(device::Event),
 }
 
 #[derive(Default, Debug)]
 struct App {
     device_config: DeviceConfig<4>,
+    detections: [Detection; 4],
+    /// Current program of the MIDI channels navigated by switches.
+    programs: [u16; 16],
+    preset: u8,
+    link: Option<device::Link>,
+    identity: Option<Identity>,
+    capturing: Option<usize>,
+    firmware_path: String,
+    upload: Option<Upload<Vec<u8>>>,
+    /// Outcome of the last firmware update.
+    update_result: String,
 }
 
 impl App {
     fn title(&self) -> String {
-        format!("Expresso")
+        match &self.identity {
+            Some(identity) if identity.is_bootloader() => format!("Expresso - bootloader {}", identity.serial_str()),
+            Some(identity) => {
+                let [major, minor, patch, _] = identity.version;
+                format!("Expresso - firmware {major}.{minor}.{patch}, serial {}", identity.serial_str())
+            },
+            None => "Expresso".into(),
+        }
+    }
+
+    fn send(&self, message: HostMessage) {
+        if let Some(link) = &self.link {
+            link.send(message);
+        }
+    }
+
+    fn set_channel_config(&mut self, channel: usize, config: ChannelConfig) {
+        self.device_config.channels[channel] = config;
+        self.send(HostMessage::ChannelConfig { channel: channel as u8, config });
+    }
+
+    fn start_upload(&mut self) {
+        let upload = match std::fs::read(self.firmware_path.trim()) {
+            Ok(file) => Upload::new(file),
+            Err(error) => {
+                self.update_result = error.to_string();
+                return;
+            },
+        };
+        let Some(mut upload) = upload else {
+            self.update_result = "Not a signed firmware image".into();
+            return;
+        };
+
+        // the firmware restarts into the bootloader, which gets the same message again once it is connected
+        self.send(upload.begin());
+        self.update_result.clear();
+        self.upload = Some(upload);
+    }
+
+    fn handle_update_ack(&mut self, step: Step) {
+        match step {
+            Step::Send(message) => self.send(message),
+            Step::Done => {
+                if let Some(upload) = self.upload.take() {
+                    let [major, minor, patch, _] = upload.header().version;
+                    self.update_result = format!("Installed firmware {major}.{minor}.{patch}");
+                }
+            },
+            Step::Failed(status) => {
+                self.upload = None;
+                self.update_result = format!("Update failed: {}", status.message());
+            },
+        }
+    }
+
+    fn update_status(&self) -> String {
+        match &self.upload {
+            Some(upload) => format!("Updating… {:.0}%", upload.progress() * 100.),
+            None => self.update_result.clone(),
+        }
     }
 
     fn update(&mut self, message: Message) {
         match message {
-            Message::ChannelConfigChanged(channel, config) => {
-                self.device_config.channels[channel] = config;
+            Message::ChannelConfigChanged(channel, config) => self.set_channel_config(channel, *config),
+            Message::RoutingConfigChanged(routing) => {
+                self.device_config.routing = routing;
+                self.send(HostMessage::Routing(routing));
+            },
+            Message::PresetSelected(preset) => {
+                self.send(HostMessage::Preset(preset));
+            },
+            Message::CaptureKey(channel) => {
+                self.capturing = if self.capturing == Some(channel) { None } else { Some(channel) };
+            },
+            Message::Keyboard(keyboard::Event::KeyPressed { physical_key, modifiers, repeat: false, .. }) => {
+                let Some(channel) = self.capturing else {
+                    return;
+                };
+                if let Some(key) = keymap::key_combo(&physical_key, modifiers) {
+                    self.capturing = None;
+                    self.set_channel_config(channel, self.device_config.channels[channel].with_hid_key(key));
+                }
+            },
+            Message::Keyboard(_) => {},
+            Message::FirmwarePathChanged(path) => self.firmware_path = path,
+            Message::UpdateFirmware => self.start_upload(),
+            Message::Device(event) => match event {
+                device::Event::Connected(link) => {
+                    match &mut self.upload {
+                        // the device came back in the bootloader, or the transfer was interrupted
+                        Some(upload) => link.send(upload.begin()),
+                        // show what the device is actually running
+                        None => link.send(HostMessage::GetConfig),
+                    }
+                    self.link = Some(link);
+                },
+                device::Event::Identified(identity) => self.identity = Some(identity),
+                device::Event::Disconnected => {
+                    self.link = None;
+                    self.identity = None;
+                    self.detections = Default::default();
+                    self.programs = Default::default();
+                },
+                device::Event::Message(message) => match *message {
+                    DeviceMessage::PedalDetection { channel, detection } => {
+                        if let Some(slot) = self.detections.get_mut(channel as usize) {
+                            *slot = detection;
+                        }
+                    },
+                    DeviceMessage::ChannelConfig { channel, config } => {
+                        if let Some(slot) = self.device_config.channels.get_mut(channel as usize) {
+                            *slot = config;
+                        }
+                    },
+                    DeviceMessage::Routing(routing) => {
+                        self.device_config.routing = routing;
+                    },
+                    DeviceMessage::Preset(preset) => {
+                        // a preset switch on the device replaces the whole config
+                        if preset != self.preset {
+                            self.preset = preset;
+                            self.send(HostMessage::GetConfig);
+                        }
+                    },
+                    DeviceMessage::Program { channel, program } => {
+                        if let Some(slot) = self.programs.get_mut(channel as usize) {
+                            *slot = program;
+                        }
+                    },
+                    DeviceMessage::UpdateAck { offset, status } => {
+                        if let Some(upload) = &mut self.upload {
+                            let step = upload.handle(offset, status);
+                            self.handle_update_ack(step);
+                        }
+                    },
+                },
             },
         }
     }
 
+    fn subscription(&self) -> Subscription<Message> {
+        let keyboard = match self.capturing {
+            Some(_) => keyboard::listen().map(Message::Keyboard),
+            None => Subscription::none(),
+        };
+
+        Subscription::batch([
+            device::subscription().map(Message::Device),
+            keyboard,
+        ])
+    }
+
     fn view(&self) -> Element<'_, Message> {
-        row(self.device_config.channels
+        let channels = row(self.device_config.channels
             .iter()
             .enumerate()
             .map(|(c, channel)| {
                 column![
-                    channel_strip(
+                    scrollable(channel_strip(
                         c,
                         channel,
-                        move |config| Message::ChannelConfigChanged(c, config),
-                    )
+                        &self.detections[c],
+                        self.programs[channel.program.channel as usize],
+                        self.capturing == Some(c),
+                        move |config| Message::ChannelConfigChanged(c, Box::new(config)),
+                        Message::CaptureKey(c),
+                    ))
+                        .height(Fill)
                 ]
                     .align_x(Center)
                     .width(Fill)
                     .height(Fill)
                     .into()
         }))
+            .spacing(SPACING)
+            .width(Fill)
+            .height(Fill);
+
+        column![
+            routing_config(
+                &self.device_config.routing,
+                self.preset,
+                Message::RoutingConfigChanged,
+                Message::PresetSelected,
+            ),
+            firmware_update(
+                &self.firmware_path,
+                self.update_status(),
+                self.upload.is_none(),
+                Message::FirmwarePathChanged,
+                Message::UpdateFirmware,
+            ),
+            channels,
+        ]
             .padding(PADDING)
             .spacing(SPACING)
             .width(Fill)
@@ -62,6 +253,7 @@ fn main() -> iced::Result {
     iced::application(App::default, App::update, App::view)
         .theme(theme())
         .title(App::title)
+        .subscription(App::subscription)
         .centered()
         .run()
 }
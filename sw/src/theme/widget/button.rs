@@ -0,0 +1,21 @@
+use iced::Element;
+use iced::theme::Theme;
+use iced::widget::button::{Button, Style};
+use iced::border::rounded;
+
+use crate::theme::config::RADIUS;
+
+pub fn button<'a, Message, Renderer>(
+    content: impl Into<Element<'a, Message, Theme, Renderer>>,
+) -> Button<'a, Message, Theme, Renderer>
+where
+    Renderer: iced::advanced::Renderer,
+{
+    Button::new(content)
+        .style(|theme: &Theme, _status| Style {
+            background: Some(theme.extended_palette().primary.base.color.into()),
+            text_color: theme.extended_palette().primary.base.text,
+            border: rounded(RADIUS),
+            ..Style::default()
+        })
+}
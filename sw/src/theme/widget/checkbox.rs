@@ -0,0 +1,29 @@
+use iced::theme::Theme;
+use iced::widget::checkbox::{Checkbox, Status, Style};
+use iced::border::rounded;
+
+use crate::theme::config::RADIUS;
+
+pub fn checkbox<'a, Message, Renderer>(
+    label: &'a str,
+    is_checked: bool,
+) -> Checkbox<'a, Message, Theme, Renderer>
+where
+    Renderer: iced::advanced::text::Renderer,
+{
+    Checkbox::new(is_checked)
+        .label(label)
+        .style(|theme: &Theme, status| {
+            let palette = theme.extended_palette();
+            let is_checked = match status {
+                Status::Active { is_checked } | Status::Hovered { is_checked } | Status::Disabled { is_checked } => is_checked,
+            };
+
+            Style {
+                background: if is_checked { palette.primary.base.color } else { palette.background.weak.color }.into(),
+                icon_color: palette.primary.base.text,
+                border: rounded(RADIUS),
+                text_color: None,
+            }
+        })
+}
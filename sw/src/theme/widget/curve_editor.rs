@@ -0,0 +1,159 @@
+use expressor_common::curve::{Breakpoint, Curve};
+use iced::{Length, Point, Rectangle, Renderer, Size, Theme, mouse};
+use iced::widget::canvas::{self, Action, Canvas, Event, Frame, Geometry, Path, Stroke};
+
+use crate::theme::config::RADIUS;
+
+const VALUE_MAX: f32 = 127.;
+const POINT_RADIUS: f32 = 5.;
+const GRAB_RADIUS: f32 = 10.;
+
+/// Canvas based breakpoint editor. Drag points to move them, click on an empty spot to add a point and right click a
+/// point to remove it.
+pub struct CurveEditor<F> {
+    curve: Curve,
+    on_change: F,
+}
+
+#[derive(Default)]
+pub struct CurveEditorState {
+    dragging: Option<usize>,
+}
+
+pub fn curve_editor<Message, F>(curve: Curve, on_change: F) -> Canvas<CurveEditor<F>, Message, Theme, Renderer>
+where
+    F: Fn(Curve) -> Message,
+{
+    Canvas::new(CurveEditor { curve, on_change })
+        .width(Length::Fill)
+        .height(Length::Fixed(120.))
+}
+
+impl<F> CurveEditor<F> {
+    fn to_position(point: &Breakpoint, size: Size) -> Point {
+        Point::new(
+            point.input as f32 / VALUE_MAX * size.width,
+            (1. - point.output as f32 / VALUE_MAX) * size.height,
+        )
+    }
+
+    fn to_breakpoint(position: Point, size: Size) -> Breakpoint {
+        let scale = |value: f32| (value * VALUE_MAX).round().clamp(0., VALUE_MAX) as u8;
+        Breakpoint::new(
+            scale(position.x / size.width),
+            scale(1. - position.y / size.height),
+        )
+    }
+
+    fn point_at(&self, position: Point, size: Size) -> Option<usize> {
+        self.curve.points()
+            .iter()
+            .position(|point| Self::to_position(point, size).distance(position) <= GRAB_RADIUS)
+    }
+}
+
+impl<Message, F> canvas::Program<Message> for CurveEditor<F>
+where
+    F: Fn(Curve) -> Message,
+{
+    type State = CurveEditorState;
+
+    fn update(
+        &self,
+        state: &mut Self::State,
+        event: &Event,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> Option<Action<Message>> {
+        let size = bounds.size();
+
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let position = cursor.position_in(bounds)?;
+
+                if let Some(index) = self.point_at(position, size) {
+                    state.dragging = Some(index);
+                    return Some(Action::capture());
+                }
+
+                let point = Self::to_breakpoint(position, size);
+                let curve = self.curve.with_inserted_point(point);
+                state.dragging = curve.points().iter().position(|p| *p == point);
+                Some(Action::publish((self.on_change)(curve)).and_capture())
+            },
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Right)) => {
+                let index = self.point_at(cursor.position_in(bounds)?, size)?;
+                Some(Action::publish((self.on_change)(self.curve.with_removed_point(index))).and_capture())
+            },
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                let index = state.dragging?;
+                let position = cursor.position_from(bounds.position())?;
+                let point = Self::to_breakpoint(position, size);
+                Some(Action::publish((self.on_change)(self.curve.with_point(index, point))).and_capture())
+            },
+            Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) => {
+                state.dragging.take().map(|_| Action::capture())
+            },
+            _ => None,
+        }
+    }
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &Renderer,
+        theme: &Theme,
+        bounds: Rectangle,
+        _cursor: mouse::Cursor,
+    ) -> Vec<Geometry> {
+        let palette = theme.palette();
+        let size = bounds.size();
+        let mut frame = Frame::new(renderer, size);
+
+        frame.fill(
+            &Path::rounded_rectangle(Point::ORIGIN, size, RADIUS.into()),
+            palette.primary.scale_alpha(0.25),
+        );
+
+        let response = Path::new(|builder| {
+            for input in 0..=127 {
+                let point = Breakpoint::new(input, self.curve.evaluate(input));
+                let position = Self::to_position(&point, size);
+                if input == 0 {
+                    builder.move_to(position);
+                } else {
+                    builder.line_to(position);
+                }
+            }
+        });
+        frame.stroke(&response, Stroke::default().with_color(palette.text).with_width(2.));
+
+        for point in self.curve.points() {
+            frame.fill(&Path::circle(Self::to_position(point, size), POINT_RADIUS), palette.primary);
+        }
+
+        vec![frame.into_geometry()]
+    }
+
+    fn mouse_interaction(
+        &self,
+        state: &Self::State,
+        bounds: Rectangle,
+        cursor: mouse::Cursor,
+    ) -> mouse::Interaction {
+        let hovering = cursor
+            .position_in(bounds)
+            .and_then(|position| self.point_at(position, bounds.size()))
+            .is_some();
+
+        if state.dragging.is_some() {
+            mouse::Interaction::Grabbing
+        } else if hovering {
+            mouse::Interaction::Grab
+        } else if cursor.is_over(bounds) {
+            mouse::Interaction::Crosshair
+        } else {
+            mouse::Interaction::default()
+        }
+    }
+}
@@ -1,9 +1,15 @@
 mod text;
 mod text_input;
 mod pick_list;
+mod button;
+mod checkbox;
 mod knob;
+mod curve_editor;
 
 pub use text::*;
 pub use text_input::*;
 pub use pick_list::*;
+pub use button::*;
+pub use checkbox::*;
 pub use knob::*;
+pub use curve_editor::*;
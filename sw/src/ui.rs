@@ -1,4 +1,8 @@
 use iced::{Center, Element, Fill};
+use expressor_common::config::{ActionKind, ChannelConfig, Destination, Gesture, GestureAction, HidOutput, InputMode, MacroSequence, MacroStep, Mapping, MessageType, NUM_PRESETS, OutputPath, ProgramConfig, ProgramStep, RoutingConfig, Takeover};
+use expressor_common::curve::{Curve, CurvePreset, Interpolation};
+use expressor_common::detect::{Detection, PedalKind};
+use expressor_common::hid::MediaKey;
 use iced::widget::{Column, column, row};
 use num_traits::{Bounded, Num, NumAssignOps};
 use std::fmt::Display;
@@ -6,18 +10,18 @@ use std::ops::{RangeInclusive};
 use std::str::FromStr;
 use strum::VariantArray;
 
-use crate::device_config::{ChannelConfig, InputMode};
+use crate::keymap::key_combo_name;
 use crate::theme::config::SPACING;
-use crate::theme::widget::{pick_list, text, primary_text, text_input};
+use crate::theme::widget::{button, checkbox, curve_editor, pick_list, text, primary_text, text_input};
 
 pub fn labeled_knob<'a, Message: Clone + 'a, T, F>(
     label: &'a str,
-    value: &'a T,
+    value: T,
     range: RangeInclusive<T>,
     on_change: F,
 ) -> Column<'a, Message>
 where
-    T: Num + NumAssignOps + PartialOrd + Ord + Display + FromStr + Clone + Bounded,
+    T: Num + NumAssignOps + PartialOrd + Ord + Display + FromStr + Clone + Bounded + 'a,
     F: Fn(T) -> Message + Copy + 'static,
 {
     column![
@@ -36,10 +40,515 @@ where
         .width(Fill)
 }
 
+fn detection_label(detection: &Detection) -> String {
+    let kind = match detection.kind {
+        PedalKind::Unknown => "Unknown pedal",
+        PedalKind::Disconnected => "No pedal",
+        PedalKind::Expression => "Expression pedal",
+        PedalKind::LatchingSwitch => "Latching switch",
+        PedalKind::MomentarySwitch => "Momentary switch",
+    };
+
+    if detection.reversed {
+        format!("{kind} (reversed)")
+    } else {
+        kind.to_string()
+    }
+}
+
+pub fn pedal_detection<'a, Message: Clone + 'a>(
+    channel: &'a ChannelConfig,
+    detection: &'a Detection,
+    on_change: impl Fn(ChannelConfig) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let channel_clone = *channel;
+    let suggestion = InputMode::suggested(detection)
+        .filter(|mode| *mode != channel.input.mode);
+
+    column![text(detection_label(detection))]
+        .push(suggestion.map(|mode| button(text(format!("Use {mode}")))
+            .on_press_with(move || on_change(channel_clone.with_input_mode(mode)))))
+        .spacing(SPACING / 2.)
+        .align_x(Center)
+        .width(Fill)
+}
+
+pub fn response_curve<'a, Message: Clone + 'a>(
+    curve: &'a Curve,
+    on_change: impl Fn(Curve) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let curve_clone = *curve;
+
+    column![
+        text("Curve")
+            .align_x(Center),
+        curve_editor(*curve, on_change),
+        row![
+            pick_list(
+                CurvePreset::VARIANTS,
+                None::<CurvePreset>,
+                move |preset| on_change(preset.curve()),
+            )
+                .placeholder("Preset")
+                .width(Fill),
+            pick_list(
+                Interpolation::VARIANTS,
+                Some(&curve.interpolation),
+                move |interpolation| on_change(curve_clone.with_interpolation(interpolation)),
+            )
+                .width(Fill),
+        ]
+            .spacing(SPACING / 2.)
+            .width(Fill),
+    ]
+        .spacing(SPACING / 2.)
+        .align_x(Center)
+        .width(Fill)
+}
+
+fn number_label(message_type: MessageType) -> &'static str {
+    match message_type {
+        MessageType::ControlChange => "CC",
+        _ => "Note",
+    }
+}
+
+pub fn mapping_config<'a, Message: Clone + 'a>(
+    index: usize,
+    mapping: &'a Mapping,
+    on_change: impl Fn(Mapping) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let mapping_clone = *mapping;
+    let message_type = mapping.message_type;
+
+    column![
+        row![
+            primary_text(format!("Output {}", index + 1))
+                .width(Fill),
+            button(text("Remove"))
+                .on_press_with(move || on_change(mapping_clone.with_enabled(false))),
+        ]
+            .align_y(Center)
+            .width(Fill),
+        pick_list(
+            MessageType::VARIANTS,
+            Some(&mapping.message_type),
+            move |value| on_change(mapping_clone.with_message_type(value)),
+        )
+            .width(Fill),
+        row![
+            labeled_knob(
+                "Channel",
+                mapping.channel + 1,
+                1..=16,
+                move |value| on_change(mapping_clone.with_channel(value - 1)),
+            ),
+        ]
+            .push(message_type.has_number().then(|| labeled_knob(
+                number_label(message_type),
+                mapping.number,
+                0..=127,
+                move |value| on_change(mapping_clone.with_number(value)),
+            )))
+            .spacing(SPACING)
+            .align_y(Center)
+            .width(Fill),
+        row![
+            labeled_knob(
+                "Minimum\nOutput",
+                mapping.minimum_output,
+                0..=127,
+                move |value| on_change(mapping_clone.with_minimum_output(value)),
+            ),
+            labeled_knob(
+                "Maximum\nOutput",
+                mapping.maximum_output,
+                0..=127,
+                move |value| on_change(mapping_clone.with_maximum_output(value)),
+            ),
+        ]
+            .spacing(SPACING)
+            .align_y(Center)
+            .width(Fill),
+        response_curve(&mapping.curve, move |curve| on_change(mapping_clone.with_curve(curve))),
+    ]
+        .spacing(SPACING)
+        .align_x(Center)
+        .width(Fill)
+}
+
+pub fn mappings_config<'a, Message: Clone + 'a>(
+    channel: &'a ChannelConfig,
+    on_change: impl Fn(ChannelConfig) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let channel_clone = *channel;
+    let free_slot = channel.mappings.iter().position(|mapping| !mapping.enabled);
+
+    Column::with_children(channel.mappings
+        .iter()
+        .enumerate()
+        .filter(|(_, mapping)| mapping.enabled)
+        .map(|(i, mapping)| mapping_config(
+            i,
+            mapping,
+            move |mapping| on_change(channel_clone.with_mapping(i, mapping)),
+        ).into()))
+        .push(free_slot.map(|i| button(text("Add Output"))
+            .on_press_with(move || on_change(channel_clone.with_mapping(i, Mapping::default().with_enabled(true))))))
+        .spacing(SPACING * 2.)
+        .align_x(Center)
+        .width(Fill)
+}
+
+pub fn gesture_config<'a, Message: Clone + 'a>(
+    gesture: Gesture,
+    action: &'a GestureAction,
+    on_change: impl Fn(GestureAction) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let action_clone = *action;
+    let message_type = action.message_type;
+    let message = action.kind == ActionKind::Message;
+    let presets: Vec<Preset> = (0..NUM_PRESETS as u8).map(Preset).collect();
+
+    column![
+        text(gesture.to_string()),
+        pick_list(
+            ActionKind::VARIANTS,
+            Some(&action.kind),
+            move |value| on_change(action_clone.with_kind(value)),
+        )
+            .width(Fill),
+    ]
+        .push(message.then(|| pick_list(
+            MessageType::VARIANTS,
+            Some(&action.message_type),
+            move |value| on_change(action_clone.with_message_type(value)),
+        )
+            .width(Fill)))
+        .push(message.then(|| row![
+            labeled_knob(
+                "Channel",
+                action.channel + 1,
+                1..=16,
+                move |value| on_change(action_clone.with_channel(value - 1)),
+            ),
+        ]
+            .push(message_type.has_number().then(|| labeled_knob(
+                number_label(message_type),
+                action.number,
+                0..=127,
+                move |value| on_change(action_clone.with_number(value)),
+            )))
+            .push(labeled_knob(
+                "Value",
+                action.value,
+                0..=127,
+                move |value| on_change(action_clone.with_value(value)),
+            ))
+            .spacing(SPACING)
+            .align_y(Center)
+            .width(Fill)))
+        .push((action.kind == ActionKind::Preset).then(|| pick_list(
+            presets,
+            Some(Preset(action.value)),
+            move |preset| on_change(action_clone.with_value(preset.0)),
+        )
+            .width(Fill)))
+        .spacing(SPACING / 2.)
+        .align_x(Center)
+        .width(Fill)
+}
+
+/// Timing and actions of the switch gestures.
+pub fn gestures_config<'a, Message: Clone + 'a>(
+    channel: &'a ChannelConfig,
+    on_change: impl Fn(ChannelConfig) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let channel_clone = *channel;
+    let gestures = &channel.gestures;
+
+    column![
+        primary_text("Gestures"),
+        row![
+            labeled_knob(
+                "Double\nTap (ms)",
+                gestures.double_tap_time,
+                0..=2000,
+                move |value| on_change(channel_clone.with_double_tap_time(value)),
+            ),
+            labeled_knob(
+                "Long\nPress (ms)",
+                gestures.long_press_time,
+                0..=5000,
+                move |value| on_change(channel_clone.with_long_press_time(value)),
+            ),
+            labeled_knob(
+                "Repeat\n(ms)",
+                gestures.repeat_interval,
+                1..=2000,
+                move |value| on_change(channel_clone.with_repeat_interval(value)),
+            ),
+        ]
+            .spacing(SPACING)
+            .align_y(Center)
+            .width(Fill),
+    ]
+        .extend(Gesture::VARIANTS.iter().map(|gesture| gesture_config(
+            *gesture,
+            gestures.action(*gesture),
+            move |action| on_change(channel_clone.with_gesture_action(*gesture, action)),
+        ).into()))
+        .spacing(SPACING)
+        .align_x(Center)
+        .width(Fill)
+}
+
+pub fn macro_step_config<'a, Message: Clone + 'a>(
+    index: usize,
+    sequence: &'a MacroSequence,
+    on_change: impl Fn(MacroSequence) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let sequence_clone = *sequence;
+    let step = sequence.steps()[index];
+    let (message_type, channel, number, value) = MessageType::split(&step.message);
+    let on_message = move |message_type: MessageType, channel: u8, number: u8, value: u8| {
+        on_change(sequence_clone.with_step(index, step.with_message(message_type.message(channel, number, value))))
+    };
+
+    column![
+        row![
+            primary_text(format!("Step {}", index + 1))
+                .width(Fill),
+            button(text("Up"))
+                .on_press_maybe((index > 0).then(|| on_change(sequence_clone.with_moved(index, index - 1)))),
+            button(text("Down"))
+                .on_press_maybe((index + 1 < sequence.steps().len()).then(|| on_change(sequence_clone.with_moved(index, index + 1)))),
+            button(text("Remove"))
+                .on_press_with(move || on_change(sequence_clone.with_removed(index))),
+        ]
+            .spacing(SPACING / 2.)
+            .align_y(Center)
+            .width(Fill),
+        pick_list(
+            MessageType::VARIANTS,
+            Some(message_type),
+            move |message_type| on_message(message_type, channel, number, value),
+        )
+            .width(Fill),
+        row![
+            labeled_knob(
+                "Channel",
+                channel + 1,
+                1..=16,
+                move |channel| on_message(message_type, channel - 1, number, value),
+            ),
+        ]
+            .push(message_type.has_number().then(|| labeled_knob(
+                number_label(message_type),
+                number,
+                0..=127,
+                move |number| on_message(message_type, channel, number, value),
+            )))
+            .push(labeled_knob(
+                "Value",
+                value,
+                0..=127,
+                move |value| on_message(message_type, channel, number, value),
+            ))
+            .spacing(SPACING)
+            .align_y(Center)
+            .width(Fill),
+        labeled_knob(
+            "Delay\n(ms)",
+            step.delay,
+            0..=10000,
+            move |delay| on_change(sequence_clone.with_step(index, step.with_delay(delay))),
+        ),
+    ]
+        .spacing(SPACING / 2.)
+        .align_x(Center)
+        .width(Fill)
+}
+
+/// Steps of the macro sent by the gestures with the macro action.
+pub fn macro_config<'a, Message: Clone + 'a>(
+    channel: &'a ChannelConfig,
+    on_change: impl Fn(ChannelConfig) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let channel_clone = *channel;
+    let sequence = &channel.sequence;
+    let on_sequence = move |sequence| on_change(channel_clone.with_sequence(sequence));
+
+    column![primary_text("Macro")]
+        .extend((0..sequence.steps().len()).map(|index| macro_step_config(index, sequence, on_sequence).into()))
+        .push((!sequence.is_full()).then(|| button(text("Add Step"))
+            .on_press_with(move || on_sequence(channel_clone.sequence.with_added(MacroStep::default())))))
+        .spacing(SPACING)
+        .align_x(Center)
+        .width(Fill)
+}
+
+/// Ramp a switch in timed ramp mode moves its value along.
+pub fn ramp_config<'a, Message: Clone + 'a>(
+    channel: &'a ChannelConfig,
+    on_change: impl Fn(ChannelConfig) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let channel_clone = *channel;
+    let ramp = &channel.ramp;
+
+    column![
+        primary_text("Ramp"),
+        row![
+            labeled_knob(
+                "Start\nValue",
+                ramp.start,
+                0..=127,
+                move |value| on_change(channel_clone.with_ramp_start(value)),
+            ),
+            labeled_knob(
+                "End\nValue",
+                ramp.end,
+                0..=127,
+                move |value| on_change(channel_clone.with_ramp_end(value)),
+            ),
+            labeled_knob(
+                "Duration\n(ms)",
+                ramp.duration,
+                0..=60000,
+                move |value| on_change(channel_clone.with_ramp_duration(value)),
+            ),
+        ]
+            .spacing(SPACING)
+            .align_y(Center)
+            .width(Fill),
+        response_curve(
+            &ramp.curve,
+            move |curve| on_change(channel_clone.with_ramp_curve(curve)),
+        ),
+        checkbox("Reverse on release", ramp.reverse)
+            .on_toggle(move |value| on_change(channel_clone.with_ramp_reverse(value))),
+    ]
+        .spacing(SPACING)
+        .align_x(Center)
+        .width(Fill)
+}
+
+/// Program navigation of a switch, with the current program of its MIDI channel on the device.
+pub fn program_config<'a, Message: Clone + 'a>(
+    channel: &'a ChannelConfig,
+    current: u16,
+    on_change: impl Fn(ChannelConfig) -> Message + Copy + 'static,
+) -> Column<'a, Message> {
+    let channel_clone = *channel;
+    let program = &channel.program;
+
+    column![
+        primary_text("Program"),
+        text(format!(
+            "Bank {}, Program {}",
+            current / ProgramConfig::BANK_SIZE,
+            current % ProgramConfig::BANK_SIZE,
+        )),
+        pick_list(
+            ProgramStep::VARIANTS,
+            Some(&program.step),
+            move |value| on_change(channel_clone.with_program_step(value)),
+        )
+            .width(Fill),
+        labeled_knob(
+            "Channel",
+            program.channel + 1,
+            1..=16,
+            move |value| on_change(channel_clone.with_program_channel(value - 1)),
+        ),
+    ]
+        .push((program.step == ProgramStep::Direct).then(|| labeled_knob(
+            "Program",
+            program.program,
+            0..=u16::MAX,
+            move |value| on_change(channel_clone.with_program(value)),
+        )))
+        .push((program.step != ProgramStep::Direct).then(|| row![
+            labeled_knob(
+                "First\nProgram",
+                program.minimum,
+                0..=u16::MAX,
+                move |value| on_change(channel_clone.with_program_range(value, channel_clone.program.maximum)),
+            ),
+            labeled_knob(
+                "Last\nProgram",
+                program.maximum,
+                0..=u16::MAX,
+                move |value| on_change(channel_clone.with_program_range(channel_clone.program.minimum, value)),
+            ),
+        ]
+            .spacing(SPACING)
+            .align_y(Center)
+            .width(Fill)))
+        .push((program.step != ProgramStep::Direct).then(|| checkbox("Wrap around", program.wrap)
+            .on_toggle(move |value| on_change(channel_clone.with_program_wrap(value)))))
+        .spacing(SPACING)
+        .align_x(Center)
+        .width(Fill)
+}
+
+pub fn hid_config<'a, Message: Clone + 'a>(
+    channel: &'a ChannelConfig,
+    capturing: bool,
+    on_change: impl Fn(ChannelConfig) -> Message + Copy + 'static,
+    on_capture: Message,
+) -> Column<'a, Message> {
+    let channel_clone = *channel;
+    let key_label = if capturing {
+        "Press a key...".to_string()
+    } else {
+        key_combo_name(&channel.hid.key)
+    };
+
+    let hid = channel.destination.hid();
+
+    column![
+        pick_list(
+            Destination::VARIANTS,
+            Some(&channel.destination),
+            move |value| on_change(channel_clone.with_destination(value)),
+        )
+            .width(Fill),
+    ]
+        .push(hid.then(|| pick_list(
+            HidOutput::VARIANTS,
+            Some(&channel.hid.output),
+            move |value| on_change(channel_clone.with_hid_output(value)),
+        )
+            .width(Fill)))
+        .push((hid && channel.hid.output == HidOutput::Key).then(|| button(text(key_label).align_x(Center).width(Fill))
+            .on_press(on_capture)
+            .width(Fill)))
+        .push((hid && channel.hid.output == HidOutput::Media).then(|| pick_list(
+            MediaKey::VARIANTS,
+            Some(&channel.hid.media),
+            move |value| on_change(channel_clone.with_media_key(value)),
+        )
+            .width(Fill)))
+        .push((hid && channel.hid.output == HidOutput::Volume).then(|| labeled_knob(
+            "Volume\nSteps",
+            channel.hid.volume_steps,
+            1..=127,
+            move |value| on_change(channel_clone.with_volume_steps(value)),
+        )))
+        .spacing(SPACING / 2.)
+        .align_x(Center)
+        .width(Fill)
+}
+
 pub fn channel_strip<'a, Message: Clone + 'a>(
     channel_index: usize,
     channel: &'a ChannelConfig,
+    detection: &'a Detection,
+    program: u16,
+    capturing: bool,
     on_change: impl Fn(ChannelConfig) -> Message + Copy + 'static,
+    on_capture: Message,
 ) -> Element<'a, Message>
 {
     let channel_clone = channel.clone();
@@ -53,6 +562,7 @@ pub fn channel_strip<'a, Message: Clone + 'a>(
             move |value| on_change(channel_clone.with_input_mode(value)),
         )
             .width(Fill),
+        pedal_detection(channel, detection, on_change),
         // row![
         //     button("C")
         //         .on_press_with(move || on_change(channel_clone.with_input_mode(InputMode::Continuous)))
@@ -72,13 +582,13 @@ pub fn channel_strip<'a, Message: Clone + 'a>(
                 row![
                     labeled_knob(
                         "Minimum\nInput",
-                        &channel.input.continuous.minimum_input,
+                        channel.input.continuous.minimum_input,
                         0..=127,
                         move |value| on_change(channel_clone.with_minimum_input(value)),
                     ),
                     labeled_knob(
                         "Maximum\nInput",
-                        &channel.input.continuous.maximum_input,
+                        channel.input.continuous.maximum_input,
                         0..=127,
                         move |value| on_change(channel_clone.with_maximum_input(value)),
                     ),
@@ -89,13 +599,13 @@ pub fn channel_strip<'a, Message: Clone + 'a>(
                 row![
                     labeled_knob(
                         "Minimum\nOutput",
-                        &channel.input.continuous.minimum_output,
+                        channel.input.continuous.minimum_output,
                         0..=127,
                         move |value| on_change(channel_clone.with_minimum_output(value)),
                     ),
                     labeled_knob(
                         "Maximum\nOutput",
-                        &channel.input.continuous.maximum_output,
+                        channel.input.continuous.maximum_output,
                         0..=127,
                         move |value| on_change(channel_clone.with_maximum_output(value)),
                     ),
@@ -103,43 +613,60 @@ pub fn channel_strip<'a, Message: Clone + 'a>(
                     .spacing(SPACING)
                     .align_y(Center)
                     .width(Fill),
-                labeled_knob(
-                    "Drive",
-                    &channel.input.continuous.drive,
-                    0..=127,
-                    move |value| on_change(channel_clone.with_drive(value)),
+                response_curve(
+                    &channel.input.continuous.curve,
+                    move |curve| on_change(channel_clone.with_curve(curve)),
                 ),
             ],
+            InputMode::ProgramNavigation => program_config(channel, program, on_change),
+            InputMode::Ramp => ramp_config(channel, on_change),
             _ => column![
                 row![
                     labeled_knob(
                         "Released\nValue",
-                        &channel.input.switch.released_value,
+                        channel.input.switch.released_value,
                         0..=127,
                         move |value| on_change(channel_clone.with_released_value(value)),
                     ),
                     labeled_knob(
                         "Pressed\nValue",
-                        &channel.input.switch.pressed_value,
+                        channel.input.switch.pressed_value,
                         0..=127,
                         move |value| on_change(channel_clone.with_pressed_value(value)),
                     ),
                 ]
                     .spacing(SPACING)
                     .align_y(Center)
-                    .width(Fill)
+                    .width(Fill),
+                gestures_config(channel, on_change),
+                macro_config(channel, on_change),
             ],
         }
             .spacing(SPACING)
             .align_x(Center)
-            .width(Fill)
-            .height(Fill),
+            .width(Fill),
+        mappings_config(channel, on_change),
+        pick_list(
+            OutputPath::VARIANTS,
+            Some(&channel.output_path),
+            move |value| on_change(channel_clone.with_output_path(value)),
+        )
+            .width(Fill),
+        hid_config(channel, capturing, on_change, on_capture),
         labeled_knob(
-            "CC",
-            &channel.cc,
-            0..=127,
-            move |value| on_change(channel_clone.with_cc(value)),
+            "Max Rate\n(1/s)",
+            channel.max_rate,
+            0..=1000,
+            move |value| on_change(channel_clone.with_max_rate(value)),
         ),
+        pick_list(
+            Takeover::VARIANTS,
+            Some(&channel.takeover),
+            move |value| on_change(channel_clone.with_takeover(value)),
+        )
+            .width(Fill),
+        checkbox("Resend state", channel.resend)
+            .on_toggle(move |value| on_change(channel_clone.with_resend(value))),
         text_input("Label", channel.label_str())
             .on_input(move |label_str| on_change(channel_clone.with_label_str(&label_str)))
             .width(Fill),
@@ -147,6 +674,107 @@ pub fn channel_strip<'a, Message: Clone + 'a>(
         .spacing(SPACING)
         .align_x(Center)
         .width(200)
-        .height(Fill)
+        .into()
+}
+
+/// Option of the preset channel pick list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PresetChannel(Option<u8>);
+
+impl Display for PresetChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(channel) => write!(f, "Program Change on {}", channel + 1),
+            None => write!(f, "Program Change off"),
+        }
+    }
+}
+
+/// Option of the refresh interval pick list, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct RefreshInterval(u8);
+
+impl RefreshInterval {
+    const OPTIONS: [Self; 7] = [Self(0), Self(1), Self(2), Self(5), Self(10), Self(30), Self(60)];
+}
+
+impl Display for RefreshInterval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            0 => write!(f, "Refresh off"),
+            seconds => write!(f, "Refresh every {seconds} s"),
+        }
+    }
+}
+
+/// Option of the preset pick list.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Preset(u8);
+
+impl Display for Preset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Preset {}", self.0 + 1)
+    }
+}
+
+pub fn routing_config<'a, Message: Clone + 'a>(
+    routing: &'a RoutingConfig,
+    preset: u8,
+    on_change: impl Fn(RoutingConfig) -> Message + Copy + 'static,
+    on_preset: impl Fn(u8) -> Message + 'static,
+) -> Element<'a, Message> {
+    let routing_clone = *routing;
+    let presets: Vec<Preset> = (0..NUM_PRESETS as u8).map(Preset).collect();
+    let preset_channels: Vec<PresetChannel> = std::iter::once(None)
+        .chain((0..16).map(Some))
+        .map(PresetChannel)
+        .collect();
+
+    row![
+        pick_list(presets, Some(Preset(preset)), move |preset| on_preset(preset.0)),
+        pick_list(
+            preset_channels,
+            Some(PresetChannel(routing.preset_channel)),
+            move |channel| on_change(routing_clone.with_preset_channel(channel.0)),
+        ),
+        pick_list(
+            RefreshInterval::OPTIONS,
+            Some(RefreshInterval(routing.refresh_interval)),
+            move |interval| on_change(routing_clone.with_refresh_interval(interval.0)),
+        ),
+        primary_text("MIDI Thru"),
+        checkbox("DIN to USB", routing.din_to_usb)
+            .on_toggle(move |value| on_change(routing_clone.with_din_to_usb(value))),
+        checkbox("DIN soft thru", routing.din_thru)
+            .on_toggle(move |value| on_change(routing_clone.with_din_thru(value))),
+        checkbox("USB to DIN", routing.usb_to_din)
+            .on_toggle(move |value| on_change(routing_clone.with_usb_to_din(value))),
+        // the cables are named after the channel labels when the device is plugged in
+        checkbox("USB cable per channel", routing.usb_cables)
+            .on_toggle(move |value| on_change(routing_clone.with_usb_cables(value))),
+    ]
+        .spacing(SPACING * 2.)
+        .align_y(Center)
+        .into()
+}
+
+/// Path of a signed firmware image, the button to install it and the state of the update.
+pub fn firmware_update<'a, Message: Clone + 'a>(
+    path: &str,
+    status: String,
+    idle: bool,
+    on_path: impl Fn(String) -> Message + 'a,
+    on_update: Message,
+) -> Element<'a, Message> {
+    row![
+        text_input("Firmware image", path)
+            .on_input(on_path)
+            .width(320),
+        button(text("Update Firmware"))
+            .on_press_maybe(idle.then_some(on_update)),
+        text(status),
+    ]
+        .spacing(SPACING)
+        .align_y(Center)
         .into()
 }
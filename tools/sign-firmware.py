@@ -0,0 +1,134 @@
+#!/usr/bin/env python3
+"""Signs a firmware image for updates over SysEx.
+
+The output is the image header followed by the image, as expected by the desktop app:
+
+    magic "EXPR", version (4 bytes), size (u32 LE), CRC-32 (u32 LE), Ed25519 signature (64 bytes)
+
+The signature covers the first 16 header bytes followed by the image.
+
+The bootloader takes the public key from the EXPRESSOR_PUBLIC_KEY environment variable when it is built. Keys stay
+out of the repository; `keys/` is ignored by git.
+
+Usage:
+    tools/sign-firmware.py keygen keys/release.key
+    tools/sign-firmware.py public keys/release.key
+    arm-none-eabi-objcopy -O binary midibox-fw midibox-fw.bin
+    tools/sign-firmware.py sign keys/release.key midibox-fw.bin 0.4.0 midibox-fw-0.4.0.expr
+
+The bootloader only starts an image with a header, which the SysEx update writes. Firmware flashed with a probe needs
+the header page as well, `probe` flashes both and shows the defmt log. The bootloader only checks the CRC on start,
+so the signature may be left out for development:
+
+    tools/sign-firmware.py probe target/thumbv7em-none-eabi/debug/midibox-fw [--key keys/release.key]
+"""
+
+import argparse
+import os
+import struct
+import subprocess
+import sys
+import tempfile
+import zlib
+
+from cryptography.hazmat.primitives.asymmetric.ed25519 import Ed25519PrivateKey
+from cryptography.hazmat.primitives.serialization import Encoding, PublicFormat
+
+MAGIC = b"EXPR"
+# flash layout, see `expressor_common::update::layout`
+FLASH_BASE = 0x0800_0000
+PAGE_SIZE = 2048
+HEADER_OFFSET = 40 * 1024
+IMAGE_CAPACITY = 128 * 1024 - HEADER_OFFSET - PAGE_SIZE
+CHIP = "STM32G431CB"
+
+
+def load_key(path):
+    with open(path) as file:
+        return Ed25519PrivateKey.from_private_bytes(bytes.fromhex(file.read().strip()))
+
+
+def print_public_key(key):
+    print(f"EXPRESSOR_PUBLIC_KEY={key.public_key().public_bytes(Encoding.Raw, PublicFormat.Raw).hex()}")
+
+
+def keygen(args):
+    key = Ed25519PrivateKey.generate()
+    with open(args.key, "x") as file:
+        file.write(key.private_bytes_raw().hex() + "\n")
+    print_public_key(key)
+
+
+def public(args):
+    print_public_key(load_key(args.key))
+
+
+def header(image, version, key):
+    """Image header, with an empty signature if there is no key."""
+    if len(image) > IMAGE_CAPACITY:
+        sys.exit(f"image has {len(image)} bytes, the device takes at most {IMAGE_CAPACITY}")
+
+    version = [int(part) for part in version.split(".")]
+    version = bytes((version + [0] * 4)[:4])
+    fields = MAGIC + version + struct.pack("<II", len(image), zlib.crc32(image))
+    signature = load_key(key).sign(fields + image) if key else bytes(64)
+    return fields + signature
+
+
+def sign(args):
+    with open(args.image, "rb") as file:
+        image = file.read()
+
+    with open(args.output, "wb") as file:
+        file.write(header(image, args.version, args.key) + image)
+
+
+def probe(args):
+    with tempfile.TemporaryDirectory() as directory:
+        path = os.path.join(directory, "midibox-fw.bin")
+        subprocess.run(["rust-objcopy", "-O", "binary", args.elf, path], check=True)
+        with open(path, "rb") as file:
+            image = file.read()
+
+        # the header page followed by the image, as the bootloader leaves them after an update
+        with open(path, "wb") as file:
+            file.write(header(image, args.version, args.key).ljust(PAGE_SIZE, b"\xff") + image)
+
+        address = hex(FLASH_BASE + HEADER_OFFSET)
+        subprocess.run(["probe-rs", "download", "--chip", CHIP, "--binary-format", "bin", "--base-address", address, path], check=True)
+
+    subprocess.run(["probe-rs", "reset", "--chip", CHIP], check=True)
+    subprocess.run(["probe-rs", "attach", "--chip", CHIP, args.elf])
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__, formatter_class=argparse.RawDescriptionHelpFormatter)
+    commands = parser.add_subparsers(required=True)
+
+    command = commands.add_parser("keygen", help="create a signing key and print its public key")
+    command.add_argument("key")
+    command.set_defaults(run=keygen)
+
+    command = commands.add_parser("public", help="print the public key for building the bootloader")
+    command.add_argument("key")
+    command.set_defaults(run=public)
+
+    command = commands.add_parser("sign", help="sign a raw firmware image")
+    command.add_argument("key")
+    command.add_argument("image")
+    command.add_argument("version", help="firmware version, e.g. 0.4.0")
+    command.add_argument("output")
+    command.set_defaults(run=sign)
+
+    command = commands.add_parser("probe", help="flash a firmware build and its header page with a probe")
+    command.add_argument("elf")
+    command.add_argument("--key", help="signing key, the bootloader does not check the signature on start")
+    command.add_argument("--version", default="0.0.0")
+    command.set_defaults(run=probe)
+
+    args = parser.parse_args()
+    args.run(args)
+
+
+if __name__ == "__main__":
+    main()